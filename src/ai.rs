@@ -1,24 +1,17 @@
+pub mod provider;
+
+use crate::cache::{AiCache, AiCacheKey};
+use crate::events::{Event, Observer};
+use crate::pool::Semaphore;
+use crate::utils::hash_file_contents;
+use crate::xmp::XmpUpdate;
 use base64::{engine::general_purpose, Engine as _};
-use colored::*;
 use image::ImageFormat;
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use provider::{AiProvider, AiProviderError, AiRequest};
 use std::io::Cursor;
+use std::sync::Mutex;
 use std::{path::Path, time::Duration};
 
-#[derive(Serialize)]
-struct OllamaRequest {
-    model: String,
-    prompt: String,
-    images: Vec<String>,
-    stream: bool,
-}
-
-#[derive(Deserialize)]
-struct OllamaResponse {
-    response: String,
-}
-
 fn apply_case_conversion(input: &str, case_style: &str) -> String {
     match case_style.to_lowercase().as_str() {
         "snakecase" | "snake_case" => to_snake_case(input),
@@ -162,33 +155,22 @@ fn resize_image_for_ai(image_path: &Path) -> Result<Vec<u8>, Box<dyn std::error:
 }
 
 /// Helper function to prepare image for AI processing
-fn prepare_image_for_ai(image_path: &Path) -> Option<String> {
-    println!(
-        "{}  {}",
-        "🖼️".bright_blue(),
-        "Resizing image for AI processing...".bright_blue()
-    );
-
+fn prepare_image_for_ai(image_path: &Path, observer: &dyn Observer) -> Option<String> {
     resize_image_for_ai(image_path)
         .map(|buffer| general_purpose::STANDARD.encode(&buffer))
         .map_err(|e| {
             let error_msg = e.to_string();
-            if error_msg.contains("invalid JPEG format") || error_msg.contains("Format error") {
-                eprintln!(
-                    "{} {}{}{}",
-                    "⚠️".bright_yellow(),
-                    "Skipping invalid/corrupted image file: ".bright_yellow(),
-                    image_path.display().to_string().bright_white(),
-                    " (not a valid image format)".bright_yellow()
-                );
+            let message = if error_msg.contains("invalid JPEG format")
+                || error_msg.contains("Format error")
+            {
+                "not a valid image, skipping AI content analysis".to_string()
             } else {
-                eprintln!(
-                    "{} {}{}",
-                    "❌".bright_red(),
-                    "Failed to resize image: ".bright_red(),
-                    error_msg.bright_white()
-                );
-            }
+                format!("failed to resize image for AI: {}", error_msg)
+            };
+            observer.on_event(&Event::Warning {
+                path: Some(image_path),
+                message: &message,
+            });
         })
         .ok()
 }
@@ -204,66 +186,15 @@ fn build_ai_prompt(case: &str, max_chars: u32, language: &str) -> String {
     )
 }
 
-/// Helper function to create HTTP client
-fn create_ai_client() -> Client {
-    Client::builder()
-        .timeout(Duration::from_secs(30))
-        .build()
-        .unwrap()
-}
-
-/// Helper function to process successful AI response
-fn process_ai_response(
-    response: reqwest::blocking::Response,
-    case: &str,
-    max_chars: u32,
-) -> Option<String> {
-    if !response.status().is_success() {
-        eprintln!(
-            "{} {}{}{}  {}{}",
-            "❌".bright_red(),
-            "Ollama API error status: ".bright_red(),
-            response.status().to_string().bright_white().bold(),
-            " - ".bright_red(),
-            "Details: ".bright_red(),
-            response.text().unwrap_or_default().bright_white()
-        );
-        return None;
-    }
-
-    let ollama_response: OllamaResponse = response
-        .json()
-        .map_err(|e| {
-            eprintln!(
-                "{} {}{}",
-                "❌".bright_red(),
-                "Failed to parse Ollama response: ".bright_red(),
-                e.to_string().bright_white()
-            );
-        })
-        .ok()?;
-
-    let filename = ollama_response.response.trim();
+/// Helper function to turn the raw model reply into a filename fragment
+fn process_ai_response(raw: &str, case: &str, max_chars: u32) -> Result<String, String> {
+    let filename = raw.trim();
     if filename.is_empty() {
-        eprintln!(
-            "{} {}",
-            "❌".bright_red(),
-            "Ollama returned empty response".bright_red()
-        );
-        return None;
+        return Err("AI model returned empty response".to_string());
     }
 
     let filename = apply_case_conversion(filename, case);
-    println!(
-        "{}  {}{}{}{}",
-        "✨".bright_yellow(),
-        "AI generated filename: ".bright_yellow(),
-        "'".bright_white(),
-        filename.bright_green().bold(),
-        "'".bright_white()
-    );
-
-    Some(if filename.len() > max_chars as usize {
+    Ok(if filename.len() > max_chars as usize {
         filename.chars().take(max_chars as usize).collect()
     } else {
         filename
@@ -271,80 +202,187 @@ fn process_ai_response(
 }
 
 /// Helper function to attempt AI request with retry logic
-fn attempt_ai_request(
-    client: &Client,
-    request: &OllamaRequest,
-    case: &str,
-    max_chars: u32,
-) -> Option<String> {
-    println!(
-        "{}  {}{}{}",
-        "🤖".bright_magenta(),
-        "Analyzing image content with AI model: ".bright_magenta(),
-        request.model.bright_white().bold(),
-        "...".bright_magenta()
-    );
-
-    for attempt in 1..=2 {
-        match client
-            .post("http://localhost:11434/api/generate")
-            .json(request)
-            .send()
-        {
-            Ok(response) => {
-                if attempt > 1 {
-                    println!(
-                        "{}  {}{}",
-                        "✅".bright_green(),
-                        "Retry successful on attempt ".bright_green(),
-                        attempt.to_string().bright_white()
-                    );
-                }
-                return process_ai_response(response, case, max_chars);
-            }
-            Err(e) => {
-                if attempt == 1 {
-                    println!(
-                        "{} {}  {}",
-                        "⚠️".bright_yellow(),
-                        "First attempt failed, retrying...".bright_yellow(),
-                        "(model might be loading)".bright_black()
-                    );
-                    std::thread::sleep(Duration::from_millis(2000));
-                } else {
-                    eprintln!(
-                        "{} {}{}",
-                        "❌".bright_red(),
-                        "Failed to send request to Ollama after 2 attempts: ".bright_red(),
-                        e.to_string().bright_white()
-                    );
-                    return None;
-                }
-            }
+fn attempt_ai_request(backend: &AiBackend<'_>, request: &AiRequest<'_>) -> Result<String, String> {
+    let model = backend.provider.describe();
+    backend.observer.on_event(&Event::AiRequest {
+        path: request.image_path,
+        model: &model,
+    });
+
+    match backend.provider.generate(request) {
+        Ok(response) => Ok(response),
+        Err(AiProviderError::Response(message)) => Err(message),
+        Err(AiProviderError::Connection(_)) => {
+            // The model might still be loading.
+            backend.observer.on_event(&Event::AiRetry {
+                path: request.image_path,
+            });
+            std::thread::sleep(Duration::from_millis(2000));
+            backend
+                .provider
+                .generate(request)
+                .map_err(|error| match error {
+                    AiProviderError::Connection(message) => format!(
+                        "Failed to reach the AI backend after 2 attempts: {}",
+                        message
+                    ),
+                    AiProviderError::Response(message) => message,
+                })
         }
     }
-    None
+}
+
+/// The AI backend of a run with the cache and request limit shared by its workers.
+pub struct AiBackend<'a> {
+    pub provider: &'a dyn AiProvider,
+    pub cache: &'a Mutex<AiCache>,
+    pub limit: &'a Semaphore,
+    pub observer: &'a dyn Observer,
 }
 
 pub fn get_ai_content_name(
     image_path: &Path,
-    model: &str,
+    backend: &AiBackend<'_>,
     max_chars: u32,
     case: &str,
     language: &str,
 ) -> Option<String> {
-    let base64_image = prepare_image_for_ai(image_path)?;
-    let client = create_ai_client();
     let prompt = build_ai_prompt(case, max_chars, language);
+    let settings = AiSettings {
+        prompt: &prompt,
+        case,
+        language,
+        max_chars,
+    };
+    cached_ai_request(image_path, backend, &settings, |raw| {
+        process_ai_response(raw, case, max_chars)
+    })
+}
+
+/// A one-sentence description and keywords for an image, for its XMP sidecar.
+pub fn get_ai_description(
+    image_path: &Path,
+    backend: &AiBackend<'_>,
+    language: &str,
+) -> Option<XmpUpdate> {
+    let prompt = build_description_prompt(language);
+    let settings = AiSettings {
+        prompt: &prompt,
+        case: "",
+        language,
+        max_chars: 0,
+    };
+    let reply = cached_ai_request(image_path, backend, &settings, |raw| {
+        parse_description_reply(raw).map(|_| raw.trim().to_string())
+    })?;
+    parse_description_reply(&reply).ok()
+}
+
+/// The request settings that, with the image contents, make up a cache key.
+struct AiSettings<'a> {
+    prompt: &'a str,
+    case: &'a str,
+    language: &'a str,
+    max_chars: u32,
+}
+
+/// Looks a request up in the AI cache and otherwise sends it, caching what
+/// `process` makes of the reply. Failures are reported as warnings.
+fn cached_ai_request(
+    image_path: &Path,
+    backend: &AiBackend<'_>,
+    settings: &AiSettings<'_>,
+    process: impl FnOnce(&str) -> Result<String, String>,
+) -> Option<String> {
+    let warn = |message: &str| {
+        backend.observer.on_event(&Event::Warning {
+            path: Some(image_path),
+            message,
+        })
+    };
+    let content_hash = hash_file_contents(image_path)
+        .map_err(|error| warn(&format!("could not hash image for the AI cache: {}", error)))
+        .ok();
+    let model = backend.provider.cache_identity();
+    let cache_key = content_hash.as_deref().map(|content_hash| AiCacheKey {
+        content_hash,
+        model: &model,
+        prompt: settings.prompt,
+        case: settings.case,
+        language: settings.language,
+        max_chars: settings.max_chars,
+    });
+
+    let cached = cache_key
+        .as_ref()
+        .and_then(|key| backend.cache.lock().unwrap().get(key).cloned());
+    if let Some(cached) = cached {
+        return Some(cached);
+    }
+
+    let base64_image = if backend.provider.needs_image_data() {
+        Some(prepare_image_for_ai(image_path, backend.observer)?)
+    } else {
+        None
+    };
+
+    let request = AiRequest {
+        prompt: settings.prompt,
+        image_path,
+        image_base64: base64_image.as_deref(),
+    };
+
+    let response = {
+        let _permit = backend.limit.acquire();
+        attempt_ai_request(backend, &request)
+    };
+    let result = response
+        .and_then(|response| process(&response))
+        .map_err(|error| warn(&error))
+        .ok()?;
+    if let Some(key) = &cache_key {
+        backend.cache.lock().unwrap().insert(key, result.clone());
+    }
+    Some(result)
+}
+
+fn build_description_prompt(language: &str) -> String {
+    format!(
+        "Describe this image for a photo library.\n\nRespond in {} with exactly two lines:\nDescription: <one sentence about the main subject and setting>\nKeywords: <up to 8 comma-separated keywords>",
+        language
+    )
+}
+
+/// Reads the `Description:` and `Keywords:` lines of a description reply.
+fn parse_description_reply(raw: &str) -> Result<XmpUpdate, String> {
+    let field = |name: &str| {
+        raw.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim()
+                .trim_matches('*')
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim_start_matches('*').trim().to_string())
+        })
+    };
 
-    let request = OllamaRequest {
-        model: model.to_string(),
-        prompt,
-        images: vec![base64_image],
-        stream: false,
+    let description = field("description").filter(|description| !description.is_empty());
+    let Some(description) = description else {
+        return Err("AI model did not return a description".to_string());
     };
+    let keywords = field("keywords")
+        .map(|keywords| {
+            keywords
+                .split(',')
+                .map(|keyword| keyword.trim().trim_end_matches('.').to_string())
+                .filter(|keyword| !keyword.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
 
-    attempt_ai_request(&client, &request, case, max_chars)
+    Ok(XmpUpdate {
+        description,
+        keywords,
+    })
 }
 
 #[cfg(test)]
@@ -403,4 +441,19 @@ mod tests {
             "cat_on_carpet"
         );
     }
+
+    #[test]
+    fn parses_description_replies() {
+        let reply =
+            "**Description:** A dog runs along a beach at sunset.\nKeywords: dog, beach, sunset.";
+
+        assert_eq!(
+            parse_description_reply(reply),
+            Ok(XmpUpdate {
+                description: "A dog runs along a beach at sunset.".to_string(),
+                keywords: vec!["dog".into(), "beach".into(), "sunset".into()],
+            })
+        );
+        assert!(parse_description_reply("just a filename").is_err());
+    }
 }
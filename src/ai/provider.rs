@@ -0,0 +1,445 @@
+use reqwest::blocking::{Client, Response};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+const DEFAULT_OLLAMA_HOST: &str = "http://localhost:11434";
+const DEFAULT_OPENAI_HOST: &str = "http://localhost:8080";
+const IMAGE_PLACEHOLDER: &str = "{image}";
+const MODEL_PLACEHOLDER: &str = "{model}";
+
+/// Which kind of AI backend to talk to.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum AiProviderKind {
+    /// Ollama `/api/generate`
+    #[default]
+    Ollama,
+    /// OpenAI-compatible `/v1/chat/completions` (llama.cpp server, LM Studio, vLLM, ...)
+    Openai,
+    /// A local command that prints the filename on stdout
+    Command,
+}
+
+/// Settings needed to construct an [`AiProvider`].
+pub struct AiProviderConfig<'a> {
+    pub kind: AiProviderKind,
+    pub model: &'a str,
+    pub host: Option<&'a str>,
+    pub api_key: Option<&'a str>,
+    pub command: Option<&'a str>,
+}
+
+/// What a provider gets for a single image.
+pub struct AiRequest<'a> {
+    pub prompt: &'a str,
+    pub image_path: &'a Path,
+    /// Resized JPEG as base64, present when [`AiProvider::needs_image_data`] is true.
+    pub image_base64: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub enum AiProviderError {
+    /// The backend could not be reached; worth retrying while a model loads.
+    Connection(String),
+    /// The backend answered with something unusable.
+    Response(String),
+}
+
+/// A vision model backend that turns an image and prompt into raw text.
+pub trait AiProvider: Send + Sync {
+    /// Human readable name used in progress messages.
+    fn describe(&self) -> String;
+
+    /// Identifies the model for the AI result cache; unlike `describe` it leaves out the host.
+    fn cache_identity(&self) -> String;
+
+    /// Whether the resized, base64-encoded image should be prepared for the request.
+    fn needs_image_data(&self) -> bool {
+        true
+    }
+
+    fn generate(&self, request: &AiRequest<'_>) -> Result<String, AiProviderError>;
+}
+
+pub fn build_provider(config: &AiProviderConfig<'_>) -> Result<Box<dyn AiProvider>, String> {
+    match config.kind {
+        AiProviderKind::Ollama => Ok(Box::new(OllamaProvider {
+            client: create_ai_client(),
+            host: trim_host(config.host.unwrap_or(DEFAULT_OLLAMA_HOST)),
+            model: config.model.to_string(),
+        })),
+        AiProviderKind::Openai => Ok(Box::new(OpenAiProvider {
+            client: create_ai_client(),
+            endpoint: chat_completions_url(config.host.unwrap_or(DEFAULT_OPENAI_HOST)),
+            model: config.model.to_string(),
+            api_key: config.api_key.map(str::to_string),
+        })),
+        AiProviderKind::Command => {
+            let command = config
+                .command
+                .ok_or("The command AI provider needs --ai-command")?;
+            let args = split_command_line(command)?;
+            if args.is_empty() {
+                return Err("--ai-command is empty".to_string());
+            }
+            Ok(Box::new(CommandProvider {
+                args,
+                model: config.model.to_string(),
+            }))
+        }
+    }
+}
+
+/// Helper function to create HTTP client
+fn create_ai_client() -> Client {
+    Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap()
+}
+
+fn trim_host(host: &str) -> String {
+    host.trim_end_matches('/').to_string()
+}
+
+/// Accepts both `http://host:port` and `http://host:port/v1` style base URLs.
+fn chat_completions_url(host: &str) -> String {
+    let host = trim_host(host);
+    if host.ends_with("/chat/completions") {
+        host
+    } else if host.ends_with("/v1") {
+        format!("{}/chat/completions", host)
+    } else {
+        format!("{}/v1/chat/completions", host)
+    }
+}
+
+fn error_status(backend: &str, response: Response) -> AiProviderError {
+    AiProviderError::Response(format!(
+        "{} API error status: {} - Details: {}",
+        backend,
+        response.status(),
+        response.text().unwrap_or_default()
+    ))
+}
+
+#[derive(Serialize)]
+struct OllamaRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    images: Vec<&'a str>,
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+    response: String,
+}
+
+struct OllamaProvider {
+    client: Client,
+    host: String,
+    model: String,
+}
+
+impl AiProvider for OllamaProvider {
+    fn describe(&self) -> String {
+        format!("{} (Ollama at {})", self.model, self.host)
+    }
+
+    fn cache_identity(&self) -> String {
+        format!("ollama:{}", self.model)
+    }
+
+    fn generate(&self, request: &AiRequest<'_>) -> Result<String, AiProviderError> {
+        let body = OllamaRequest {
+            model: &self.model,
+            prompt: request.prompt,
+            images: request.image_base64.into_iter().collect(),
+            stream: false,
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/api/generate", self.host))
+            .json(&body)
+            .send()
+            .map_err(|error| AiProviderError::Connection(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(error_status("Ollama", response));
+        }
+
+        response
+            .json::<OllamaResponse>()
+            .map(|ollama| ollama.response)
+            .map_err(|error| {
+                AiProviderError::Response(format!("Failed to parse Ollama response: {}", error))
+            })
+    }
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    max_tokens: u32,
+    stream: bool,
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: Vec<ChatContent>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ChatContent {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatResponseMessage {
+    content: Option<String>,
+}
+
+struct OpenAiProvider {
+    client: Client,
+    endpoint: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl AiProvider for OpenAiProvider {
+    fn describe(&self) -> String {
+        format!("{} (OpenAI-compatible at {})", self.model, self.endpoint)
+    }
+
+    fn cache_identity(&self) -> String {
+        format!("openai:{}", self.model)
+    }
+
+    fn generate(&self, request: &AiRequest<'_>) -> Result<String, AiProviderError> {
+        let mut content = vec![ChatContent::Text {
+            text: request.prompt.to_string(),
+        }];
+        if let Some(image) = request.image_base64 {
+            content.push(ChatContent::ImageUrl {
+                image_url: ImageUrl {
+                    url: format!("data:image/jpeg;base64,{}", image),
+                },
+            });
+        }
+        let body = ChatRequest {
+            model: &self.model,
+            messages: vec![ChatMessage {
+                role: "user",
+                content,
+            }],
+            max_tokens: 100,
+            stream: false,
+        };
+
+        let mut http_request = self.client.post(&self.endpoint).json(&body);
+        if let Some(api_key) = &self.api_key {
+            http_request = http_request.bearer_auth(api_key);
+        }
+        let response = http_request
+            .send()
+            .map_err(|error| AiProviderError::Connection(error.to_string()))?;
+
+        if !response.status().is_success() {
+            return Err(error_status("Chat completions", response));
+        }
+
+        let chat: ChatResponse = response.json().map_err(|error| {
+            AiProviderError::Response(format!("Failed to parse chat completion: {}", error))
+        })?;
+
+        chat.choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| AiProviderError::Response("Chat completion had no content".to_string()))
+    }
+}
+
+/// Runs a local program: the prompt goes to stdin and the reply is read from stdout.
+///
+/// `{image}` and `{model}` in the arguments are substituted; without an
+/// `{image}` placeholder the image path is appended as the last argument.
+struct CommandProvider {
+    args: Vec<String>,
+    model: String,
+}
+
+impl CommandProvider {
+    fn command_args(&self, image_path: &Path) -> Vec<String> {
+        let image = image_path.to_string_lossy();
+        let mut args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| {
+                arg.replace(IMAGE_PLACEHOLDER, &image)
+                    .replace(MODEL_PLACEHOLDER, &self.model)
+            })
+            .collect();
+        if !self.args.iter().any(|arg| arg.contains(IMAGE_PLACEHOLDER)) {
+            args.push(image.into_owned());
+        }
+        args
+    }
+}
+
+impl AiProvider for CommandProvider {
+    fn describe(&self) -> String {
+        format!("command `{}`", self.args.join(" "))
+    }
+
+    fn cache_identity(&self) -> String {
+        format!("command:{}:{}", self.args.join(" "), self.model)
+    }
+
+    fn needs_image_data(&self) -> bool {
+        false
+    }
+
+    fn generate(&self, request: &AiRequest<'_>) -> Result<String, AiProviderError> {
+        let args = self.command_args(request.image_path);
+        let mut child = Command::new(&args[0])
+            .args(&args[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| {
+                AiProviderError::Response(format!("Could not start {}: {}", args[0], error))
+            })?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // A command that ignores stdin may close it early; that is not an error.
+            let _ = stdin.write_all(request.prompt.as_bytes());
+        }
+
+        let output = child
+            .wait_with_output()
+            .map_err(|error| AiProviderError::Response(error.to_string()))?;
+
+        if !output.status.success() {
+            return Err(AiProviderError::Response(format!(
+                "{} exited with {}: {}",
+                args[0],
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Splits a command line on whitespace, honouring single and double quotes.
+fn split_command_line(command: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut in_arg = false;
+
+    for ch in command.chars() {
+        match (quote, ch) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(ch);
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(format!("Unterminated quote in --ai-command '{}'", command));
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chat_completions_url_accepts_common_base_urls() {
+        assert_eq!(
+            chat_completions_url("http://gpu:8080"),
+            "http://gpu:8080/v1/chat/completions"
+        );
+        assert_eq!(
+            chat_completions_url("http://localhost:1234/v1/"),
+            "http://localhost:1234/v1/chat/completions"
+        );
+        assert_eq!(
+            chat_completions_url("http://vllm:8000/v1/chat/completions"),
+            "http://vllm:8000/v1/chat/completions"
+        );
+    }
+
+    #[test]
+    fn command_provider_substitutes_placeholders() {
+        let provider = CommandProvider {
+            args: split_command_line("describe --model {model} 'the image={image}'").unwrap(),
+            model: "llava".to_string(),
+        };
+
+        assert_eq!(
+            provider.command_args(Path::new("/tmp/a b.jpg")),
+            vec!["describe", "--model", "llava", "the image=/tmp/a b.jpg"]
+        );
+    }
+
+    #[test]
+    fn command_provider_appends_image_without_placeholder() {
+        let provider = CommandProvider {
+            args: split_command_line("my-tool -q").unwrap(),
+            model: String::new(),
+        };
+
+        assert_eq!(
+            provider.command_args(Path::new("photo.jpg")),
+            vec!["my-tool", "-q", "photo.jpg"]
+        );
+        assert!(split_command_line("broken 'quote").is_err());
+    }
+}
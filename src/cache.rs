@@ -1,4 +1,4 @@
-use colored::*;
+use crate::utils::hash_text;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
@@ -10,12 +10,18 @@ use std::{
 #[derive(Serialize, Deserialize)]
 pub struct GPSCache {
     cache: HashMap<String, String>,
+    #[serde(skip)]
+    hits: usize,
+    #[serde(skip)]
+    lookups: usize,
 }
 
 impl GPSCache {
     pub fn new() -> Self {
         GPSCache {
             cache: HashMap::new(),
+            hits: 0,
+            lookups: 0,
         }
     }
 
@@ -26,56 +32,169 @@ impl GPSCache {
     }
 
     pub fn load() -> Self {
-        let cache = Self::get_cache_file_path()
+        Self::get_cache_file_path()
             .filter(|path| path.exists())
             .and_then(|path| File::open(&path).ok())
             .map(BufReader::new)
-            .and_then(|reader| serde_json::from_reader::<_, GPSCache>(reader).ok());
-
-        match cache {
-            Some(loaded_cache) => {
-                println!(
-                    "{}  {}{}",
-                    "💾".bright_green(),
-                    "Loaded GPS cache with ".bright_green(),
-                    format!("{} entries", loaded_cache.cache.len())
-                        .bright_white()
-                        .bold()
-                );
-                loaded_cache
-            }
-            None => GPSCache::new(),
-        }
+            .and_then(|reader| serde_json::from_reader::<_, GPSCache>(reader).ok())
+            .unwrap_or_else(GPSCache::new)
     }
 
-    pub fn save(&self) {
-        let result = Self::get_cache_file_path()
+    pub fn save(&self) -> Result<(), String> {
+        Self::get_cache_file_path()
             .and_then(|path| File::create(&path).ok())
             .map(BufWriter::new)
-            .and_then(|writer| serde_json::to_writer_pretty(writer, &self).ok());
-
-        match result {
-            Some(_) => println!(
-                "{}  {}{}",
-                "💾".bright_green(),
-                "Saved GPS cache with ".bright_green(),
-                format!("{} entries", self.cache.len())
-                    .bright_white()
-                    .bold()
-            ),
-            None => eprintln!(
-                "{} {}",
-                "❌".bright_red(),
-                "Failed to save GPS cache".bright_red()
-            ),
-        }
+            .and_then(|writer| serde_json::to_writer_pretty(writer, &self).ok())
+            .ok_or_else(|| "Failed to save GPS cache".to_string())
     }
 
-    pub fn get(&self, key: &str) -> Option<&String> {
-        self.cache.get(key)
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Looks up a place and counts the hit.
+    pub fn get(&mut self, key: &str) -> Option<&String> {
+        let entry = self.cache.get(key);
+        if entry.is_some() {
+            self.hits += 1;
+        }
+        entry
     }
 
     pub fn insert(&mut self, key: String, value: String) {
         self.cache.insert(key, value);
     }
+
+    /// Counts a query to the geocoder, whether or not it found a place.
+    pub fn count_lookup(&mut self) {
+        self.lookups += 1;
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn lookups(&self) -> usize {
+        self.lookups
+    }
+}
+
+/// Settings that influence an AI naming result besides the image itself.
+pub struct AiCacheKey<'a> {
+    pub content_hash: &'a str,
+    pub model: &'a str,
+    pub prompt: &'a str,
+    pub case: &'a str,
+    pub language: &'a str,
+    pub max_chars: u32,
+}
+
+impl AiCacheKey<'_> {
+    /// `<content hash>-<settings hash>`, so entries for one image stay grouped.
+    fn to_key(&self) -> String {
+        let settings = format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.model, self.case, self.language, self.max_chars, self.prompt
+        );
+        format!("{}-{}", self.content_hash, &hash_text(&settings)[..16])
+    }
+}
+
+/// AI naming results from previous runs, keyed by image content and settings.
+#[derive(Serialize, Deserialize, Default)]
+pub struct AiCache {
+    cache: HashMap<String, String>,
+    #[serde(skip)]
+    hits: usize,
+    #[serde(skip)]
+    misses: usize,
+    #[serde(skip)]
+    updated: bool,
+}
+
+impl AiCache {
+    fn get_cache_file_path() -> Option<PathBuf> {
+        std::env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".nameforge_ai_cache.json"))
+    }
+
+    pub fn load() -> Self {
+        Self::get_cache_file_path()
+            .filter(|path| path.exists())
+            .and_then(|path| File::open(&path).ok())
+            .map(BufReader::new)
+            .and_then(|reader| serde_json::from_reader::<_, AiCache>(reader).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the cache back if this run added entries.
+    pub fn save(&self) -> Result<(), String> {
+        if !self.updated {
+            return Ok(());
+        }
+
+        Self::get_cache_file_path()
+            .and_then(|path| File::create(&path).ok())
+            .map(BufWriter::new)
+            .and_then(|writer| serde_json::to_writer_pretty(writer, &self).ok())
+            .ok_or_else(|| "Failed to save AI cache".to_string())
+    }
+
+    /// Looks up a result and counts the hit or miss.
+    pub fn get(&mut self, key: &AiCacheKey<'_>) -> Option<&String> {
+        let entry = self.cache.get(&key.to_key());
+        if entry.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        entry
+    }
+
+    pub fn insert(&mut self, key: &AiCacheKey<'_>, value: String) {
+        self.cache.insert(key.to_key(), value);
+        self.updated = true;
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key<'a>(content_hash: &'a str, model: &'a str, case: &'a str) -> AiCacheKey<'a> {
+        AiCacheKey {
+            content_hash,
+            model,
+            prompt: "Describe the image",
+            case,
+            language: "English",
+            max_chars: 30,
+        }
+    }
+
+    #[test]
+    fn ai_cache_keys_include_content_and_settings() {
+        let mut cache = AiCache::default();
+        cache.insert(&key("abc", "llava", "snake_case"), "beach".to_string());
+
+        assert_eq!(
+            cache
+                .get(&key("abc", "llava", "snake_case"))
+                .map(String::as_str),
+            Some("beach")
+        );
+        assert!(cache.get(&key("abd", "llava", "snake_case")).is_none());
+        assert!(cache.get(&key("abc", "bakllava", "snake_case")).is_none());
+        assert!(cache.get(&key("abc", "llava", "kebab_case")).is_none());
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+    }
 }
@@ -0,0 +1,282 @@
+use chrono::{Months, NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path};
+
+/// A correction for a camera clock that was set wrong: whole months (so a
+/// year off keeps the day of the month) plus seconds.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeShift {
+    months: i32,
+    seconds: i64,
+}
+
+impl TimeShift {
+    pub fn from_seconds(seconds: i64) -> Self {
+        TimeShift { months: 0, seconds }
+    }
+
+    /// Parses shifts such as `+1h`, `-1y` or `+2d3h30m` (units: y, d, h, m, s).
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "Invalid time shift \"{}\": use e.g. +1h, -1y or +2d3h30m",
+                value
+            )
+        };
+        let text: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+        let (sign, body) = match text.strip_prefix('-') {
+            Some(body) => (-1, body),
+            None => (1, text.strip_prefix('+').unwrap_or(&text)),
+        };
+        if body.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut shift = TimeShift::default();
+        let mut digits = String::new();
+        for ch in body.chars() {
+            if ch.is_ascii_digit() {
+                digits.push(ch);
+                continue;
+            }
+            let amount: i64 = digits.parse().map_err(|_| invalid())?;
+            digits.clear();
+            let unit_seconds = match ch.to_ascii_lowercase() {
+                'y' => {
+                    shift.months = amount
+                        .checked_mul(12)
+                        .and_then(|months| i32::try_from(months).ok())
+                        .and_then(|months| shift.months.checked_add(months))
+                        .ok_or_else(invalid)?;
+                    continue;
+                }
+                'd' => 86_400,
+                'h' => 3_600,
+                'm' => 60,
+                's' => 1,
+                _ => return Err(invalid()),
+            };
+            shift.seconds = amount
+                .checked_mul(unit_seconds)
+                .and_then(|seconds| shift.seconds.checked_add(seconds))
+                .ok_or_else(invalid)?;
+        }
+        if !digits.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(TimeShift {
+            months: sign * shift.months,
+            seconds: sign as i64 * shift.seconds,
+        })
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.months == 0 && self.seconds == 0
+    }
+
+    pub fn apply(self, date_time: NaiveDateTime) -> NaiveDateTime {
+        let months = Months::new(self.months.unsigned_abs());
+        let shifted = if self.months < 0 {
+            date_time.checked_sub_months(months)
+        } else {
+            date_time.checked_add_months(months)
+        };
+        shifted
+            .zip(TimeDelta::try_seconds(self.seconds))
+            .and_then(|(date_time, delta)| date_time.checked_add_signed(delta))
+            .unwrap_or(date_time)
+    }
+}
+
+/// Parses a length of time such as `10m` or `1h30m`, in seconds.
+pub fn parse_duration(value: &str) -> Result<u64, String> {
+    match TimeShift::parse(value) {
+        Ok(TimeShift { months: 0, seconds }) if seconds >= 0 => Ok(seconds.unsigned_abs()),
+        Ok(_) => Err(format!(
+            "Invalid duration \"{}\": use e.g. 30s, 10m or 1h30m",
+            value
+        )),
+        Err(error) => Err(error),
+    }
+}
+
+impl fmt::Display for TimeShift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0s");
+        }
+        let negative = self.months < 0 || (self.months == 0 && self.seconds < 0);
+        write!(f, "{}", if negative { '-' } else { '+' })?;
+
+        let (months, mut seconds) = (self.months.unsigned_abs(), self.seconds.unsigned_abs());
+        if months > 0 {
+            write!(f, "{}y", months / 12)?;
+        }
+        for (unit, length) in [('d', 86_400), ('h', 3_600), ('m', 60), ('s', 1)] {
+            if seconds >= length {
+                write!(f, "{}{}", seconds / length, unit)?;
+                seconds %= length;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for TimeShift {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        TimeShift::parse(&value)
+    }
+}
+
+impl From<TimeShift> for String {
+    fn from(shift: TimeShift) -> Self {
+        shift.to_string()
+    }
+}
+
+/// What EXIF says about the camera that took a photo.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct CameraIdentity {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+}
+
+/// One `[[camera]]` entry of a clock rules file. Every field that is set
+/// must match the photo's EXIF value, ignoring case.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraRule {
+    make: Option<String>,
+    model: Option<String>,
+    serial: Option<String>,
+    shift: TimeShift,
+}
+
+impl CameraRule {
+    fn matches(&self, camera: &CameraIdentity) -> bool {
+        let field_matches = |rule: &Option<String>, value: &Option<String>| match rule {
+            Some(rule) => value
+                .as_deref()
+                .is_some_and(|value| value.trim().eq_ignore_ascii_case(rule.trim())),
+            None => true,
+        };
+        field_matches(&self.make, &camera.make)
+            && field_matches(&self.model, &camera.model)
+            && field_matches(&self.serial, &camera.serial)
+    }
+}
+
+/// Per-camera clock corrections loaded from `--clock-rules`.
+#[derive(Debug, Default, Deserialize)]
+pub struct ClockRules {
+    #[serde(default, rename = "camera")]
+    rules: Vec<CameraRule>,
+}
+
+impl ClockRules {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("Could not read clock rules {}: {}", path.display(), error))?;
+        Self::parse(&text)
+            .map_err(|error| format!("Invalid clock rules {}: {}", path.display(), error))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let rules: ClockRules = toml::from_str(text).map_err(|error| error.to_string())?;
+        if let Some(index) = rules
+            .rules
+            .iter()
+            .position(|rule| rule.make.is_none() && rule.model.is_none() && rule.serial.is_none())
+        {
+            return Err(format!(
+                "camera rule {} needs a make, model or serial",
+                index + 1
+            ));
+        }
+        Ok(rules)
+    }
+
+    /// The shift of the first rule that matches `camera`.
+    pub fn shift_for(&self, camera: &CameraIdentity) -> Option<TimeShift> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(camera))
+            .map(|rule| rule.shift)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_parse_print_and_apply() {
+        let shift = TimeShift::parse("+1d2h30m").unwrap();
+        assert_eq!(shift.to_string(), "+1d2h30m");
+        assert_eq!(TimeShift::parse("-1y").unwrap().to_string(), "-1y");
+        assert_eq!(TimeShift::from_seconds(-3_725).to_string(), "-1h2m5s");
+        assert!(TimeShift::parse("+1w").is_err());
+        assert!(TimeShift::parse("12").is_err());
+
+        let taken =
+            NaiveDateTime::parse_from_str("2024-02-29 23:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(
+            shift.apply(taken).to_string(),
+            "2024-03-02 01:30:00".to_string()
+        );
+        assert_eq!(
+            TimeShift::parse("-1y").unwrap().apply(taken).to_string(),
+            "2023-02-28 23:00:00".to_string()
+        );
+    }
+
+    #[test]
+    fn oversized_shifts_are_errors_not_panics() {
+        assert!(TimeShift::parse("+9223372036854775807d").is_err());
+        assert!(TimeShift::parse("+5000000000000000000s5000000000000000000s").is_err());
+        assert!(TimeShift::parse("-999999999999y").is_err());
+
+        let taken =
+            NaiveDateTime::parse_from_str("2024-05-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        assert_eq!(TimeShift::from_seconds(i64::MAX).apply(taken), taken);
+        assert_eq!(TimeShift::from_seconds(i64::MIN).apply(taken), taken);
+    }
+
+    #[test]
+    fn first_matching_camera_rule_wins() {
+        let rules = ClockRules::parse(
+            r#"
+            [[camera]]
+            model = "Canon EOS R6"
+            serial = "032021001234"
+            shift = "+1h"
+
+            [[camera]]
+            make = "canon"
+            shift = "-1y"
+            "#,
+        )
+        .unwrap();
+        let camera = |serial: &str| CameraIdentity {
+            make: Some("Canon".into()),
+            model: Some("Canon EOS R6".into()),
+            serial: Some(serial.into()),
+        };
+
+        assert_eq!(
+            rules.shift_for(&camera("032021001234")),
+            TimeShift::parse("+1h").ok()
+        );
+        assert_eq!(
+            rules.shift_for(&camera("999")),
+            TimeShift::parse("-1y").ok()
+        );
+        assert_eq!(rules.shift_for(&CameraIdentity::default()), None);
+        assert!(ClockRules::parse("[[camera]]\nshift = \"+1h\"").is_err());
+    }
+}
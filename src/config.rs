@@ -0,0 +1,408 @@
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, Command};
+use std::{
+    any::TypeId,
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the per-project settings file, looked for in the input folder and above.
+pub const PROJECT_CONFIG_FILE: &str = ".nameforge.toml";
+
+/// Settings that only make sense on the command line.
+const COMMAND_LINE_ONLY: [&str; 4] = ["profile", "no-config", "help", "version"];
+
+/// Settings found outside the command line, as extra arguments for the parser.
+#[derive(Debug, Default)]
+pub struct ConfigArguments {
+    /// Config files that were read, lowest precedence first.
+    pub files: Vec<PathBuf>,
+    pub args: Vec<OsString>,
+}
+
+/// `$XDG_CONFIG_HOME/nameforge/config.toml`, falling back to `~/.config`.
+pub fn user_config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("nameforge").join("config.toml"))
+}
+
+/// The nearest `.nameforge.toml` in `start` or one of its parent folders.
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    let start = std::path::absolute(start).unwrap_or_else(|_| start.to_path_buf());
+    let folder = if start.is_file() {
+        start.parent()?
+    } else {
+        &start
+    };
+    folder
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Collects the settings that `matches` leaves out. Keys are long option
+/// names, and later sources win: the user config file, its `[profile.<name>]`
+/// table, the project file found from `start`, its profile table, and finally
+/// `NAMEFORGE_<OPTION>` environment variables. Options given on the command
+/// line are never overridden.
+pub fn config_arguments(
+    command: &Command,
+    matches: &ArgMatches,
+    start: &Path,
+    profile: Option<&str>,
+) -> Result<ConfigArguments, String> {
+    let mut files = Vec::new();
+    for path in user_config_path()
+        .filter(|path| path.is_file())
+        .into_iter()
+        .chain(find_project_config(start))
+    {
+        let text = fs::read_to_string(&path)
+            .map_err(|error| format!("Could not read config {}: {}", path.display(), error))?;
+        let table = text
+            .parse::<toml::Table>()
+            .map_err(|error| format!("Invalid config {}: {}", path.display(), error))?;
+        files.push((path, table));
+    }
+
+    let mut layers = Settings::new(command, matches);
+    layers.apply_files(&files, profile)?;
+    layers.apply_env(|name| env::var(name).ok())?;
+
+    Ok(ConfigArguments {
+        files: files.into_iter().map(|(path, _)| path).collect(),
+        args: layers.into_args(),
+    })
+}
+
+/// Settings gathered so far, keyed by option name and rendered as arguments.
+struct Settings<'a> {
+    command: &'a Command,
+    matches: &'a ArgMatches,
+    subcommand: Option<(&'a Command, &'a ArgMatches)>,
+    values: BTreeMap<String, Vec<OsString>>,
+}
+
+impl<'a> Settings<'a> {
+    fn new(command: &'a Command, matches: &'a ArgMatches) -> Self {
+        let subcommand = matches
+            .subcommand()
+            .and_then(|(name, sub_matches)| Some((command.find_subcommand(name)?, sub_matches)));
+        Settings {
+            command,
+            matches,
+            subcommand,
+            values: BTreeMap::new(),
+        }
+    }
+
+    fn apply_files(
+        &mut self,
+        files: &[(PathBuf, toml::Table)],
+        profile: Option<&str>,
+    ) -> Result<(), String> {
+        let mut profile_found = false;
+        for (path, table) in files {
+            let base = path.parent();
+            self.apply_table(table, base)
+                .map_err(|error| format!("Invalid config {}: {}", path.display(), error))?;
+
+            let profiles = table.get("profile").and_then(toml::Value::as_table);
+            for (name, section) in profiles.into_iter().flatten() {
+                let invalid =
+                    |error: String| format!("Invalid config {}: {}", path.display(), error);
+                let section = section
+                    .as_table()
+                    .ok_or_else(|| invalid(format!("profile.{} must be a table", name)))?;
+                if profile == Some(name.as_str()) {
+                    self.apply_table(section, base).map_err(invalid)?;
+                    profile_found = true;
+                } else {
+                    // Typos in profiles that are not in use should not wait to be found.
+                    for key in section.keys() {
+                        self.find(key).map_err(invalid)?;
+                    }
+                }
+            }
+        }
+
+        match profile {
+            Some(name) if !profile_found => Err(format!(
+                "Profile \"{}\" is not defined in {}",
+                name,
+                if files.is_empty() {
+                    "any config file".to_string()
+                } else {
+                    files
+                        .iter()
+                        .map(|(path, _)| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(" or ")
+                }
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_table(&mut self, table: &toml::Table, base: Option<&Path>) -> Result<(), String> {
+        for (key, value) in table {
+            if key == "profile" && value.is_table() {
+                continue;
+            }
+            let Some(arg) = self.find(key)? else { continue };
+            if self.given(arg) {
+                continue;
+            }
+
+            let items = match value {
+                toml::Value::Array(items) => items
+                    .iter()
+                    .map(|item| scalar_text(key, item))
+                    .collect::<Result<Vec<_>, _>>()?,
+                toml::Value::Boolean(set) if is_flag(arg) => {
+                    self.set_flag(arg, *set);
+                    continue;
+                }
+                value => vec![scalar_text(key, value)?],
+            };
+            let items = items
+                .into_iter()
+                .map(|item| resolve_path(arg, item, base))
+                .collect();
+            self.set_values(arg, items);
+        }
+        Ok(())
+    }
+
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        let args: Vec<&'a Arg> = self
+            .subcommand
+            .iter()
+            .flat_map(|(subcommand, _)| subcommand.get_arguments())
+            .chain(self.command.get_arguments())
+            .collect();
+
+        let mut seen = Vec::new();
+        for arg in args {
+            let Some(long) = arg.get_long() else { continue };
+            if seen.contains(&long) {
+                continue;
+            }
+            seen.push(long);
+            if COMMAND_LINE_ONLY.contains(&long) || arg.get_env().is_some() || self.given(arg) {
+                continue;
+            }
+            let name = format!("NAMEFORGE_{}", long.to_uppercase().replace('-', "_"));
+            let Some(value) = var(&name) else { continue };
+
+            if is_flag(arg) {
+                let set = match value.trim().to_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => true,
+                    "" | "0" | "false" | "no" | "off" => false,
+                    _ => return Err(format!("{} must be true or false, not \"{}\"", name, value)),
+                };
+                self.set_flag(arg, set);
+            } else {
+                let value = resolve_path(arg, value, None);
+                self.set_values(arg, vec![value]);
+            }
+        }
+        Ok(())
+    }
+
+    /// The option a config key names, or `None` when it belongs to another subcommand.
+    fn find(&self, key: &str) -> Result<Option<&'a Arg>, String> {
+        let long = key.replace('_', "-");
+        if COMMAND_LINE_ONLY.contains(&long.as_str()) {
+            return Err(format!("\"{}\" can only be given on the command line", key));
+        }
+        let has_long = |arg: &&Arg| arg.get_long() == Some(long.as_str());
+
+        if let Some(arg) = self
+            .subcommand
+            .and_then(|(subcommand, _)| subcommand.get_arguments().find(has_long))
+            .or_else(|| self.command.get_arguments().find(has_long))
+        {
+            return Ok(Some(arg));
+        }
+        if self
+            .command
+            .get_subcommands()
+            .any(|subcommand| subcommand.get_arguments().any(|arg| has_long(&arg)))
+        {
+            return Ok(None);
+        }
+        Err(format!("unknown setting \"{}\"", key))
+    }
+
+    /// Whether the option was set on the command line or through its own env variable.
+    fn given(&self, arg: &Arg) -> bool {
+        let id = arg.get_id().as_str();
+        let explicit = |matches: &ArgMatches| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        let in_subcommand = self.subcommand.is_some_and(|(subcommand, _)| {
+            subcommand
+                .get_arguments()
+                .any(|sub_arg| std::ptr::eq(sub_arg, arg))
+        });
+
+        match self.subcommand {
+            Some((_, sub_matches)) if in_subcommand => explicit(sub_matches),
+            Some((_, sub_matches)) if arg.is_global_set() => {
+                explicit(self.matches) || explicit(sub_matches)
+            }
+            _ => explicit(self.matches),
+        }
+    }
+
+    fn set_flag(&mut self, arg: &Arg, set: bool) {
+        let long = arg.get_long().unwrap_or_default();
+        let values = if set {
+            vec![OsString::from(format!("--{}", long))]
+        } else {
+            Vec::new()
+        };
+        self.values.insert(long.to_string(), values);
+    }
+
+    fn set_values(&mut self, arg: &Arg, items: Vec<OsString>) {
+        let long = arg.get_long().unwrap_or_default();
+        let values = items
+            .into_iter()
+            .map(|item| {
+                let mut value = OsString::from(format!("--{}=", long));
+                value.push(item);
+                value
+            })
+            .collect();
+        self.values.insert(long.to_string(), values);
+    }
+
+    fn into_args(self) -> Vec<OsString> {
+        self.values.into_values().flatten().collect()
+    }
+}
+
+fn is_flag(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::SetTrue)
+}
+
+fn scalar_text(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(text) => Ok(text.clone()),
+        toml::Value::Integer(number) => Ok(number.to_string()),
+        toml::Value::Float(number) => Ok(number.to_string()),
+        toml::Value::Boolean(set) => Ok(set.to_string()),
+        toml::Value::Datetime(date_time) => Ok(date_time.to_string()),
+        _ => Err(format!(
+            "\"{}\" must be a string, number, boolean or list",
+            key
+        )),
+    }
+}
+
+/// Expands `~` in path options, and makes relative paths in a config file
+/// relative to the folder that file is in.
+fn resolve_path(arg: &Arg, value: String, base: Option<&Path>) -> OsString {
+    if arg.get_value_parser().type_id() != TypeId::of::<PathBuf>() {
+        return value.into();
+    }
+    let path = match (value.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(value),
+    };
+    match base {
+        Some(base) if path.is_relative() => base.join(path).into_os_string(),
+        _ => path.into_os_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("nf")
+            .arg(
+                Arg::new("dry_run")
+                    .long("dry-run")
+                    .action(ArgAction::SetTrue)
+                    .global(true),
+            )
+            .arg(Arg::new("jobs").long("jobs").global(true))
+            .arg(
+                Arg::new("gpx")
+                    .long("gpx")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .global(true),
+            )
+            .subcommand(Command::new("plan").arg(Arg::new("out").long("out")))
+    }
+
+    #[test]
+    fn later_layers_win_and_the_command_line_is_kept() {
+        let user: toml::Table = r#"
+            dry_run = true
+            jobs = 2
+            out = "plan.json"
+
+            [profile.phone-import]
+            gpx = ["tracks/a.gpx", "/abs/b.gpx"]
+        "#
+        .parse()
+        .unwrap();
+        let project: toml::Table = "jobs = 4\ndry-run = false".parse().unwrap();
+        let files = [
+            (
+                PathBuf::from("/home/me/.config/nameforge/config.toml"),
+                user,
+            ),
+            (PathBuf::from("/photos/.nameforge.toml"), project),
+        ];
+        let args = |argv: &[&str], profile: Option<&str>, env: &[(&str, &str)]| {
+            let command = command();
+            let matches = command.clone().get_matches_from(argv);
+            let mut settings = Settings::new(&command, &matches);
+            settings.apply_files(&files, profile)?;
+            settings.apply_env(|name| {
+                env.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            })?;
+            Ok::<_, String>(settings.into_args())
+        };
+
+        assert_eq!(args(&["nf"], None, &[]).unwrap(), ["--jobs=4"]);
+        assert_eq!(
+            args(
+                &["nf", "--jobs", "8"],
+                Some("phone-import"),
+                &[("NAMEFORGE_DRY_RUN", "1")]
+            )
+            .unwrap(),
+            [
+                "--dry-run",
+                "--gpx=/home/me/.config/nameforge/tracks/a.gpx",
+                "--gpx=/abs/b.gpx"
+            ]
+        );
+        assert!(args(&["nf"], Some("missing"), &[]).is_err());
+
+        let typo: toml::Table = "job = 2".parse().unwrap();
+        let command = command();
+        let matches = command.clone().get_matches_from(["nf"]);
+        assert!(Settings::new(&command, &matches)
+            .apply_files(&[(PathBuf::from("c.toml"), typo)], None)
+            .is_err());
+    }
+}
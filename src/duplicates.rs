@@ -0,0 +1,107 @@
+use crate::pool::for_each_ordered;
+use crate::utils::hash_file_contents;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// What to do with a byte-identical copy of a file that is being kept.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum DuplicateMode {
+    /// Leave duplicates where they are, untouched
+    Skip,
+    /// Delete duplicates
+    Delete,
+    /// Move duplicates into --duplicates-dir
+    MoveTo,
+    /// Rename everything as usual and list the duplicates found
+    Report,
+}
+
+/// Groups paths with identical contents, comparing sizes first and hashing
+/// only files that share a size. Groups and their members keep input order.
+pub fn find_duplicate_groups(paths: &[PathBuf], jobs: usize) -> Vec<Vec<usize>> {
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        if let Ok(metadata) = fs::metadata(path) {
+            by_size.entry(metadata.len()).or_default().push(index);
+        }
+    }
+
+    let mut candidates: Vec<usize> = by_size
+        .into_values()
+        .filter(|indices| indices.len() > 1)
+        .flatten()
+        .collect();
+    candidates.sort_unstable();
+
+    let mut by_hash: HashMap<String, Vec<usize>> = HashMap::new();
+    for_each_ordered(
+        &candidates,
+        jobs,
+        |_, &index| hash_file_contents(&paths[index]).ok(),
+        |_, &index, hash| {
+            if let Some(hash) = hash {
+                by_hash.entry(hash).or_default().push(index);
+            }
+        },
+    );
+
+    let mut groups: Vec<Vec<usize>> = by_hash
+        .into_values()
+        .filter(|indices| indices.len() > 1)
+        .collect();
+    groups.sort_unstable_by_key(|indices| indices[0]);
+    groups
+}
+
+/// Whether two files have the same size and contents.
+pub fn same_contents(a: &Path, b: &Path) -> bool {
+    let sizes = (fs::metadata(a), fs::metadata(b));
+    let (Ok(a_meta), Ok(b_meta)) = sizes else {
+        return false;
+    };
+    if a_meta.len() != b_meta.len() {
+        return false;
+    }
+
+    match (hash_file_contents(a), hash_file_contents(b)) {
+        (Ok(a_hash), Ok(b_hash)) => a_hash == b_hash,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn groups_only_identical_contents() {
+        let dir = create_temp_dir("duplicates");
+        let files = [
+            ("a.jpg", "same"),
+            ("b.jpg", "diff"),
+            ("c.jpg", "same"),
+            ("d.jpg", "longer"),
+        ];
+        let paths: Vec<PathBuf> = files
+            .iter()
+            .map(|(name, contents)| {
+                let path = dir.join(name);
+                fs::write(&path, contents).unwrap();
+                path
+            })
+            .collect();
+
+        assert_eq!(find_duplicate_groups(&paths, 2), vec![vec![0, 2]]);
+        assert!(same_contents(&paths[0], &paths[2]));
+        assert!(!same_contents(&paths[0], &paths[1]));
+
+        for path in &paths {
+            fs::remove_file(path).unwrap();
+        }
+        fs::remove_dir(dir).unwrap();
+    }
+}
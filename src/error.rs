@@ -0,0 +1,76 @@
+use std::{fmt, io, path::PathBuf};
+
+/// Everything that can go wrong in a run, for a whole run or a single file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Options that do not work together, or a settings file (clock rules,
+    /// places, GPX, GeoNames) that could not be loaded.
+    Config(String),
+    /// The input path does not exist.
+    NotFound(PathBuf),
+    /// The input file is not a supported photo or video.
+    Unsupported(PathBuf),
+    /// A file system operation on `path` failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A copy did not match its source; the copy was removed and the source kept.
+    ChecksumMismatch(PathBuf),
+    /// Every candidate name for the file is taken.
+    NoUniqueName(PathBuf),
+    /// A photo lacks the EXIF data an operation needs.
+    MissingExif(String),
+    /// A rename plan could not be read or written.
+    Plan(String),
+    /// A rename journal could not be found, read or written.
+    Journal(String),
+}
+
+impl Error {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(message)
+            | Error::MissingExif(message)
+            | Error::Plan(message)
+            | Error::Journal(message) => write!(f, "{}", message),
+            Error::NotFound(path) => write!(
+                f,
+                "Input path does not exist or is not accessible: {}",
+                path.display()
+            ),
+            Error::Unsupported(path) => {
+                write!(f, "Not a supported media file: {}", path.display())
+            }
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::ChecksumMismatch(path) => {
+                write!(
+                    f,
+                    "{}: checksum mismatch after copy, source kept",
+                    path.display()
+                )
+            }
+            Error::NoUniqueName(path) => write!(
+                f,
+                "Failed to generate a unique filename for {}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
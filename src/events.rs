@@ -0,0 +1,329 @@
+use crate::{
+    exif::DateSource,
+    journal::JournalOp,
+    plan::{PlannedAction, PlannedRename},
+    Error, MediaKind,
+};
+use serde::{Serialize, Serializer};
+use std::{path::Path, time::Duration};
+
+/// Receives progress events while a run works. Events may arrive from worker
+/// threads, so observers must be `Sync`.
+pub trait Observer: Sync {
+    fn on_event(&self, event: &Event<'_>);
+}
+
+/// Ignores every event.
+impl Observer for () {
+    fn on_event(&self, _event: &Event<'_>) {}
+}
+
+impl<F: Fn(&Event<'_>) + Sync> Observer for F {
+    fn on_event(&self, event: &Event<'_>) {
+        self(event)
+    }
+}
+
+/// Why a file was left alone. Serialized as a stable kebab-case string.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum SkipReason {
+    /// The file already has the name and folder it would get.
+    AlreadyNamed,
+    /// A byte-identical copy that `--duplicates` leaves untouched.
+    Duplicate,
+    /// Beyond the `max_media` limit of this run.
+    MaxMedia,
+    /// The file has no usable extension.
+    NoExtension,
+    /// Captured outside `since`/`until`, or with no capture time to check.
+    OutsideDateRange,
+    /// A plan entry whose source is gone.
+    SourceMissing,
+    /// A plan entry whose destination is already taken.
+    DestinationExists,
+    /// A duplicate to delete whose kept copy is gone or no longer identical.
+    KeptCopyChanged,
+    /// Undo: the renamed file no longer exists.
+    RenamedFileMissing,
+    /// Undo: the renamed file changed since the run.
+    RenamedFileChanged,
+    /// Undo: another file now has the original path.
+    OriginalTaken,
+    /// Undo: the original of a copy is gone, so the copy is the only one left.
+    LastCopy,
+    /// Undo: the saved earlier contents of a changed file are gone.
+    BackupMissing,
+}
+
+/// A timed part of a run, as listed under the slowest steps of the summary.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Step {
+    /// Walking the input folder.
+    Scan,
+    /// Hashing file contents for `--duplicates`.
+    Duplicates,
+    /// Perceptual hashing for `--group-similar` and `--burst-folder`.
+    Similar,
+    /// Reading EXIF, video, XMP and sidecar metadata of one file.
+    Metadata,
+    /// Turning one file's coordinates into a place name.
+    Geocoding,
+    /// Asking the AI backend for one file's name.
+    AiNaming,
+    /// Asking the AI backend for one photo's XMP description.
+    AiDescription,
+    /// Renaming, copying or deleting one file with its sidecars.
+    Transfer,
+}
+
+impl Step {
+    pub fn label(self) -> &'static str {
+        match self {
+            Step::Scan => "scanning",
+            Step::Duplicates => "duplicate hashing",
+            Step::Similar => "similarity hashing",
+            Step::Metadata => "reading metadata",
+            Step::Geocoding => "geocoding",
+            Step::AiNaming => "AI naming",
+            Step::AiDescription => "AI descriptions",
+            Step::Transfer => "renaming",
+        }
+    }
+}
+
+/// Something a run did or found. The kinds listed in [`Event::is_json_event`]
+/// are written by `--output json`; their field names are a stable interface,
+/// so only add to them.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// A supported media file was found under the input path.
+    Discovered {
+        path: &'a Path,
+        kind: MediaKind,
+    },
+    /// A rename, copy or delete was decided, with its reasons.
+    Planned(&'a PlannedRename),
+    /// A planned action was carried out.
+    Renamed {
+        source: &'a Path,
+        destination: &'a Path,
+        action: PlannedAction,
+    },
+    Skipped {
+        path: &'a Path,
+        reason: SkipReason,
+    },
+    /// Something went wrong; `path` is missing when the whole run could not start.
+    Failed {
+        path: Option<&'a Path>,
+        #[serde(serialize_with = "display")]
+        error: &'a Error,
+    },
+    GpsResolved {
+        path: &'a Path,
+        lat: f64,
+        lon: f64,
+        place: Option<&'a str>,
+    },
+    AiNamed {
+        path: &'a Path,
+        name: &'a str,
+    },
+
+    /// Scanning finished.
+    Scanned {
+        images: usize,
+        videos: usize,
+    },
+    /// Only the first `max` files are processed.
+    MediaLimit {
+        max: usize,
+    },
+    GeoNamesLoading {
+        path: &'a Path,
+    },
+    GeoNamesLoaded {
+        places: usize,
+    },
+    GpxLoaded {
+        points: usize,
+    },
+    GpsCacheLoaded {
+        entries: usize,
+    },
+    GpsCacheSaved {
+        entries: usize,
+    },
+    /// Work on a file's metadata and name has started.
+    Processing {
+        path: &'a Path,
+        kind: MediaKind,
+    },
+    /// Files that are named together (RAW+JPEG, Live Photos).
+    Paired {
+        paths: Vec<&'a Path>,
+    },
+    DuplicatesFound {
+        count: usize,
+    },
+    /// `path` has the same contents as `original`.
+    Duplicate {
+        path: &'a Path,
+        original: &'a Path,
+    },
+    HashingSimilar,
+    /// A group of near-identical photos, numbered from 1.
+    SimilarGroup {
+        group: usize,
+        paths: Vec<&'a Path>,
+    },
+    GeocoderLookup {
+        lat: f64,
+        lon: f64,
+    },
+    AiRequest {
+        path: &'a Path,
+        model: &'a str,
+    },
+    AiRetry {
+        path: &'a Path,
+    },
+    AiCacheStats {
+        hits: usize,
+        misses: usize,
+    },
+    /// What a dry run would do with a planned rename.
+    DryRun(&'a PlannedRename),
+    SidecarMoved {
+        source: &'a Path,
+        destination: &'a Path,
+    },
+    XmpWritten {
+        path: &'a Path,
+    },
+    /// Where a file's capture time came from; `None` when it has none.
+    Dated {
+        path: &'a Path,
+        source: Option<DateSource>,
+    },
+    /// The AI backend gave no name, so the file is named without it.
+    AiFallback {
+        path: &'a Path,
+        reason: &'a str,
+    },
+    /// Place names found in the GPS cache versus looked up with the geocoder.
+    GpsCacheStats {
+        hits: usize,
+        lookups: usize,
+    },
+    /// How long a step took, for one file when `path` is set.
+    StepTimed {
+        step: Step,
+        path: Option<&'a Path>,
+        elapsed: Duration,
+    },
+    /// Something worth knowing that does not stop the file from being handled.
+    Warning {
+        path: Option<&'a Path>,
+        message: &'a str,
+    },
+    UndoStarted {
+        journal: &'a Path,
+        entries: usize,
+    },
+    /// An undo step, or with `dry_run` what it would do.
+    Restored {
+        op: JournalOp,
+        from: &'a Path,
+        to: &'a Path,
+        dry_run: bool,
+    },
+}
+
+impl Event<'_> {
+    /// Whether `--output json` writes this event: what happens to each file,
+    /// the duplicates found and the steps of an undo.
+    pub fn is_json_event(&self) -> bool {
+        matches!(
+            self,
+            Event::Discovered { .. }
+                | Event::Planned(_)
+                | Event::Renamed { .. }
+                | Event::Skipped { .. }
+                | Event::Failed { .. }
+                | Event::GpsResolved { .. }
+                | Event::AiNamed { .. }
+                | Event::DuplicatesFound { .. }
+                | Event::Duplicate { .. }
+                | Event::UndoStarted { .. }
+                | Event::Restored { .. }
+        )
+    }
+}
+
+fn display<S: Serializer>(error: &&Error, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn file_events_serialize_with_stable_names() {
+        fn line(event: Event<'_>) -> serde_json::Value {
+            serde_json::to_value(&event).unwrap()
+        }
+        let path = Path::new("/photos/IMG_1.jpg");
+
+        assert_eq!(
+            line(Event::Skipped {
+                path,
+                reason: SkipReason::AlreadyNamed
+            }),
+            serde_json::json!({"event": "skipped", "path": "/photos/IMG_1.jpg", "reason": "already-named"})
+        );
+        assert_eq!(
+            line(Event::GpsResolved {
+                path,
+                lat: 48.5,
+                lon: 2.25,
+                place: Some("Paris")
+            })["event"],
+            "gps-resolved"
+        );
+        assert_eq!(
+            line(Event::Failed {
+                path: Some(path),
+                error: &Error::NoUniqueName(path.to_path_buf())
+            })["error"],
+            "Failed to generate a unique filename for /photos/IMG_1.jpg"
+        );
+
+        let planned = PlannedRename {
+            source: path.to_path_buf(),
+            destination: PathBuf::from("/photos/2024-05-01_Paris.jpg"),
+            action: PlannedAction::Rename,
+            reasons: Default::default(),
+            sidecars: Vec::new(),
+            xmp: None,
+        };
+        let planned = line(Event::Planned(&planned));
+        assert_eq!(planned["event"], "planned");
+        assert_eq!(planned["destination"], "/photos/2024-05-01_Paris.jpg");
+        assert_eq!(planned["action"], "rename");
+        assert!(Event::Discovered {
+            path,
+            kind: MediaKind::Image
+        }
+        .is_json_event());
+        assert!(!Event::HashingSimilar.is_json_event());
+    }
+}
@@ -1,8 +1,42 @@
-use chrono::{DateTime, Local, NaiveDateTime};
-use colored::*;
+use crate::clock::CameraIdentity;
+use crate::timezone::{parse_subseconds, parse_utc_offset, CaptureTime};
+use chrono::{DateTime, NaiveDateTime, Timelike, Utc};
 use exif::{Field, In, Reader, Tag, Value};
+use serde::{Deserialize, Serialize};
 use std::{fs, io::BufReader, path::Path};
 
+/// Apple maker notes start with this signature, then a version and "MM".
+const APPLE_MAKER_NOTE_PREFIX: &[u8] = b"Apple iOS\0";
+/// Apple maker note tag holding the Live Photo `ContentIdentifier`.
+const APPLE_CONTENT_IDENTIFIER_TAG: u16 = 0x0011;
+
+/// Where a capture time was taken from.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    Exif,
+    Container,
+    /// An embedded XMP packet or `.xmp` sidecar
+    Xmp,
+    /// A Google Takeout or exiftool JSON sidecar
+    Sidecar,
+    FileCreated,
+    FileModified,
+}
+
+impl DateSource {
+    pub fn label(self) -> &'static str {
+        match self {
+            DateSource::Exif => "EXIF",
+            DateSource::Container => "video container",
+            DateSource::Xmp => "XMP",
+            DateSource::Sidecar => "JSON sidecar",
+            DateSource::FileCreated => "file created",
+            DateSource::FileModified => "file modified",
+        }
+    }
+}
+
 pub fn parse_gps_rational(field: Option<&Field>) -> Option<f64> {
     field.and_then(|f| match &f.value {
         Value::Rational(vec) if vec.len() >= 3 => {
@@ -16,94 +50,163 @@ pub fn parse_gps_rational(field: Option<&Field>) -> Option<f64> {
 }
 
 /// Helper function to get file system time based on preference
-fn get_file_time(metadata: &fs::Metadata, prefer_modified: bool) -> Option<std::time::SystemTime> {
-    let (primary, fallback) = if prefer_modified {
-        (metadata.modified(), metadata.created())
-    } else {
-        (metadata.created(), metadata.modified())
+fn get_file_time(
+    metadata: &fs::Metadata,
+    prefer_modified: bool,
+) -> Option<(std::time::SystemTime, DateSource)> {
+    let created = || {
+        metadata
+            .created()
+            .ok()
+            .map(|t| (t, DateSource::FileCreated))
+    };
+    let modified = || {
+        metadata
+            .modified()
+            .ok()
+            .map(|t| (t, DateSource::FileModified))
     };
 
-    primary.ok().or_else(|| fallback.ok())
+    if prefer_modified {
+        modified().or_else(created)
+    } else {
+        created().or_else(modified)
+    }
 }
 
-/// Helper function to format system time as string
-fn format_system_time(time: std::time::SystemTime, date_only: bool) -> String {
-    let dt: DateTime<Local> = time.into();
-    let format_str = if date_only {
-        "%Y-%m-%d"
-    } else {
-        "%Y-%m-%d_%H-%M-%S"
+/// Helper function to convert system time to a capture moment
+fn system_time_to_capture(time: std::time::SystemTime) -> CaptureTime {
+    let dt: DateTime<Utc> = time.into();
+    CaptureTime::Instant(dt)
+}
+
+/// Formats a capture time as a date or full timestamp fragment, with
+/// milliseconds after the seconds when `subseconds` is set
+pub fn format_date_time(date_time: &NaiveDateTime, date_only: bool, subseconds: bool) -> String {
+    let format_str = match (date_only, subseconds) {
+        (true, _) => "%Y-%m-%d",
+        (false, false) => "%Y-%m-%d_%H-%M-%S",
+        (false, true) => "%Y-%m-%d_%H-%M-%S-%3f",
     };
-    dt.format(format_str).to_string()
+    date_time.format(format_str).to_string()
 }
 
-pub fn get_file_date_string(path: &Path, date_only: bool, prefer_modified: bool) -> Option<String> {
+pub fn get_file_date_time(path: &Path, prefer_modified: bool) -> Option<(CaptureTime, DateSource)> {
     let metadata = fs::metadata(path).ok()?;
-    get_file_time(&metadata, prefer_modified).map(|time| format_system_time(time, date_only))
+    get_file_time(&metadata, prefer_modified)
+        .map(|(time, source)| (system_time_to_capture(time), source))
 }
 
-/// Helper function to try parsing EXIF date
-fn try_parse_exif_date(exif: &exif::Exif, date_only: bool) -> Option<String> {
-    exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)
+/// Helper function to try parsing EXIF date, with `SubSecTimeOriginal` and
+/// the `OffsetTimeOriginal` time zone when the camera wrote them
+pub fn try_parse_exif_date(exif: &exif::Exif) -> Option<CaptureTime> {
+    let date_time = exif
+        .get_field(Tag::DateTimeOriginal, In::PRIMARY)
         .map(|field| field.display_value().with_unit(exif).to_string())
         .and_then(|date_str| {
             NaiveDateTime::parse_from_str(&date_str, "%Y:%m:%d %H:%M:%S")
                 .or_else(|_| NaiveDateTime::parse_from_str(&date_str, "%Y-%m-%d %H:%M:%S"))
                 .ok()
-        })
-        .map(|date| {
-            let format_str = if date_only {
-                "%Y-%m-%d"
-            } else {
-                "%Y-%m-%d_%H-%M-%S"
-            };
-            date.format(format_str).to_string()
-        })
+        })?;
+    let date_time = get_ascii_field(exif, Tag::SubSecTimeOriginal)
+        .and_then(|digits| parse_subseconds(&digits))
+        .and_then(|nanos| date_time.with_nanosecond(nanos))
+        .unwrap_or(date_time);
+    let offset = get_ascii_field(exif, Tag::OffsetTimeOriginal)
+        .or_else(|| get_ascii_field(exif, Tag::OffsetTime))
+        .and_then(|offset| parse_utc_offset(&offset));
+
+    Some(CaptureTime::new(date_time, offset))
 }
 
-pub fn get_date_string(
+pub fn get_date_time(
     path: &Path,
     exif_opt: &Option<exif::Exif>,
-    date_only: bool,
     use_file_date: bool,
     prefer_modified: bool,
-) -> Option<String> {
+) -> Option<(CaptureTime, DateSource)> {
     // If use_file_date is true, prioritize file system date
     if use_file_date {
-        return get_file_date_string(path, date_only, prefer_modified);
+        return get_file_date_time(path, prefer_modified);
     }
 
     let metadata = fs::metadata(path).ok()?;
 
-    // Try EXIF date first, with appropriate fallback messages
-    let exif_result = exif_opt
-        .as_ref()
-        .and_then(|exif| try_parse_exif_date(exif, date_only));
-
-    match (exif_opt, exif_result) {
-        (None, _) => {
-            eprintln!(
-                "{} {}{}  {}",
-                "⚠️".bright_yellow(),
-                "No EXIF data for ".bright_yellow(),
-                path.display().to_string().bright_white(),
-                "falling back to file modified time".bright_yellow()
-            );
-        }
-        (Some(_), None) => {
-            eprintln!(
-                "{} {}{}  {}",
-                "⚠️".bright_yellow(),
-                "No EXIF DateTimeOriginal for ".bright_yellow(),
-                path.display().to_string().bright_white(),
-                "falling back to file modified time".bright_yellow()
-            );
-        }
-        (Some(_), Some(date)) => return Some(date),
+    if let Some(date) = exif_opt.as_ref().and_then(try_parse_exif_date) {
+        return Some((date, DateSource::Exif));
     }
 
     // Fallback to file system date
-    get_file_time(&metadata, prefer_modified).map(|time| format_system_time(time, date_only))
+    get_file_time(&metadata, prefer_modified)
+        .map(|(time, source)| (system_time_to_capture(time), source))
+}
+
+/// Helper function to read the first string of an ASCII EXIF field
+fn get_ascii_field(exif: &exif::Exif, tag: Tag) -> Option<String> {
+    exif.get_field(tag, In::PRIMARY)
+        .and_then(|field| match &field.value {
+            Value::Ascii(vec) => vec
+                .first()
+                .map(|bytes| String::from_utf8_lossy(bytes).into_owned()),
+            _ => None,
+        })
+        .map(|value| {
+            value
+                .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                .to_string()
+        })
+        .filter(|value| !value.is_empty())
+}
+
+/// Camera model from EXIF, falling back to the maker when no model is set
+pub fn get_camera_name(exif: &exif::Exif) -> Option<String> {
+    get_ascii_field(exif, Tag::Model).or_else(|| get_ascii_field(exif, Tag::Make))
+}
+
+/// Make, model and body serial number, for matching clock rules
+pub fn get_camera_identity(exif: &exif::Exif) -> CameraIdentity {
+    CameraIdentity {
+        make: get_ascii_field(exif, Tag::Make),
+        model: get_ascii_field(exif, Tag::Model),
+        serial: get_ascii_field(exif, Tag::BodySerialNumber),
+    }
+}
+
+/// The Live Photo `ContentIdentifier` from Apple's maker note; the `.MOV`
+/// half of the Live Photo carries the same value.
+pub fn get_content_identifier(exif: &exif::Exif) -> Option<String> {
+    match &exif.get_field(Tag::MakerNote, In::PRIMARY)?.value {
+        Value::Undefined(note, _) => parse_apple_content_identifier(note),
+        _ => None,
+    }
+}
+
+/// Reads tag 0x0011 from the big-endian IFD that follows the 14-byte Apple
+/// maker note header. Value offsets are relative to the start of the note.
+fn parse_apple_content_identifier(note: &[u8]) -> Option<String> {
+    if !note.starts_with(APPLE_MAKER_NOTE_PREFIX) || note.get(12..14)? != b"MM" {
+        return None;
+    }
+    let read_u16 = |at: usize| Some(u16::from_be_bytes(note.get(at..at + 2)?.try_into().ok()?));
+    let read_u32 = |at: usize| Some(u32::from_be_bytes(note.get(at..at + 4)?.try_into().ok()?));
+
+    let entries = read_u16(14)? as usize;
+    let entry = (0..entries)
+        .map(|index| 16 + index * 12)
+        .find(|&entry| read_u16(entry) == Some(APPLE_CONTENT_IDENTIFIER_TAG))?;
+    // Type 2 is ASCII; strings of four bytes or less are stored inline.
+    if read_u16(entry + 2)? != 2 {
+        return None;
+    }
+    let length = read_u32(entry + 4)? as usize;
+    let start = if length <= 4 {
+        entry + 8
+    } else {
+        read_u32(entry + 8)? as usize
+    };
+    let value = String::from_utf8_lossy(note.get(start..start.checked_add(length)?)?);
+    let value = value.trim_end_matches('\0');
+    (!value.is_empty()).then(|| value.to_string())
 }
 
 pub fn extract_gps_coordinates(exif: &exif::Exif) -> Option<(f64, f64)> {
@@ -145,3 +248,31 @@ pub fn read_exif_data(path: &Path) -> Option<exif::Exif> {
         Reader::new().read_from_container(&mut bufreader).ok()
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_live_photo_identifier_from_an_apple_maker_note() {
+        let identifier = b"5F3C1A2E-LIVE\0";
+        let mut note = APPLE_MAKER_NOTE_PREFIX.to_vec();
+        note.extend_from_slice(b"\0\x01MM");
+        note.extend_from_slice(&2u16.to_be_bytes());
+        // An unrelated entry first, then ContentIdentifier pointing past the IFD.
+        note.extend_from_slice(&[0x00, 0x01, 0x00, 0x09, 0, 0, 0, 1, 0, 0, 0, 14]);
+        let value_offset = (note.len() + 12 + 4) as u32;
+        note.extend_from_slice(&APPLE_CONTENT_IDENTIFIER_TAG.to_be_bytes());
+        note.extend_from_slice(&2u16.to_be_bytes());
+        note.extend_from_slice(&(identifier.len() as u32).to_be_bytes());
+        note.extend_from_slice(&value_offset.to_be_bytes());
+        note.extend_from_slice(&[0u8; 4]);
+        note.extend_from_slice(identifier);
+
+        assert_eq!(
+            parse_apple_content_identifier(&note).as_deref(),
+            Some("5F3C1A2E-LIVE")
+        );
+        assert_eq!(parse_apple_content_identifier(b"Nikon\0\x02"), None);
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{Error, MediaKind};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use std::{fmt, fs, path::Path};
+
+/// Per-folder ignore file, read with gitignore semantics.
+pub const IGNORE_FILE: &str = ".nameforgeignore";
+
+/// A `--include` or `--exclude` pattern. Patterns without a `/` match file
+/// and folder names anywhere in the tree; patterns with one match the path
+/// relative to the input folder. A trailing `/` only matches folders.
+/// Matching ignores case, since cameras write `.JPG` as often as `.jpg`.
+#[derive(Clone, Debug)]
+pub struct PathGlob {
+    text: String,
+    matcher: GlobMatcher,
+    anchored: bool,
+    folders_only: bool,
+}
+
+impl PathGlob {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let folders_only = text.ends_with('/') && text.len() > 1;
+        let pattern = text.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let matcher = GlobBuilder::new(pattern.trim_start_matches('/'))
+            .literal_separator(true)
+            .case_insensitive(true)
+            .build()
+            .map_err(|error| format!("invalid glob \"{}\": {}", text, error))?
+            .compile_matcher();
+
+        Ok(PathGlob {
+            text: text.to_string(),
+            matcher,
+            anchored,
+            folders_only,
+        })
+    }
+
+    /// `relative` is the path below the input folder, with `/` separators.
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.folders_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.matcher.is_match(relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            self.matcher.is_match(name)
+        }
+    }
+}
+
+impl fmt::Display for PathGlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Narrows down which files a folder scan picks up. The default takes every
+/// supported photo and video at any depth.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MediaFilter<'a> {
+    /// When not empty, only files matching one of these are taken.
+    pub include: &'a [PathGlob],
+    /// Files and folders matching one of these are left out.
+    pub exclude: &'a [PathGlob],
+    pub only: Option<MediaKind>,
+    /// Smallest file size in bytes.
+    pub min_size: Option<u64>,
+    /// Largest file size in bytes.
+    pub max_size: Option<u64>,
+    /// How many folder levels to scan; 1 is the input folder alone.
+    pub max_depth: Option<usize>,
+}
+
+impl MediaFilter<'_> {
+    pub(crate) fn descends_into(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max| depth < max)
+    }
+
+    pub(crate) fn excludes_folder(&self, relative: &str) -> bool {
+        self.exclude.iter().any(|glob| glob.matches(relative, true))
+    }
+
+    /// Whether a supported media file passes every filter. The size is read
+    /// only when a size limit is set.
+    pub(crate) fn takes_file(&self, path: &Path, relative: &str, kind: MediaKind) -> bool {
+        if self.only.is_some_and(|only| only != kind)
+            || self
+                .exclude
+                .iter()
+                .any(|glob| glob.matches(relative, false))
+        {
+            return false;
+        }
+        if !self.include.is_empty()
+            && !self
+                .include
+                .iter()
+                .any(|glob| glob.matches(relative, false))
+        {
+            return false;
+        }
+        if self.min_size.is_none() && self.max_size.is_none() {
+            return true;
+        }
+
+        fs::metadata(path).is_ok_and(|metadata| {
+            let size = metadata.len();
+            self.min_size.is_none_or(|min| size >= min)
+                && self.max_size.is_none_or(|max| size <= max)
+        })
+    }
+}
+
+/// The `.nameforgeignore` files of the folders a scan is in, outermost first.
+#[derive(Default)]
+pub(crate) struct IgnoreStack {
+    files: Vec<Gitignore>,
+}
+
+impl IgnoreStack {
+    /// Reads the ignore file of `dir`, if it has one. Returns whether one was
+    /// pushed, so the caller can `pop` it when leaving the folder.
+    pub fn enter(&mut self, dir: &Path) -> Result<bool, Error> {
+        let path = dir.join(IGNORE_FILE);
+        if !path.is_file() {
+            return Ok(false);
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        if let Some(error) = builder.add(&path) {
+            return Err(Error::Config(format!(
+                "Invalid {}: {}",
+                path.display(),
+                error
+            )));
+        }
+        let gitignore = builder
+            .build()
+            .map_err(|error| Error::Config(format!("Invalid {}: {}", path.display(), error)))?;
+        self.files.push(gitignore);
+        Ok(true)
+    }
+
+    pub fn pop(&mut self) {
+        self.files.pop();
+    }
+
+    /// The innermost file with a matching rule decides, so a subfolder can
+    /// re-include with `!pattern` what a parent ignores.
+    pub fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        self.files
+            .iter()
+            .rev()
+            .find_map(|gitignore| match gitignore.matched(path, is_dir) {
+                Match::None => None,
+                Match::Ignore(_) => Some(true),
+                Match::Whitelist(_) => Some(false),
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// Parses a file size such as `500k`, `20MB` or `1.5GiB`. `k`, `M`, `G` and
+/// `T` count in thousands, `KiB`, `MiB`, ... in 1024s; plain numbers are bytes.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size \"{}\", expected e.g. 500k or 20MB", text))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "unknown size unit in \"{}\", use B, k, MB, GB, KiB, MiB or GiB",
+                text
+            ))
+        }
+    };
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// Parses a `--since` date (`2024-05-01`) or date and time (`2024-05-01T18:00`).
+pub fn parse_since(text: &str) -> Result<NaiveDateTime, String> {
+    parse_date_bound(text, NaiveTime::MIN)
+}
+
+/// Parses an `--until` date or date and time; a date alone includes that whole day.
+pub fn parse_until(text: &str) -> Result<NaiveDateTime, String> {
+    let end_of_day =
+        NaiveTime::from_hms_nano_opt(23, 59, 59, 999_999_999).unwrap_or(NaiveTime::MIN);
+    parse_date_bound(text, end_of_day)
+}
+
+fn parse_date_bound(text: &str, time_for_date: NaiveTime) -> Result<NaiveDateTime, String> {
+    let text = text.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date.and_time(time_for_date));
+    }
+    [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .ok_or_else(|| {
+        format!(
+            "invalid date \"{}\", expected YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS]",
+            text
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn globs(patterns: &[&str]) -> Vec<PathGlob> {
+        patterns
+            .iter()
+            .map(|pattern| PathGlob::parse(pattern).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn globs_match_names_or_relative_paths() {
+        let include = globs(&["*.jpg", "2024/**/*.mov"]);
+        let exclude = globs(&["Exports/", "*_edit.*"]);
+        let filter = MediaFilter {
+            include: &include,
+            exclude: &exclude,
+            ..Default::default()
+        };
+        let takes = |relative: &str, kind| filter.takes_file(Path::new(relative), relative, kind);
+
+        assert!(takes("Trip/IMG_1.JPG", MediaKind::Image));
+        assert!(takes("2024/05/clip.mov", MediaKind::Video));
+        assert!(!takes("2023/05/clip.mov", MediaKind::Video));
+        assert!(!takes("Trip/IMG_1_edit.jpg", MediaKind::Image));
+        assert!(!takes("Trip/IMG_1.png", MediaKind::Image));
+        assert!(filter.excludes_folder("Trip/Exports"));
+        assert!(!filter.excludes_folder("Trip/Exported"));
+        assert!(!exclude[0].matches("Exports", false));
+
+        let only_videos = MediaFilter {
+            only: Some(MediaKind::Video),
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert!(!only_videos.takes_file(Path::new("a.jpg"), "a.jpg", MediaKind::Image));
+        assert!(!only_videos.descends_into(1));
+    }
+
+    #[test]
+    fn inner_ignore_files_can_re_include() {
+        let root = crate::utils::create_temp_dir("ignore");
+        let trip = root.join("Trip");
+        fs::create_dir_all(&trip).unwrap();
+        fs::write(root.join(IGNORE_FILE), "Exports/\n*.png\n").unwrap();
+        fs::write(trip.join(IGNORE_FILE), "!keep.png\n").unwrap();
+
+        let mut ignores = IgnoreStack::default();
+        assert!(ignores.enter(&root).unwrap());
+        assert!(ignores.ignores(&root.join("Exports"), true));
+        assert!(!ignores.ignores(&root.join("Exports"), false));
+        assert!(ignores.ignores(&trip.join("keep.png"), false));
+        assert!(ignores.enter(&trip).unwrap());
+        assert!(!ignores.ignores(&trip.join("keep.png"), false));
+        assert!(ignores.ignores(&trip.join("other.png"), false));
+        ignores.pop();
+        assert!(ignores.ignores(&trip.join("keep.png"), false));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sizes_and_date_bounds_parse() {
+        assert_eq!(parse_size("500k"), Ok(500_000));
+        assert_eq!(parse_size("1.5MiB"), Ok(1_572_864));
+        assert_eq!(parse_size("2048"), Ok(2048));
+        assert!(parse_size("12 parsecs").is_err());
+
+        assert_eq!(
+            parse_since("2024-05-01").unwrap().to_string(),
+            "2024-05-01 00:00:00"
+        );
+        assert_eq!(
+            parse_until("2024-05-01")
+                .unwrap()
+                .format("%H:%M:%S")
+                .to_string(),
+            "23:59:59"
+        );
+        assert_eq!(
+            parse_until("2024-05-01T18:30").unwrap().to_string(),
+            "2024-05-01 18:30:00"
+        );
+        assert!(parse_since("May 1st").is_err());
+    }
+}
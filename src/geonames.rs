@@ -0,0 +1,266 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// First line of the compact index written by `nf geonames-index`.
+const COMPACT_HEADER: &str = "#nameforge-geonames 1";
+/// GeoNames feature class for cities, towns and villages.
+const POPULATED_PLACE_CLASS: &str = "P";
+const EARTH_RADIUS_KM: f64 = 6371.0;
+/// Places further away than this are not considered a match.
+const MAX_DISTANCE_KM: f64 = 100.0;
+
+#[derive(Debug)]
+struct Place {
+    name: Box<str>,
+    lat: f32,
+    lon: f32,
+    point: [f32; 3],
+}
+
+impl Place {
+    fn new(name: &str, lat: f64, lon: f64) -> Self {
+        Place {
+            name: name.into(),
+            lat: lat as f32,
+            lon: lon as f32,
+            point: to_unit_vector(lat, lon),
+        }
+    }
+}
+
+/// Nearest populated place lookup over a GeoNames dump, using a k-d tree over
+/// points on the unit sphere so that distances behave across the antimeridian.
+#[derive(Debug)]
+pub struct GeoNamesIndex {
+    /// Places ordered as an implicit k-d tree: the median of every range is its node.
+    places: Vec<Place>,
+}
+
+fn to_unit_vector(lat: f64, lon: f64) -> [f32; 3] {
+    let (lat, lon) = (lat.to_radians(), lon.to_radians());
+    [
+        (lat.cos() * lon.cos()) as f32,
+        (lat.cos() * lon.sin()) as f32,
+        lat.sin() as f32,
+    ]
+}
+
+fn squared_distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Converts a surface distance into the squared chord length between unit vectors.
+fn squared_chord_for_km(km: f64) -> f32 {
+    let angle = km / EARTH_RADIUS_KM;
+    let chord = 2.0 * (angle / 2.0).sin();
+    (chord * chord) as f32
+}
+
+impl GeoNamesIndex {
+    /// Loads either a GeoNames `cities*.txt`/`allCountries.txt` dump or a
+    /// compact index made by [`GeoNamesIndex::write_compact`].
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|error| {
+            format!("Could not open GeoNames file {}: {}", path.display(), error)
+        })?;
+        let mut lines = BufReader::new(file).lines();
+
+        let first_line = match lines.next() {
+            Some(line) => line.map_err(|error| error.to_string())?,
+            None => return Err(format!("GeoNames file {} is empty", path.display())),
+        };
+
+        let is_compact = first_line.trim() == COMPACT_HEADER;
+        let parse_line = if is_compact {
+            parse_compact_line
+        } else {
+            parse_dump_line
+        };
+
+        let mut places = Vec::new();
+        if !is_compact {
+            places.extend(parse_dump_line(&first_line));
+        }
+        for line in lines {
+            let line = line.map_err(|error| format!("{}: {}", path.display(), error))?;
+            places.extend(parse_line(&line));
+        }
+
+        if places.is_empty() {
+            return Err(format!(
+                "No populated places found in {} (expected a GeoNames dump or a nameforge index)",
+                path.display()
+            ));
+        }
+
+        Ok(Self::from_places(places))
+    }
+
+    fn from_places(mut places: Vec<Place>) -> Self {
+        build_kd_tree(&mut places, 0);
+        GeoNamesIndex { places }
+    }
+
+    pub fn len(&self) -> usize {
+        self.places.len()
+    }
+
+    /// Writes the places as a compact `lat<TAB>lon<TAB>name` index.
+    pub fn write_compact(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|error| format!("Could not create {}: {}", path.display(), error))?;
+        let mut writer = BufWriter::new(file);
+
+        writeln!(writer, "{}", COMPACT_HEADER).map_err(|error| error.to_string())?;
+        for place in &self.places {
+            writeln!(writer, "{:.5}\t{:.5}\t{}", place.lat, place.lon, place.name)
+                .map_err(|error| error.to_string())?;
+        }
+        writer.flush().map_err(|error| error.to_string())
+    }
+
+    /// Returns the name of the nearest populated place within range.
+    pub fn nearest(&self, lat: f64, lon: f64) -> Option<&str> {
+        let target = to_unit_vector(lat, lon);
+        let mut best = None;
+        let mut best_distance = squared_chord_for_km(MAX_DISTANCE_KM);
+
+        search_kd_tree(&self.places, 0, 0, &target, &mut best, &mut best_distance);
+
+        best.map(|index| self.places[index].name.as_ref())
+    }
+}
+
+/// Parses a line of the GeoNames `geoname` table, keeping populated places only.
+fn parse_dump_line(line: &str) -> Option<Place> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    if columns.get(6).copied() != Some(POPULATED_PLACE_CLASS) {
+        return None;
+    }
+
+    let name = columns
+        .get(2)
+        .filter(|ascii_name| !ascii_name.is_empty())
+        .or_else(|| columns.get(1))?;
+    let lat = columns.get(4)?.parse().ok()?;
+    let lon = columns.get(5)?.parse().ok()?;
+    Some(Place::new(name, lat, lon))
+}
+
+fn parse_compact_line(line: &str) -> Option<Place> {
+    let mut columns = line.splitn(3, '\t');
+    let lat = columns.next()?.parse().ok()?;
+    let lon = columns.next()?.parse().ok()?;
+    let name = columns.next().filter(|name| !name.is_empty())?;
+    Some(Place::new(name, lat, lon))
+}
+
+/// Reorders `places` in place so that every range has its median as the node.
+fn build_kd_tree(places: &mut [Place], depth: usize) {
+    if places.len() <= 1 {
+        return;
+    }
+
+    let axis = depth % 3;
+    let mid = places.len() / 2;
+    places.select_nth_unstable_by(mid, |a, b| a.point[axis].total_cmp(&b.point[axis]));
+
+    let (left, right) = places.split_at_mut(mid);
+    build_kd_tree(left, depth + 1);
+    build_kd_tree(&mut right[1..], depth + 1);
+}
+
+fn search_kd_tree(
+    places: &[Place],
+    offset: usize,
+    depth: usize,
+    target: &[f32; 3],
+    best: &mut Option<usize>,
+    best_distance: &mut f32,
+) {
+    if places.is_empty() {
+        return;
+    }
+
+    let mid = places.len() / 2;
+    let node = &places[mid];
+    let distance = squared_distance(&node.point, target);
+    if distance <= *best_distance {
+        *best_distance = distance;
+        *best = Some(offset + mid);
+    }
+
+    let axis = depth % 3;
+    let delta = target[axis] - node.point[axis];
+    let (near, near_offset, far, far_offset) = if delta < 0.0 {
+        (&places[..mid], offset, &places[mid + 1..], offset + mid + 1)
+    } else {
+        (&places[mid + 1..], offset + mid + 1, &places[..mid], offset)
+    };
+
+    search_kd_tree(near, near_offset, depth + 1, target, best, best_distance);
+    if delta * delta <= *best_distance {
+        search_kd_tree(far, far_offset, depth + 1, target, best, best_distance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_index() -> GeoNamesIndex {
+        GeoNamesIndex::from_places(vec![
+            Place::new("Paris", 48.85341, 2.3488),
+            Place::new("Versailles", 48.80359, 2.13424),
+            Place::new("London", 51.50853, -0.12574),
+            Place::new("Taveuni", -16.85, 179.97),
+            Place::new("Apia", -13.83333, -171.76666),
+            Place::new("Berlin", 52.52437, 13.41053),
+        ])
+    }
+
+    #[test]
+    fn nearest_finds_the_closest_place() {
+        let index = sample_index();
+
+        assert_eq!(index.nearest(48.8584, 2.2945), Some("Paris"));
+        assert_eq!(index.nearest(48.80, 2.12), Some("Versailles"));
+        assert_eq!(index.nearest(51.5, -0.1), Some("London"));
+    }
+
+    #[test]
+    fn nearest_works_across_the_antimeridian_and_has_a_range() {
+        let index = sample_index();
+
+        assert_eq!(index.nearest(-16.85, -179.98), Some("Taveuni"));
+        assert_eq!(index.nearest(0.0, -30.0), None);
+    }
+
+    #[test]
+    fn dump_lines_keep_populated_places_only() {
+        let city = "2988507\tParis\tParis\tLutece\t48.85341\t2.3488\tP\tPPLC\tFR";
+        let river = "2988500\tSeine\tSeine\t\t49.43\t0.23\tH\tSTM\tFR";
+
+        assert_eq!(
+            parse_dump_line(city).map(|place| place.name),
+            Some("Paris".into())
+        );
+        assert!(parse_dump_line(river).is_none());
+    }
+
+    #[test]
+    fn compact_index_round_trips() {
+        let dir = crate::utils::create_temp_dir("geonames");
+        let path = dir.join("index.txt");
+        sample_index().write_compact(&path).unwrap();
+
+        let loaded = GeoNamesIndex::load(&path).unwrap();
+
+        assert_eq!(loaded.len(), 6);
+        assert_eq!(loaded.nearest(52.5, 13.4), Some("Berlin"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,17 @@
 use crate::cache::GPSCache;
-use colored::*;
+use crate::events::{Event, Observer};
+use crate::geonames::GeoNamesIndex;
+use crate::pool::Semaphore;
 use reqwest::blocking::Client;
 use serde::Deserialize;
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Nominatim's usage policy allows one request per second.
+const NOMINATIM_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Deserialize)]
 struct NominatimResponse {
@@ -37,14 +47,6 @@ fn extract_place_name(display_name: &str) -> String {
 
 /// Helper function to perform API request and extract place
 fn fetch_place_from_api(lat: f64, lon: f64) -> Option<String> {
-    println!(
-        "{}  {}({}, {})...",
-        "🌍".bright_blue(),
-        "Resolving GPS coordinates ".bright_blue(),
-        lat.to_string().bright_white(),
-        lon.to_string().bright_white()
-    );
-
     let client = Client::new();
     let url = build_nominatim_url(lat, lon);
 
@@ -57,18 +59,100 @@ fn fetch_place_from_api(lat: f64, lon: f64) -> Option<String> {
         .map(|nominatim| extract_place_name(&nominatim.display_name))
 }
 
-pub fn gps_to_place(lat: f64, lon: f64, cache: &mut GPSCache) -> (Option<String>, bool) {
-    let key = to_cache_key(lat, lon);
+/// Reverse geocoding backend used behind the GPS cache.
+pub enum Geocoder {
+    /// When the last request was sent, shared by every worker.
+    Nominatim(Mutex<Option<Instant>>),
+    Offline(GeoNamesIndex),
+}
+
+/// Waits until `interval` has passed since the last request, then records a
+/// new one. The lock is held while waiting, so callers take turns.
+fn wait_for_turn(last_request: &Mutex<Option<Instant>>, interval: Duration) {
+    let mut last_request = last_request.lock().unwrap();
+    if let Some(wait) = last_request.and_then(|sent| interval.checked_sub(sent.elapsed())) {
+        thread::sleep(wait);
+    }
+    *last_request = Some(Instant::now());
+}
+
+impl Geocoder {
+    pub fn nominatim() -> Self {
+        Geocoder::Nominatim(Mutex::new(None))
+    }
+
+    /// Cache key for a spot. Offline names come from another data set than
+    /// Nominatim's, so they are kept apart; Nominatim keeps the plain key that
+    /// caches were written with before there was a second backend.
+    fn cache_key(&self, lat: f64, lon: f64) -> String {
+        match self {
+            Geocoder::Nominatim(_) => to_cache_key(lat, lon),
+            Geocoder::Offline(_) => format!("offline:{}", to_cache_key(lat, lon)),
+        }
+    }
+
+    fn lookup(&self, lat: f64, lon: f64, observer: &dyn Observer) -> Option<String> {
+        match self {
+            Geocoder::Nominatim(last_request) => {
+                wait_for_turn(last_request, NOMINATIM_INTERVAL);
+                observer.on_event(&Event::GeocoderLookup { lat, lon });
+                fetch_place_from_api(lat, lon)
+            }
+            Geocoder::Offline(index) => index.nearest(lat, lon).map(|name| name.replace(' ', "_")),
+        }
+    }
+}
+
+/// Resolves a place name through the cache, holding a `limit` permit only
+/// while the geocoder itself is queried.
+pub fn gps_to_place(
+    lat: f64,
+    lon: f64,
+    cache: &Mutex<GPSCache>,
+    geocoder: &Geocoder,
+    limit: &Semaphore,
+    observer: &dyn Observer,
+) -> (Option<String>, bool) {
+    let key = geocoder.cache_key(lat, lon);
 
     // Check cache first
-    if let Some(place) = cache.get(&key) {
+    if let Some(place) = cache.lock().unwrap().get(&key) {
+        return (Some(place.clone()), false);
+    }
+
+    let _permit = limit.acquire();
+    // Another worker may have resolved the same spot while we waited.
+    if let Some(place) = cache.lock().unwrap().get(&key) {
         return (Some(place.clone()), false);
     }
 
-    let Some(place) = fetch_place_from_api(lat, lon) else {
+    let place = geocoder.lookup(lat, lon, observer);
+    let mut cache = cache.lock().unwrap();
+    cache.count_lookup();
+    let Some(place) = place else {
         return (None, false);
     };
 
     cache.insert(key, place.clone());
     (Some(place), true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_spaced_by_the_interval() {
+        let last_request = Mutex::new(None);
+        let interval = Duration::from_millis(40);
+        let started = Instant::now();
+
+        thread::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|| wait_for_turn(&last_request, interval));
+            }
+        });
+
+        assert!(started.elapsed() >= interval * 2);
+    }
+}
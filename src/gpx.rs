@@ -0,0 +1,138 @@
+use crate::xmp::{element_body, simple_property};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::{fs, path::Path};
+
+/// Track points from one or more GPX files, ordered by time.
+#[derive(Debug, Default)]
+pub struct GpxTrack {
+    points: Vec<TrackPoint>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TrackPoint {
+    time: DateTime<Utc>,
+    lat: f64,
+    lon: f64,
+}
+
+impl GpxTrack {
+    /// Loads and merges the track points of every file in `paths`.
+    pub fn load(paths: &[impl AsRef<Path>]) -> Result<Self, String> {
+        let mut points = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let text = fs::read_to_string(path).map_err(|error| {
+                format!("Could not read GPX file {}: {}", path.display(), error)
+            })?;
+            let found = parse_track_points(&text);
+            if found.is_empty() {
+                return Err(format!("No timed track points in {}", path.display()));
+            }
+            points.extend(found);
+        }
+
+        points.sort_by_key(|point| point.time);
+        Ok(GpxTrack { points })
+    }
+
+    pub fn point_count(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Position at `time`, interpolated between the track points around it.
+    /// Nothing is returned when those points are more than `max_gap_seconds`
+    /// apart, or when `time` is further than that outside the track.
+    pub fn position_at(&self, time: NaiveDateTime, max_gap_seconds: u64) -> Option<(f64, f64)> {
+        let time = time.and_utc();
+        let seconds =
+            |from: DateTime<Utc>, to: DateTime<Utc>| (to - from).num_milliseconds() as f64 / 1000.0;
+        let max_gap = max_gap_seconds as f64;
+        let after = self.points.partition_point(|point| point.time <= time);
+
+        match (
+            after.checked_sub(1).map(|index| self.points[index]),
+            self.points.get(after),
+        ) {
+            (Some(before), _) if before.time == time => Some((before.lat, before.lon)),
+            (Some(before), Some(next)) => {
+                let span = seconds(before.time, next.time);
+                if span > max_gap {
+                    return None;
+                }
+                let fraction = seconds(before.time, time) / span;
+                Some((
+                    before.lat + (next.lat - before.lat) * fraction,
+                    before.lon + (next.lon - before.lon) * fraction,
+                ))
+            }
+            (Some(last), None) => {
+                (seconds(last.time, time) <= max_gap).then_some((last.lat, last.lon))
+            }
+            (None, Some(first)) => {
+                (seconds(time, first.time) <= max_gap).then_some((first.lat, first.lon))
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Reads `<trkpt lat=".." lon=".."><time>..</time></trkpt>` elements; points
+/// without a time are of no use for geotagging and are skipped.
+fn parse_track_points(text: &str) -> Vec<TrackPoint> {
+    text.split("<trkpt")
+        .skip(1)
+        .filter_map(|rest| {
+            let point = &rest[..rest.find("</trkpt>").unwrap_or(rest.len())];
+            let tag_end = point.find('>')?;
+            let attributes = format!(" {}", &point[..tag_end]);
+            let lat = simple_property(&attributes, "lat")?.parse().ok()?;
+            let lon = simple_property(&attributes, "lon")?.parse().ok()?;
+            let time = DateTime::parse_from_rfc3339(element_body(point, "time")?.trim()).ok()?;
+
+            Some(TrackPoint {
+                time: time.with_timezone(&Utc),
+                lat,
+                lon,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACK: &str = r#"<?xml version="1.0"?>
+<gpx version="1.1" creator="phone">
+ <trk><trkseg>
+  <trkpt lat="48.0" lon="2.0"><ele>35</ele><time>2024-05-01T10:00:00Z</time></trkpt>
+  <trkpt lon="3.0" lat="49.0"><time>2024-05-01T12:05:00+02:00</time></trkpt>
+  <trkpt lat="50.0" lon="4.0"><time>2024-05-01T11:00:00Z</time></trkpt>
+  <trkpt lat="51.0" lon="5.0"></trkpt>
+ </trkseg></trk>
+</gpx>"#;
+
+    #[test]
+    fn interpolates_between_points_within_the_gap() {
+        let track = GpxTrack {
+            points: {
+                let mut points = parse_track_points(TRACK);
+                points.sort_by_key(|point| point.time);
+                points
+            },
+        };
+        let at = |time: &str| NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap();
+
+        assert_eq!(track.point_count(), 3);
+        assert_eq!(
+            track.position_at(at("2024-05-01 10:02:30"), 300),
+            Some((48.5, 2.5))
+        );
+        assert_eq!(track.position_at(at("2024-05-01 10:30:00"), 900), None);
+        assert_eq!(
+            track.position_at(at("2024-05-01 11:04:00"), 300),
+            Some((50.0, 4.0))
+        );
+        assert_eq!(track.position_at(at("2024-05-01 11:06:00"), 300), None);
+    }
+}
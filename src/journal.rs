@@ -0,0 +1,670 @@
+use crate::{
+    events::{Event, Observer, SkipReason},
+    Error,
+};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+const JOURNAL_EXTENSION: &str = "jsonl";
+const UNDONE_SUFFIX: &str = ".undone";
+const TRASH_EXTENSION: &str = "trash";
+
+/// Whether a journaled file was moved, copied or newly written.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalOp {
+    /// `from` was moved to `to`; undo moves it back.
+    #[default]
+    Move,
+    /// `from` was copied to `to` and left in place; undo removes the copy.
+    Copy,
+    /// `to` did not exist before the run and was written by it (an XMP
+    /// sidecar, for example); undo removes it.
+    Create,
+    /// `from` was deleted by moving it to `to`, in the run's trash folder next
+    /// to the journal; undo moves it back.
+    Trash,
+    /// `to` was changed in place (an existing XMP sidecar, for example) after
+    /// its earlier contents were kept at `from`, in the run's trash folder;
+    /// undo puts them back.
+    Replace,
+}
+
+/// One completed move or copy, recorded right after it happened.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    #[serde(default)]
+    pub op: JournalOp,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Folders that did not exist before this move, outermost first.
+    #[serde(default)]
+    pub created_dirs: Vec<PathBuf>,
+    pub size: u64,
+    pub modified_ns: Option<u64>,
+}
+
+/// Fingerprint used to tell whether a destination changed after the run.
+fn file_fingerprint(path: &Path) -> Option<(u64, Option<u64>)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified_ns = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .and_then(|duration| u64::try_from(duration.as_nanos()).ok());
+    Some((metadata.len(), modified_ns))
+}
+
+/// Append-only log of the renames made by a single run.
+///
+/// The file is only created once the first entry is recorded, so dry runs and
+/// runs that change nothing leave no journal behind.
+pub struct Journal<'a> {
+    dir: Option<PathBuf>,
+    path: Option<PathBuf>,
+    writer: Option<BufWriter<File>>,
+    failed: bool,
+    trashed: usize,
+    observer: &'a dyn Observer,
+}
+
+impl<'a> Journal<'a> {
+    pub fn new(observer: &'a dyn Observer) -> Self {
+        Self::in_dir(get_journal_dir(), observer)
+    }
+
+    fn in_dir(dir: Option<PathBuf>, observer: &'a dyn Observer) -> Self {
+        Journal {
+            dir,
+            path: None,
+            writer: None,
+            failed: false,
+            trashed: 0,
+            observer,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn warn(&self, message: &str) {
+        self.observer.on_event(&Event::Warning {
+            path: None,
+            message,
+        });
+    }
+
+    fn open(&mut self) -> Option<&mut BufWriter<File>> {
+        if self.writer.is_none() && !self.failed {
+            match self.dir.as_deref().map(create_journal_file) {
+                Some(Ok((path, file))) => {
+                    self.path = Some(path);
+                    self.writer = Some(BufWriter::new(file));
+                }
+                Some(Err(error)) => {
+                    self.failed = true;
+                    self.warn(&format!(
+                        "Could not create rename journal, this run cannot be undone: {}",
+                        error
+                    ));
+                }
+                None => {
+                    self.failed = true;
+                    self.warn("HOME is not set, this run will not be journaled");
+                }
+            }
+        }
+
+        self.writer.as_mut()
+    }
+
+    /// Records a move that has already been carried out.
+    pub fn record(&mut self, from: &Path, to: &Path, created_dirs: Vec<PathBuf>) {
+        self.append(JournalOp::Move, from, to, created_dirs);
+    }
+
+    /// Records a copy that has already been made and verified.
+    pub fn record_copy(&mut self, from: &Path, to: &Path, created_dirs: Vec<PathBuf>) {
+        self.append(JournalOp::Copy, from, to, created_dirs);
+    }
+
+    /// Records a file the run created from scratch.
+    pub fn record_created(&mut self, path: &Path) {
+        self.append(JournalOp::Create, path, path, Vec::new());
+    }
+
+    /// Deletes a file by moving it to this run's trash folder, so undo can
+    /// bring it back, and returns where it went. Without a journal to record
+    /// that in, the file is kept.
+    pub fn trash(&mut self, path: &Path) -> Result<PathBuf, Error> {
+        let (trashed, created_dirs) = self.trash_slot(path, "deleted")?;
+        move_file(path, &trashed).map_err(|error| Error::io(path, error))?;
+        self.append(JournalOp::Trash, path, &trashed, created_dirs);
+        Ok(trashed)
+    }
+
+    /// Changes an existing file with `change`, keeping its earlier contents in
+    /// the run's trash folder so undo can put them back. Without a journal to
+    /// record that in, the file is left unchanged.
+    pub fn replace(
+        &mut self,
+        path: &Path,
+        change: impl FnOnce(&Path) -> std::io::Result<()>,
+    ) -> Result<(), Error> {
+        let (backup, created_dirs) = self.trash_slot(path, "changed")?;
+        copy_with_modified(path, &backup).map_err(|error| Error::io(path, error))?;
+        if let Err(error) = change(path) {
+            let _ = fs::remove_file(&backup);
+            return Err(Error::io(path, error));
+        }
+        self.append(JournalOp::Replace, &backup, path, created_dirs);
+        Ok(())
+    }
+
+    /// A free path in the trash folder for `path`, and the folders that still
+    /// have to be created for it.
+    fn trash_slot(&mut self, path: &Path, verb: &str) -> Result<(PathBuf, Vec<PathBuf>), Error> {
+        self.open();
+        let Some(trash_dir) = self
+            .path
+            .as_deref()
+            .map(|journal| journal.with_extension(TRASH_EXTENSION))
+        else {
+            return Err(Error::Journal(format!(
+                "{}: not {}, the run has no journal to undo it from",
+                path.display(),
+                verb
+            )));
+        };
+
+        let created_dirs = missing_ancestors(&trash_dir);
+        fs::create_dir_all(&trash_dir).map_err(|error| Error::io(&trash_dir, error))?;
+        self.trashed += 1;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        Ok((
+            trash_dir.join(format!("{}_{}", self.trashed, name)),
+            created_dirs,
+        ))
+    }
+
+    fn append(&mut self, op: JournalOp, from: &Path, to: &Path, created_dirs: Vec<PathBuf>) {
+        let Some((size, modified_ns)) = file_fingerprint(to) else {
+            return;
+        };
+        let entry = JournalEntry {
+            op,
+            from: absolute_path(from),
+            to: absolute_path(to),
+            created_dirs: created_dirs.iter().map(|dir| absolute_path(dir)).collect(),
+            size,
+            modified_ns,
+        };
+
+        let Some(writer) = self.open() else {
+            return;
+        };
+
+        // Flush after every entry so an interrupted run can still be undone.
+        let written = serde_json::to_writer(&mut *writer, &entry)
+            .map_err(|error| error.to_string())
+            .and_then(|_| writeln!(writer).map_err(|error| error.to_string()))
+            .and_then(|_| writer.flush().map_err(|error| error.to_string()));
+
+        if let Err(error) = written {
+            self.warn(&format!("Failed to write rename journal entry: {}", error));
+        }
+    }
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn get_journal_dir() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".nameforge_journals"))
+}
+
+fn create_journal_file(dir: &Path) -> Result<(PathBuf, File), String> {
+    fs::create_dir_all(dir).map_err(|error| format!("{}: {}", dir.display(), error))?;
+
+    let stamp = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let mut counter = 0;
+    loop {
+        let name = if counter == 0 {
+            format!("{}.{}", stamp, JOURNAL_EXTENSION)
+        } else {
+            format!("{}_{}.{}", stamp, counter, JOURNAL_EXTENSION)
+        };
+        let path = dir.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => counter += 1,
+            Err(error) => return Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+}
+
+/// Folders between `dir` and its closest existing ancestor, outermost first.
+pub fn missing_ancestors(dir: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+fn is_undone(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.ends_with(UNDONE_SUFFIX))
+        .unwrap_or(false)
+}
+
+fn list_journal_files(dir: &Path) -> Vec<PathBuf> {
+    let mut journals: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.extension().and_then(|ext| ext.to_str()) == Some(JOURNAL_EXTENSION)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    journals.sort();
+    journals
+}
+
+fn read_journal(path: &Path) -> Result<Vec<JournalEntry>, String> {
+    let file = File::open(path)
+        .map_err(|error| format!("Could not open {}: {}", path.display(), error))?;
+
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map(|l| !l.trim().is_empty()).unwrap_or(true))
+        .map(|(index, line)| {
+            line.map_err(|error| error.to_string())
+                .and_then(|line| serde_json::from_str(&line).map_err(|error| error.to_string()))
+                .map_err(|error| format!("{} line {}: {}", path.display(), index + 1, error))
+        })
+        .collect()
+}
+
+/// A rename journal kept from a previous run.
+#[derive(Debug, Serialize)]
+pub struct RunJournal {
+    pub path: PathBuf,
+    /// Recorded renames, or `None` if the journal could not be read.
+    pub entries: Option<usize>,
+    pub undone: bool,
+}
+
+/// The rename journals of previous runs, oldest first.
+pub fn list_journals() -> Result<Vec<RunJournal>, Error> {
+    let dir = get_journal_dir().ok_or_else(|| Error::Journal("HOME is not set".into()))?;
+
+    Ok(list_journal_files(&dir)
+        .into_iter()
+        .map(|path| RunJournal {
+            entries: read_journal(&path)
+                .map(|entries| count_changes(&entries))
+                .ok(),
+            undone: is_undone(&path),
+            path,
+        })
+        .collect())
+}
+
+fn latest_active_journal(dir: &Path) -> Option<PathBuf> {
+    list_journal_files(dir)
+        .into_iter()
+        .rev()
+        .find(|path| !is_undone(path))
+}
+
+/// How many journal entries an undo restored or had to skip.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct UndoSummary {
+    pub restored: usize,
+    pub skipped: usize,
+    pub removed_dirs: usize,
+}
+
+/// Replays a journal in reverse. Defaults to the newest journal that has not
+/// been undone yet.
+pub fn undo_journal(
+    journal_path: Option<&Path>,
+    dry_run: bool,
+    observer: &dyn Observer,
+) -> Result<UndoSummary, Error> {
+    let journal_path = match journal_path {
+        Some(path) => path.to_path_buf(),
+        None => get_journal_dir()
+            .and_then(|dir| latest_active_journal(&dir))
+            .ok_or_else(|| Error::Journal("No rename journal found to undo".into()))?,
+    };
+
+    let entries = read_journal(&journal_path).map_err(Error::Journal)?;
+    observer.on_event(&Event::UndoStarted {
+        journal: &journal_path,
+        entries: count_changes(&entries),
+    });
+
+    let summary = undo_entries(&entries, dry_run, observer);
+
+    if !dry_run && summary.skipped == 0 {
+        if let Err(error) = mark_undone(&journal_path) {
+            observer.on_event(&Event::Warning {
+                path: Some(&journal_path),
+                message: &format!("could not mark journal as undone: {}", error),
+            });
+        }
+    }
+    Ok(summary)
+}
+
+fn mark_undone(journal_path: &Path) -> std::io::Result<()> {
+    let Some(stem) = journal_path.file_stem().and_then(|stem| stem.to_str()) else {
+        return Ok(());
+    };
+    if stem.ends_with(UNDONE_SUFFIX) {
+        return Ok(());
+    }
+
+    let undone_path =
+        journal_path.with_file_name(format!("{}{}.{}", stem, UNDONE_SUFFIX, JOURNAL_EXTENSION));
+    fs::rename(journal_path, undone_path)
+}
+
+fn check_undo_entry(entry: &JournalEntry) -> Result<(), SkipReason> {
+    let Some(fingerprint) = file_fingerprint(&entry.to) else {
+        return Err(SkipReason::RenamedFileMissing);
+    };
+    if fingerprint != (entry.size, entry.modified_ns) {
+        return Err(SkipReason::RenamedFileChanged);
+    }
+    match entry.op {
+        JournalOp::Move | JournalOp::Trash if entry.from.exists() => Err(SkipReason::OriginalTaken),
+        JournalOp::Copy if !entry.from.exists() => Err(SkipReason::LastCopy),
+        JournalOp::Replace if !entry.from.exists() => Err(SkipReason::BackupMissing),
+        _ => Ok(()),
+    }
+}
+
+/// Copies a file along with its modification time, so that once it is moved
+/// back the journal fingerprints of earlier entries still match.
+fn copy_with_modified(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::copy(from, to)?;
+    let modified = fs::metadata(from)?.modified()?;
+    File::options().write(true).open(to)?.set_modified(modified)
+}
+
+/// Moves a file, copying and deleting when it crosses filesystems (for
+/// example back to a camera card after an import, or into the trash).
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == std::io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+fn undo_entries(entries: &[JournalEntry], dry_run: bool, observer: &dyn Observer) -> UndoSummary {
+    let mut summary = UndoSummary::default();
+    let superseded = superseded_copies(entries);
+
+    for (_, entry) in entries
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(index, _)| !superseded.contains(index))
+    {
+        if let Err(reason) = check_undo_entry(entry) {
+            observer.on_event(&Event::Skipped {
+                path: &entry.to,
+                reason,
+            });
+            summary.skipped += 1;
+            continue;
+        }
+
+        let restored = if dry_run {
+            Ok(())
+        } else {
+            match entry.op {
+                JournalOp::Move | JournalOp::Trash => move_file(&entry.to, &entry.from),
+                JournalOp::Replace => move_file(&entry.from, &entry.to),
+                JournalOp::Copy | JournalOp::Create => fs::remove_file(&entry.to),
+            }
+        };
+
+        match restored {
+            Ok(()) => {
+                observer.on_event(&Event::Restored {
+                    op: entry.op,
+                    from: &entry.to,
+                    to: &entry.from,
+                    dry_run,
+                });
+                summary.restored += 1;
+                if !dry_run {
+                    summary.removed_dirs += remove_created_dirs(&entry.created_dirs);
+                }
+            }
+            Err(error) => {
+                observer.on_event(&Event::Failed {
+                    path: Some(&entry.to),
+                    error: &Error::io(&entry.to, error),
+                });
+                summary.skipped += 1;
+            }
+        }
+    }
+
+    summary
+}
+
+/// An import that deleted its source journals the copy first and a move once
+/// the source is gone. Undoing the move covers the copy as well, so these
+/// copies are left out of undo and of entry counts.
+fn superseded_copies(entries: &[JournalEntry]) -> HashSet<usize> {
+    let moved: HashSet<(&Path, &Path)> = entries
+        .iter()
+        .filter(|entry| entry.op == JournalOp::Move)
+        .map(|entry| (entry.from.as_path(), entry.to.as_path()))
+        .collect();
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| {
+            entry.op == JournalOp::Copy
+                && moved.contains(&(entry.from.as_path(), entry.to.as_path()))
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+fn count_changes(entries: &[JournalEntry]) -> usize {
+    entries.len() - superseded_copies(entries).len()
+}
+
+/// Removes folders created by the run, innermost first, as long as they are empty.
+fn remove_created_dirs(created_dirs: &[PathBuf]) -> usize {
+    created_dirs
+        .iter()
+        .rev()
+        .take_while(|dir| is_empty_dir(dir))
+        .filter(|dir| fs::remove_dir(dir).is_ok())
+        .count()
+}
+
+fn is_empty_dir(dir: &Path) -> bool {
+    fs::read_dir(dir)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    #[test]
+    fn undo_restores_files_and_removes_created_folders() {
+        let temp_dir = create_temp_dir("journal");
+        let original = temp_dir.join("IMG_0001.jpg");
+        fs::write(&original, b"photo").unwrap();
+
+        let date_folder = temp_dir.join("2024-05-01");
+        let renamed = date_folder.join("2024-05-01_beach.jpg");
+        let created_dirs = missing_ancestors(&date_folder);
+        fs::create_dir_all(&date_folder).unwrap();
+        fs::rename(&original, &renamed).unwrap();
+
+        let mut journal = Journal::in_dir(Some(temp_dir.join("journals")), &());
+        journal.record(&original, &renamed, created_dirs);
+        let entries = read_journal(journal.path().unwrap()).unwrap();
+
+        let summary = undo_entries(&entries, false, &());
+
+        assert_eq!(
+            summary,
+            UndoSummary {
+                restored: 1,
+                skipped: 0,
+                removed_dirs: 1
+            }
+        );
+        assert!(original.exists());
+        assert!(!date_folder.exists());
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn undo_removes_copies_but_keeps_the_original() {
+        let temp_dir = create_temp_dir("journal");
+        let original = temp_dir.join("DSC_0001.jpg");
+        let copy = temp_dir.join("library").join("2024-05-01_photo.jpg");
+        fs::write(&original, b"photo").unwrap();
+        let created_dirs = missing_ancestors(copy.parent().unwrap());
+        fs::create_dir_all(copy.parent().unwrap()).unwrap();
+        fs::copy(&original, &copy).unwrap();
+
+        let mut journal = Journal::in_dir(Some(temp_dir.join("journals")), &());
+        journal.record_copy(&original, &copy, created_dirs);
+        let entries = read_journal(journal.path().unwrap()).unwrap();
+
+        let summary = undo_entries(&entries, false, &());
+
+        assert_eq!(summary.restored, 1);
+        assert!(original.exists());
+        assert!(!copy.parent().unwrap().exists());
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn undo_moves_back_imports_that_deleted_their_source() {
+        let temp_dir = create_temp_dir("journal");
+        let original = temp_dir.join("card").join("DSC_0002.jpg");
+        let copy = temp_dir.join("2024-05-01_photo.jpg");
+        fs::create_dir_all(original.parent().unwrap()).unwrap();
+        fs::write(&copy, b"photo").unwrap();
+
+        let mut journal = Journal::in_dir(Some(temp_dir.join("journals")), &());
+        journal.record_copy(&original, &copy, Vec::new());
+        journal.record(&original, &copy, Vec::new());
+        let entries = read_journal(journal.path().unwrap()).unwrap();
+
+        let summary = undo_entries(&entries, false, &());
+
+        assert_eq!((summary.restored, summary.skipped), (1, 0));
+        assert_eq!(fs::read(&original).unwrap(), b"photo");
+        assert!(!copy.exists());
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn undo_brings_back_trashed_files() {
+        let temp_dir = create_temp_dir("journal");
+        let duplicate = temp_dir.join("IMG_0003.jpg");
+        fs::write(&duplicate, b"photo").unwrap();
+
+        let mut journal = Journal::in_dir(Some(temp_dir.join("journals")), &());
+        let trashed = journal.trash(&duplicate).unwrap();
+        assert!(!duplicate.exists());
+        assert!(trashed.exists());
+        let entries = read_journal(journal.path().unwrap()).unwrap();
+
+        let summary = undo_entries(&entries, false, &());
+
+        assert_eq!(summary.restored, 1);
+        assert_eq!(fs::read(&duplicate).unwrap(), b"photo");
+        assert!(!trashed.parent().unwrap().exists());
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn undo_puts_back_the_contents_of_changed_files() {
+        let temp_dir = create_temp_dir("journal");
+        let sidecar = temp_dir.join("IMG_0004.xmp");
+        fs::write(&sidecar, b"by hand").unwrap();
+
+        let mut journal = Journal::in_dir(Some(temp_dir.join("journals")), &());
+        journal
+            .replace(&sidecar, |path| fs::write(path, b"by hand, plus keywords"))
+            .unwrap();
+        let entries = read_journal(journal.path().unwrap()).unwrap();
+
+        let summary = undo_entries(&entries, false, &());
+
+        assert_eq!(summary.restored, 1);
+        assert_eq!(fs::read(&sidecar).unwrap(), b"by hand");
+        assert_eq!(summary.removed_dirs, 1);
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn undo_skips_destinations_that_changed() {
+        let temp_dir = create_temp_dir("journal");
+        let original = temp_dir.join("IMG_0002.jpg");
+        let renamed = temp_dir.join("2024-05-01_photo.jpg");
+        fs::write(&renamed, b"photo").unwrap();
+
+        let mut journal = Journal::in_dir(Some(temp_dir.join("journals")), &());
+        journal.record(&original, &renamed, Vec::new());
+        let entries = read_journal(journal.path().unwrap()).unwrap();
+        fs::write(&renamed, b"edited photo").unwrap();
+
+        let summary = undo_entries(&entries, false, &());
+
+        assert_eq!(summary.skipped, 1);
+        assert!(renamed.exists());
+        assert!(!original.exists());
+
+        fs::remove_dir_all(temp_dir).unwrap();
+    }
+}
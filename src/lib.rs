@@ -1,23 +1,95 @@
 mod ai;
 mod cache;
+mod clock;
+mod config;
+mod duplicates;
+mod error;
+mod events;
 mod exif;
+mod filter;
+mod geonames;
 mod gps;
+mod gpx;
+mod journal;
+mod pairs;
+mod places;
+mod plan;
+mod pool;
+mod report;
+mod sidecar;
+mod similar;
+mod template;
+mod timezone;
+mod union_find;
 mod utils;
-
-use ai::get_ai_content_name;
-use cache::GPSCache;
-use colored::*;
-use exif::{extract_gps_coordinates, get_date_string, get_file_date_string, read_exif_data};
-use gps::gps_to_place;
+mod video;
+mod xmp;
+
+pub use ai::provider::AiProviderKind;
+pub use clock::{parse_duration, CameraIdentity, TimeShift};
+pub use config::{config_arguments, ConfigArguments};
+pub use duplicates::DuplicateMode;
+pub use error::Error;
+pub use events::{Event, Observer, SkipReason, Step};
+pub use exif::DateSource;
+pub use filter::{parse_since, parse_size, parse_until, MediaFilter, PathGlob, IGNORE_FILE};
+pub use journal::{JournalOp, RunJournal, UndoSummary};
+pub use plan::{PlannedAction, PlannedRename, RenamePlanFile, RenameReasons, SidecarMove};
+pub use report::{
+    date_source_label, format_duration, AiCounts, DateCount, Failure, FileCounts, GpsCounts,
+    RunReport, RunStats, StepTiming,
+};
+pub use similar::DEFAULT_SIMILAR_DISTANCE;
+pub use template::{FolderTemplate, NameTemplate};
+pub use timezone::TimeZoneMode;
+pub use xmp::XmpUpdate;
+
+use ai::{
+    get_ai_content_name, get_ai_description,
+    provider::{build_provider, AiProvider, AiProviderConfig},
+    AiBackend,
+};
+use cache::{AiCache, GPSCache};
+use chrono::NaiveDateTime;
+use clock::ClockRules;
+use duplicates::{find_duplicate_groups, same_contents};
+use exif::{
+    extract_gps_coordinates, format_date_time, get_camera_identity, get_camera_name,
+    get_content_identifier, get_date_time, get_file_date_time, read_exif_data, try_parse_exif_date,
+};
+use filter::IgnoreStack;
+use geonames::GeoNamesIndex;
+use gps::{gps_to_place, Geocoder};
+use gpx::GpxTrack;
+use journal::{missing_ancestors, Journal};
+use pairs::find_pairs;
+use places::NamedPlaces;
+use pool::{for_each_ordered, Semaphore};
+use sidecar::{attach_sidecars, is_sidecar, read_sidecar_metadata, Sidecar};
+use similar::{dhash, group_similar};
 use std::{
+    collections::{HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
+    sync::Mutex,
+    time::Instant,
 };
-use utils::{create_date_folder_path, sanitize_filename_fragment, unique_filename};
+use template::NamingContext;
+use timezone::CaptureTime;
+use utils::{
+    candidate_filenames, filename_with_suffix, hash_file_contents, sanitize_filename_fragment,
+    unique_filename, unique_shared_suffix,
+};
+use video::{read_video_metadata, VideoMetadata};
+use xmp::{read_embedded_xmp, read_xmp_file, write_xmp_sidecar, XmpMetadata};
 
+/// How many XMP keywords the `{keywords}` token joins into a name.
+const MAX_KEYWORDS_IN_NAME: usize = 3;
 const SUPPORTED_IMAGE_EXTENSIONS: &[&str] = &[
     "jpg", "jpeg", "png", "tiff", "tif", "bmp", "webp", "heic", "heif", "raw", "cr2", "nef", "arw",
 ];
+/// Camera RAW formats among the supported images.
+const RAW_EXTENSIONS: &[&str] = &["raw", "cr2", "nef", "arw"];
 const SUPPORTED_VIDEO_EXTENSIONS: &[&str] = &[
     "mp4", "mov", "m4v", "avi", "mkv", "mts", "m2ts", "mpg", "mpeg", "3gp", "webm",
 ];
@@ -25,29 +97,145 @@ const GENERIC_CAMERA_PREFIXES: &[&str] = &[
     "img", "dsc", "mov", "mvi", "vid", "pxl", "dji", "imgp", "gopr", "gp", "mvimg",
 ];
 
+/// Reverse geocoding backend selected on the command line.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum GeocoderBackend {
+    /// OpenStreetMap Nominatim web API
+    #[default]
+    Nominatim,
+    /// Local GeoNames dump or index (requires --geonames)
+    Offline,
+}
+
+/// Settings for a run. Start from [`Default`], which matches the CLI's
+/// defaults, and change the fields you need; new fields may be added.
+#[non_exhaustive]
 pub struct ProcessingOptions<'a> {
     pub dry_run: bool,
     pub organize_by_date: bool,
     pub ai_content: bool,
+    pub ai_provider: AiProviderKind,
     pub ai_model: &'a str,
+    pub ai_host: Option<&'a str>,
+    pub ai_api_key: Option<&'a str>,
+    pub ai_command: Option<&'a str>,
     pub ai_max_chars: u32,
     pub ai_case: &'a str,
     pub ai_language: &'a str,
     pub date_only: bool,
+    /// Add milliseconds to full timestamps and `{time}`.
+    pub subseconds: bool,
+    /// Clock that capture times are converted to before naming.
+    pub timezone: TimeZoneMode,
+    /// Correction added to every capture time, unless a clock rule matches.
+    pub time_shift: Option<TimeShift>,
+    /// TOML file of per-camera clock corrections.
+    pub clock_rules: Option<&'a Path>,
+    /// GPX track logs used to place files without GPS.
+    pub gpx: &'a [PathBuf],
+    /// Longest gap between track points, in seconds, that is interpolated over.
+    pub gpx_max_gap: u64,
+    /// Added to capture times (in UTC) before they are looked up in the track.
+    pub gpx_offset: Option<TimeShift>,
     pub max_media: Option<usize>,
+    /// Worker threads for metadata, resizing and lookups.
+    pub jobs: usize,
+    /// Concurrent requests allowed to the AI backend.
+    pub ai_jobs: usize,
+    /// Concurrent reverse geocoding lookups.
+    pub geo_jobs: usize,
+    /// Which files under the input folder are taken.
+    pub filter: MediaFilter<'a>,
+    /// Only files captured at or after this time are renamed.
+    pub since: Option<NaiveDateTime>,
+    /// Only files captured at or before this time are renamed.
+    pub until: Option<NaiveDateTime>,
     pub use_file_date: bool,
     pub prefer_modified: bool,
     pub no_date: bool,
+    pub name_template: Option<&'a NameTemplate>,
+    pub folder_template: Option<&'a FolderTemplate>,
+    pub geocoder: GeocoderBackend,
+    pub geonames_path: Option<&'a Path>,
+    /// TOML file of named places checked before the geocoder.
+    pub places: Option<&'a Path>,
+    pub duplicates: Option<DuplicateMode>,
+    pub duplicates_dir: Option<&'a Path>,
+    /// Give near-identical photos a shared base name with sequence suffixes.
+    pub group_similar: bool,
+    /// Maximum dHash distance for photos to count as similar.
+    pub similar_distance: u32,
+    /// Move each similar group into its own subfolder (implies `group_similar`).
+    pub burst_folder: bool,
+    /// Copy into this library instead of renaming in place.
+    pub import_to: Option<&'a Path>,
+    /// When importing, delete each source file once its copy is verified.
+    pub delete_source_after_verify: bool,
+    /// Write an AI description and keywords to each photo's XMP sidecar.
+    pub write_xmp: bool,
+}
+
+impl Default for ProcessingOptions<'_> {
+    fn default() -> Self {
+        ProcessingOptions {
+            dry_run: false,
+            organize_by_date: false,
+            ai_content: false,
+            ai_provider: AiProviderKind::default(),
+            ai_model: "llava-llama3:latest",
+            ai_host: None,
+            ai_api_key: None,
+            ai_command: None,
+            ai_max_chars: 20,
+            ai_case: "snake_case",
+            ai_language: "English",
+            date_only: true,
+            subseconds: false,
+            timezone: TimeZoneMode::default(),
+            time_shift: None,
+            clock_rules: None,
+            gpx: &[],
+            gpx_max_gap: 600,
+            gpx_offset: None,
+            max_media: None,
+            jobs: 1,
+            ai_jobs: 1,
+            geo_jobs: 1,
+            filter: MediaFilter::default(),
+            since: None,
+            until: None,
+            use_file_date: false,
+            prefer_modified: false,
+            no_date: false,
+            name_template: None,
+            folder_template: None,
+            geocoder: GeocoderBackend::default(),
+            geonames_path: None,
+            places: None,
+            duplicates: None,
+            duplicates_dir: None,
+            group_similar: false,
+            similar_distance: DEFAULT_SIMILAR_DISTANCE,
+            burst_folder: false,
+            import_to: None,
+            delete_source_after_verify: false,
+            write_xmp: false,
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum MediaKind {
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    #[value(name = "images", alias = "image")]
     Image,
+    #[value(name = "videos", alias = "video")]
     Video,
 }
 
 impl MediaKind {
-    fn label(self) -> &'static str {
+    /// `image` or `video`, as used by the `{kind}` token.
+    pub fn label(self) -> &'static str {
         match self {
             MediaKind::Image => "image",
             MediaKind::Video => "video",
@@ -62,18 +250,200 @@ impl MediaKind {
     }
 }
 
+/// A supported photo or video found by [`discover`].
 #[derive(Debug)]
-struct MediaFile {
-    path: PathBuf,
-    kind: MediaKind,
+pub struct MediaFile {
+    pub path: PathBuf,
+    pub kind: MediaKind,
+    /// XMP, AAE, THM, LRV and Takeout JSON files that move with this file.
+    sidecars: Vec<Sidecar>,
+}
+
+impl MediaFile {
+    /// The sidecar files that move along with this file.
+    pub fn sidecars(&self) -> impl Iterator<Item = &Path> {
+        self.sidecars.iter().map(|sidecar| sidecar.path.as_path())
+    }
 }
 
 #[derive(Debug)]
 struct RenamePlan {
     base_name: String,
     extension: String,
-    date_folder: Option<String>,
+    target_subfolder: Option<PathBuf>,
+    /// Extra folder inside the target folder, used for burst groups.
+    group_folder: Option<String>,
     gps_cache_updated: bool,
+    reasons: RenameReasons,
+    xmp_update: Option<XmpUpdate>,
+}
+
+/// Filename and folder templates in effect for a run.
+struct Layout {
+    name: NameTemplate,
+    folder: Option<FolderTemplate>,
+}
+
+impl Layout {
+    fn from_options(options: &ProcessingOptions<'_>) -> Self {
+        let folder = options
+            .folder_template
+            .cloned()
+            .or_else(|| options.organize_by_date.then(FolderTemplate::default));
+
+        Layout {
+            name: options.name_template.cloned().unwrap_or_default(),
+            folder,
+        }
+    }
+
+    fn organizes_folders(&self) -> bool {
+        self.folder.is_some()
+    }
+
+    fn needs_ai(&self, ai_content: bool) -> bool {
+        self.name.uses_ai()
+            || (self.name.uses_content() && ai_content)
+            || self.folder.as_ref().is_some_and(FolderTemplate::uses_ai)
+    }
+
+    fn needs_place(&self, ai_content: bool) -> bool {
+        self.name.uses_place()
+            || (self.name.uses_content() && !ai_content)
+            || self.folder.as_ref().is_some_and(FolderTemplate::uses_place)
+    }
+}
+
+/// Templates and backends shared by every file of a run.
+struct Pipeline<'a> {
+    layout: Layout,
+    geocoder: Geocoder,
+    ai_provider: Option<Box<dyn AiProvider>>,
+    /// Caps concurrent requests to the AI backend.
+    ai_limit: Semaphore,
+    /// Caps concurrent reverse geocoding lookups.
+    geo_limit: Semaphore,
+    clock_rules: ClockRules,
+    gpx_track: Option<GpxTrack>,
+    places: NamedPlaces,
+    observer: &'a dyn Observer,
+}
+
+/// Persistent caches shared by the worker threads of a run.
+struct RunCaches {
+    gps: Mutex<GPSCache>,
+    ai: Mutex<AiCache>,
+    /// Facts already read to check the date range, taken by the planner.
+    facts: Mutex<HashMap<PathBuf, FileFacts>>,
+}
+
+impl<'a> Pipeline<'a> {
+    fn from_options(
+        options: &ProcessingOptions<'_>,
+        observer: &'a dyn Observer,
+    ) -> Result<Self, Error> {
+        if options.duplicates == Some(DuplicateMode::MoveTo) && options.duplicates_dir.is_none() {
+            return Err(Error::Config(
+                "--duplicates move-to needs a folder: use --duplicates-dir <path>".into(),
+            ));
+        }
+
+        let layout = Layout::from_options(options);
+        if options.write_xmp && !layout.needs_ai(options.ai_content) {
+            return Err(Error::Config(
+                "--write-xmp needs AI content analysis: add --ai-content".into(),
+            ));
+        }
+        let geocoder = load_geocoder(options, observer)?;
+        let places = match options.places {
+            Some(path) => NamedPlaces::load(path).map_err(Error::Config)?,
+            None => NamedPlaces::default(),
+        };
+        let clock_rules = match options.clock_rules {
+            Some(path) => ClockRules::load(path).map_err(Error::Config)?,
+            None => ClockRules::default(),
+        };
+        let gpx_track = if options.gpx.is_empty() {
+            None
+        } else {
+            let track = GpxTrack::load(options.gpx).map_err(Error::Config)?;
+            observer.on_event(&Event::GpxLoaded {
+                points: track.point_count(),
+            });
+            Some(track)
+        };
+        let ai_provider = if layout.needs_ai(options.ai_content) {
+            Some(
+                build_provider(&AiProviderConfig {
+                    kind: options.ai_provider,
+                    model: options.ai_model,
+                    host: options.ai_host,
+                    api_key: options.ai_api_key,
+                    command: options.ai_command,
+                })
+                .map_err(Error::Config)?,
+            )
+        } else {
+            None
+        };
+
+        Ok(Pipeline {
+            layout,
+            geocoder,
+            ai_provider,
+            ai_limit: Semaphore::new(options.ai_jobs),
+            geo_limit: Semaphore::new(options.geo_jobs),
+            clock_rules,
+            gpx_track,
+            places,
+            observer,
+        })
+    }
+
+    fn emit(&self, event: Event<'_>) {
+        self.observer.on_event(&event);
+    }
+
+    fn warn(&self, path: &Path, message: &str) {
+        self.emit(Event::Warning {
+            path: Some(path),
+            message,
+        });
+    }
+
+    fn timed<T>(&self, step: Step, path: Option<&Path>, work: impl FnOnce() -> T) -> T {
+        timed(self.observer, step, path, work)
+    }
+
+    fn ai_backend<'b>(
+        &'b self,
+        ai_provider: &'b dyn AiProvider,
+        caches: &'b RunCaches,
+    ) -> AiBackend<'b> {
+        AiBackend {
+            provider: ai_provider,
+            cache: &caches.ai,
+            limit: &self.ai_limit,
+            observer: self.observer,
+        }
+    }
+}
+
+/// Runs `work` and reports how long it took.
+fn timed<T>(
+    observer: &dyn Observer,
+    step: Step,
+    path: Option<&Path>,
+    work: impl FnOnce() -> T,
+) -> T {
+    let started = Instant::now();
+    let result = work();
+    observer.on_event(&Event::StepTimed {
+        step,
+        path,
+        elapsed: started.elapsed(),
+    });
+    result
 }
 
 fn is_supported_extension(extension: &str, supported_extensions: &[&str]) -> bool {
@@ -101,310 +471,1488 @@ fn is_not_resource_fork(path: &Path) -> bool {
         .unwrap_or(true)
 }
 
-fn collect_media_files(input_path: &Path) -> Result<Vec<MediaFile>, String> {
+/// Lists the supported photos and videos under `input_path` that pass
+/// `filter` and the `.nameforgeignore` files, in path order, with the sidecar
+/// files that belong to each. A single file is accepted too, and is taken
+/// whatever the filter says.
+pub fn discover(input_path: &Path, filter: &MediaFilter<'_>) -> Result<Vec<MediaFile>, Error> {
     if input_path.is_file() {
-        return classify_media_kind(input_path)
+        let kind = classify_media_kind(input_path)
             .filter(|_| is_not_resource_fork(input_path))
-            .map(|kind| {
-                vec![MediaFile {
-                    path: input_path.to_path_buf(),
-                    kind,
-                }]
-            })
-            .ok_or_else(|| format!("Not a supported media file: {}", input_path.display()));
+            .ok_or_else(|| Error::Unsupported(input_path.to_path_buf()))?;
+        let sidecar_paths: Vec<PathBuf> = input_path
+            .parent()
+            .and_then(|parent| fs::read_dir(parent).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| is_sidecar(path) && is_not_resource_fork(path))
+            .collect();
+        let sidecars = attach_sidecars(&[input_path], &sidecar_paths).remove(0);
+        return Ok(vec![MediaFile {
+            path: input_path.to_path_buf(),
+            kind,
+            sidecars,
+        }]);
     }
 
     if input_path.is_dir() {
-        let mut media_files = Vec::new();
-        collect_media_files_from_dir(input_path, &mut media_files)?;
-        return Ok(media_files);
+        let mut scan = FolderScan {
+            root: input_path,
+            filter,
+            ignores: IgnoreStack::default(),
+            media_files: Vec::new(),
+        };
+        scan.collect(input_path, 1)?;
+        return Ok(scan.media_files);
     }
 
-    Err(format!(
-        "Input path does not exist or is not accessible: {}",
-        input_path.display()
-    ))
+    Err(Error::NotFound(input_path.to_path_buf()))
 }
 
-fn collect_media_files_from_dir(
-    dir_path: &Path,
-    media_files: &mut Vec<MediaFile>,
-) -> Result<(), String> {
-    let mut entries: Vec<_> = fs::read_dir(dir_path)
-        .map_err(|error| format!("Could not open folder {}: {}", dir_path.display(), error))?
-        .filter_map(Result::ok)
-        .collect();
-
-    entries.sort_by_key(|entry| entry.path());
+/// State of a recursive folder scan.
+struct FolderScan<'a> {
+    root: &'a Path,
+    filter: &'a MediaFilter<'a>,
+    ignores: IgnoreStack,
+    media_files: Vec<MediaFile>,
+}
 
-    for entry in entries {
-        let path = entry.path();
-        if !is_not_resource_fork(&path) {
-            continue;
+impl FolderScan<'_> {
+    /// Collects `dir`, which is `depth` levels down (the input folder is 1).
+    fn collect(&mut self, dir_path: &Path, depth: usize) -> Result<(), Error> {
+        let pushed = self.ignores.enter(dir_path)?;
+        let result = self.collect_entries(dir_path, depth);
+        if pushed {
+            self.ignores.pop();
         }
+        result
+    }
 
-        let Ok(file_type) = entry.file_type() else {
-            continue;
-        };
+    fn collect_entries(&mut self, dir_path: &Path, depth: usize) -> Result<(), Error> {
+        let mut entries: Vec<_> = fs::read_dir(dir_path)
+            .map_err(|error| Error::io(dir_path, error))?
+            .filter_map(Result::ok)
+            .collect();
 
-        if file_type.is_symlink() {
-            continue;
-        }
+        entries.sort_by_key(|entry| entry.path());
 
-        if file_type.is_dir() {
-            collect_media_files_from_dir(&path, media_files)?;
-            continue;
-        }
+        let mut here = Vec::new();
+        let mut sidecar_paths = Vec::new();
+        for entry in entries {
+            let path = entry.path();
+            if !is_not_resource_fork(&path) {
+                continue;
+            }
 
-        if !file_type.is_file() {
-            continue;
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_symlink() {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if self.filter.descends_into(depth)
+                    && !self.ignores.ignores(&path, true)
+                    && !self.filter.excludes_folder(&self.relative(&path))
+                {
+                    self.collect(&path, depth + 1)?;
+                }
+                continue;
+            }
+
+            if !file_type.is_file() {
+                continue;
+            }
+
+            if let Some(kind) = classify_media_kind(&path) {
+                if self.ignores.ignores(&path, false)
+                    || !self.filter.takes_file(&path, &self.relative(&path), kind)
+                {
+                    continue;
+                }
+                here.push(self.media_files.len());
+                self.media_files.push(MediaFile {
+                    path,
+                    kind,
+                    sidecars: Vec::new(),
+                });
+            } else if is_sidecar(&path) {
+                sidecar_paths.push(path);
+            }
         }
 
-        if let Some(kind) = classify_media_kind(&path) {
-            media_files.push(MediaFile { path, kind });
+        // Sidecars only pair with media in their own folder, not with subfolders.
+        let media_paths: Vec<&Path> = here
+            .iter()
+            .map(|&index| self.media_files[index].path.as_path())
+            .collect();
+        let attached = attach_sidecars(&media_paths, &sidecar_paths);
+        for (index, sidecars) in here.into_iter().zip(attached) {
+            self.media_files[index].sidecars = sidecars;
         }
+
+        Ok(())
     }
 
-    Ok(())
+    /// `path` below the input folder, with `/` separators for glob matching.
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(self.root)
+            .unwrap_or(path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
 }
 
-pub fn process_folder(input_path: &Path, options: &ProcessingOptions<'_>) {
-    let media_files = match collect_media_files(input_path) {
-        Ok(files) => files,
-        Err(error) => {
-            eprintln!("{} {}", "❌".bright_red(), error.bright_white());
-            return;
+fn load_geonames_index(path: &Path, observer: &dyn Observer) -> Result<GeoNamesIndex, Error> {
+    observer.on_event(&Event::GeoNamesLoading { path });
+    let index = GeoNamesIndex::load(path).map_err(Error::Config)?;
+    observer.on_event(&Event::GeoNamesLoaded {
+        places: index.len(),
+    });
+    Ok(index)
+}
+
+fn load_geocoder(
+    options: &ProcessingOptions<'_>,
+    observer: &dyn Observer,
+) -> Result<Geocoder, Error> {
+    match options.geocoder {
+        GeocoderBackend::Nominatim => Ok(Geocoder::nominatim()),
+        GeocoderBackend::Offline => {
+            let path = options.geonames_path.ok_or_else(|| {
+                Error::Config(
+                    "The offline geocoder needs a GeoNames file: use --geonames <path>".into(),
+                )
+            })?;
+            load_geonames_index(path, observer).map(Geocoder::Offline)
         }
+    }
+}
+
+/// Converts a GeoNames dump into the compact index accepted by `--geonames`
+/// and returns how many places it holds.
+pub fn build_geonames_index(
+    geonames_path: &Path,
+    output_path: &Path,
+    observer: &dyn Observer,
+) -> Result<usize, Error> {
+    let index = load_geonames_index(geonames_path, observer)?;
+    index.write_compact(output_path).map_err(Error::Config)?;
+    Ok(index.len())
+}
+
+/// The clock correction found by [`measure_clock_offset`].
+#[derive(Debug)]
+pub struct ClockOffset {
+    /// Added to the photo's capture time, it gives the reference's.
+    pub shift: TimeShift,
+    pub camera: CameraIdentity,
+}
+
+/// Compares two photos of the same moment and measures the shift that brings
+/// `photo`'s camera clock in line with `reference`'s.
+pub fn measure_clock_offset(
+    reference: &Path,
+    photo: &Path,
+    timezone: TimeZoneMode,
+) -> Result<ClockOffset, Error> {
+    let read = |path: &Path| {
+        let exif = read_exif_data(path)
+            .ok_or_else(|| Error::MissingExif(format!("No EXIF data in {}", path.display())))?;
+        let taken = try_parse_exif_date(&exif).ok_or_else(|| {
+            Error::MissingExif(format!("No EXIF DateTimeOriginal in {}", path.display()))
+        })?;
+        Ok::<_, Error>((taken.in_zone(timezone), get_camera_identity(&exif)))
     };
+    let (reference_time, _) = read(reference)?;
+    let (photo_time, camera) = read(photo)?;
 
-    if media_files.is_empty() {
-        println!(
-            "{}  {}",
-            "📊".bright_blue(),
-            "Found 0 supported media files to process".bright_blue()
-        );
-        return;
+    Ok(ClockOffset {
+        shift: TimeShift::from_seconds((reference_time - photo_time).num_seconds()),
+        camera,
+    })
+}
+
+/// One result of planning: a rename to carry out, or a file left alone.
+enum PlanStep {
+    Rename(Box<PlannedRename>),
+    Skip(PathBuf, SkipReason),
+}
+
+/// Builds the rename for every media file under `input_path` and hands each one
+/// to `on_step` in scan order. Renames that change nothing arrive as skips.
+fn plan_folder(
+    input_path: &Path,
+    options: &ProcessingOptions<'_>,
+    observer: &dyn Observer,
+    mut on_step: impl FnMut(PlanStep),
+) -> Result<(), Error> {
+    let pipeline = Pipeline::from_options(options, observer)?;
+    let media_files = pipeline.timed(Step::Scan, None, || discover(input_path, &options.filter))?;
+    for media_file in &media_files {
+        pipeline.emit(Event::Discovered {
+            path: &media_file.path,
+            kind: media_file.kind,
+        });
     }
+    let mut on_step = |step: PlanStep| {
+        let step = match step {
+            PlanStep::Rename(planned) if planned.is_noop() => {
+                PlanStep::Skip(planned.source.clone(), SkipReason::AlreadyNamed)
+            }
+            step => step,
+        };
+        match &step {
+            PlanStep::Rename(planned) => pipeline.emit(Event::Planned(planned)),
+            PlanStep::Skip(path, reason) => pipeline.emit(Event::Skipped {
+                path,
+                reason: *reason,
+            }),
+        }
+        on_step(step);
+    };
 
     let image_count = media_files
         .iter()
         .filter(|media_file| media_file.kind == MediaKind::Image)
         .count();
-    let video_count = media_files.len() - image_count;
-
-    println!(
-        "{}  {}{}{}{}{}{}{}{}",
-        "📊".bright_blue(),
-        "Found ".bright_blue(),
-        media_files.len().to_string().bright_white().bold(),
-        " supported media files to process ".bright_blue(),
-        "(".bright_black(),
-        image_count.to_string().bright_white().bold(),
-        " images, ".bright_black(),
-        video_count.to_string().bright_white().bold(),
-        " videos)".bright_black()
-    );
+    pipeline.emit(Event::Scanned {
+        images: image_count,
+        videos: media_files.len() - image_count,
+    });
+    if media_files.is_empty() {
+        return Ok(());
+    }
 
-    let base_folder = get_base_folder(input_path).to_path_buf();
-    let mut gps_cache = GPSCache::load();
+    let placement = match options.import_to {
+        Some(library) => Placement::Library(library.to_path_buf()),
+        None => Placement::InPlace(get_base_folder(input_path).to_path_buf()),
+    };
+    let action = match (options.import_to, options.delete_source_after_verify) {
+        (None, _) => PlannedAction::Rename,
+        (Some(_), false) => PlannedAction::Copy,
+        (Some(_), true) => PlannedAction::CopyAndDelete,
+    };
+    let gps_cache = GPSCache::load();
+    if gps_cache.len() > 0 {
+        pipeline.emit(Event::GpsCacheLoaded {
+            entries: gps_cache.len(),
+        });
+    }
+    let caches = RunCaches {
+        gps: Mutex::new(gps_cache),
+        ai: Mutex::new(AiCache::load()),
+        facts: Mutex::new(HashMap::new()),
+    };
     let mut cache_updated = false;
-    let mut processed_count = 0;
+    let mut reserved_paths = HashSet::new();
+
+    // The date range is checked first, so that files outside it count
+    // neither towards `max_media` nor as kept copies of duplicates.
+    let media_files = if options.since.is_some() || options.until.is_some() {
+        let mut in_range = vec![false; media_files.len()];
+        for_each_ordered(
+            &media_files,
+            options.jobs,
+            |_, media_file| read_file_facts(media_file, &pipeline, options),
+            |index, media_file, facts| {
+                if in_date_range(options, facts.captured_at) {
+                    in_range[index] = true;
+                    caches
+                        .facts
+                        .lock()
+                        .unwrap()
+                        .insert(media_file.path.clone(), facts);
+                } else {
+                    on_step(PlanStep::Skip(
+                        media_file.path.clone(),
+                        SkipReason::OutsideDateRange,
+                    ));
+                }
+            },
+        );
+        media_files
+            .into_iter()
+            .zip(in_range)
+            .filter_map(|(media_file, in_range)| in_range.then_some(media_file))
+            .collect()
+    } else {
+        media_files
+    };
 
-    for media_file in media_files {
-        if options
-            .max_media
-            .map(|max| processed_count >= max)
-            .unwrap_or(false)
-        {
-            println!(
-                "{}  {}{}{}",
-                "🎯".bright_cyan(),
-                "Reached maximum file limit of ".bright_cyan(),
-                options.max_media.unwrap().to_string().bright_white().bold(),
-                ". Stopping processing.".bright_cyan()
+    let media_files = match options.max_media {
+        Some(max) if media_files.len() > max => {
+            pipeline.emit(Event::MediaLimit { max });
+            for media_file in &media_files[max..] {
+                on_step(PlanStep::Skip(
+                    media_file.path.clone(),
+                    SkipReason::MaxMedia,
+                ));
+            }
+            &media_files[..max]
+        }
+        _ => &media_files[..],
+    };
+
+    let duplicate_of = match options.duplicates {
+        Some(mode) => pipeline.timed(Step::Duplicates, None, || {
+            find_duplicates(media_files, mode, options.jobs, observer)
+        }),
+        None => HashMap::new(),
+    };
+    let removes_duplicates = options
+        .duplicates
+        .is_some_and(|mode| mode != DuplicateMode::Report);
+    let work: Vec<&MediaFile> = media_files
+        .iter()
+        .filter(|media_file| !(removes_duplicates && duplicate_of.contains_key(&media_file.path)))
+        .collect();
+    let mut kept_destinations = HashMap::new();
+    let mut duplicates = Vec::new();
+    let similar = if options.group_similar || options.burst_folder {
+        pipeline.timed(Step::Similar, None, || {
+            find_similar_groups(&work, options, observer)
+        })
+    } else {
+        HashMap::new()
+    };
+    let mut group_names = HashMap::new();
+    let units = pair_units(&work, options.jobs, observer);
+
+    // Plans are built on the worker threads; names are assigned here, in input
+    // order, so collision suffixes do not depend on timing.
+    for_each_ordered(
+        &units,
+        options.jobs,
+        |index, unit| {
+            let mut facts: Vec<FileFacts> = unit
+                .iter()
+                .map(|media_file| {
+                    pipeline.emit(Event::Processing {
+                        path: &media_file.path,
+                        kind: media_file.kind,
+                    });
+                    caches
+                        .facts
+                        .lock()
+                        .unwrap()
+                        .remove(&media_file.path)
+                        .unwrap_or_else(|| read_file_facts(media_file, &pipeline, options))
+                })
+                .collect();
+            // Only the leader of a pair is looked up and named; the other
+            // members take its name, so AI and the geocoder run once per pair.
+            let leader = pair_leader(unit, &facts);
+            let leader_plan = build_rename_plan(
+                unit[leader],
+                facts.remove(leader),
+                &caches,
+                &pipeline,
+                options,
+                index + 1,
             );
-            break;
+            let mut rename_plans: Vec<_> = unit
+                .iter()
+                .enumerate()
+                .filter(|&(member, _)| member != leader)
+                .zip(facts)
+                .map(|((_, media_file), facts)| match &leader_plan {
+                    Ok(leader_plan) => {
+                        follow_pair_leader(media_file, &unit[leader].path, leader_plan)
+                    }
+                    Err(_) => {
+                        build_rename_plan(media_file, facts, &caches, &pipeline, options, index + 1)
+                    }
+                })
+                .collect();
+            rename_plans.insert(leader, leader_plan);
+            let xmp_update = rename_plans[leader]
+                .as_mut()
+                .ok()
+                .and_then(|rename_plan| rename_plan.xmp_update.take());
+            if let Ok(rename_plan) = &mut rename_plans[xmp_holder(unit, leader)] {
+                rename_plan.xmp_update = xmp_update;
+            }
+            rename_plans
+        },
+        |_, unit, rename_plans| {
+            let mut members = Vec::new();
+            for (&media_file, rename_plan) in unit.iter().zip(rename_plans) {
+                let rename_plan = match rename_plan {
+                    Ok(rename_plan) => rename_plan,
+                    Err(reason) => {
+                        on_step(PlanStep::Skip(media_file.path.clone(), reason));
+                        continue;
+                    }
+                };
+                cache_updated |= rename_plan.gps_cache_updated;
+
+                if let Some(mode) = options
+                    .duplicates
+                    .filter(|_| !duplicate_of.contains_key(&media_file.path))
+                {
+                    if let Some(existing) =
+                        existing_copy(media_file, &rename_plan, &placement, &pipeline.layout)
+                    {
+                        pipeline.emit(Event::Duplicate {
+                            path: &media_file.path,
+                            original: &existing,
+                        });
+                        if mode != DuplicateMode::Report {
+                            duplicates.push((media_file, existing));
+                            continue;
+                        }
+                    }
+                }
+                members.push((media_file, rename_plan));
+            }
+
+            // Pairs join a similar group together, whichever member was hashed.
+            let similar_member = members
+                .iter()
+                .find_map(|(media_file, _)| similar.get(&media_file.path));
+            let members = match similar_member {
+                Some(member) => members
+                    .into_iter()
+                    .map(|(media_file, rename_plan)| {
+                        let rename_plan = join_similar_group(
+                            rename_plan,
+                            member,
+                            &mut group_names,
+                            options.burst_folder,
+                        );
+                        (media_file, rename_plan)
+                    })
+                    .collect(),
+                None => members,
+            };
+
+            for planned in
+                assign_destinations(members, &placement, &pipeline, action, &mut reserved_paths)
+            {
+                kept_destinations.insert(planned.source.clone(), planned.destination.clone());
+                on_step(PlanStep::Rename(Box::new(planned)));
+            }
+        },
+    );
+
+    if removes_duplicates {
+        let mode = options.duplicates.unwrap_or(DuplicateMode::Skip);
+        let copies = media_files.iter().filter_map(|media_file| {
+            let keeper = duplicate_of.get(&media_file.path)?;
+            let kept = kept_destinations.get(keeper).unwrap_or(keeper);
+            Some((media_file, kept.clone()))
+        });
+        for (duplicate, kept) in copies.chain(duplicates) {
+            match plan_duplicate(
+                duplicate,
+                &kept,
+                mode,
+                options.duplicates_dir,
+                &mut reserved_paths,
+                observer,
+            ) {
+                Some(planned) => on_step(PlanStep::Rename(Box::new(planned))),
+                None => on_step(PlanStep::Skip(
+                    duplicate.path.clone(),
+                    SkipReason::Duplicate,
+                )),
+            }
         }
+    }
 
-        println!(
-            "{}  {}{}{}",
-            "📷".bright_blue(),
-            "Processing ".bright_blue(),
-            media_file.kind.label().bright_white().bold(),
-            format!(" file: {}", media_file.path.display()).bright_blue()
-        );
+    let gps_cache = caches.gps.into_inner().unwrap();
+    let ai_cache = caches.ai.into_inner().unwrap();
+    let saved = if cache_updated {
+        gps_cache.save().map(|()| {
+            pipeline.emit(Event::GpsCacheSaved {
+                entries: gps_cache.len(),
+            })
+        })
+    } else {
+        Ok(())
+    };
+    for error in [saved, ai_cache.save()].into_iter().filter_map(Result::err) {
+        pipeline.emit(Event::Warning {
+            path: None,
+            message: &error,
+        });
+    }
 
-        if let Some(rename_plan) = build_rename_plan(&media_file, &mut gps_cache, options) {
-            cache_updated |= rename_plan.gps_cache_updated;
+    if gps_cache.hits() + gps_cache.lookups() > 0 {
+        pipeline.emit(Event::GpsCacheStats {
+            hits: gps_cache.hits(),
+            lookups: gps_cache.lookups(),
+        });
+    }
+    if pipeline.ai_provider.is_some() {
+        pipeline.emit(Event::AiCacheStats {
+            hits: ai_cache.hits(),
+            misses: ai_cache.misses(),
+        });
+    }
 
-            let target_folder = get_target_folder(
-                &media_file.path,
-                &base_folder,
-                rename_plan.date_folder.as_deref(),
-                options.organize_by_date,
+    Ok(())
+}
+
+/// Maps every byte-identical copy to the file that is kept. The kept file is
+/// the first one that no longer has a camera-style name (it was most likely
+/// renamed by an earlier run), or else the first one found.
+fn find_duplicates(
+    media_files: &[MediaFile],
+    mode: DuplicateMode,
+    jobs: usize,
+    observer: &dyn Observer,
+) -> HashMap<PathBuf, PathBuf> {
+    let paths: Vec<PathBuf> = media_files
+        .iter()
+        .map(|media_file| media_file.path.clone())
+        .collect();
+    let mut duplicate_of = HashMap::new();
+
+    for group in find_duplicate_groups(&paths, jobs) {
+        let keeper = group
+            .iter()
+            .copied()
+            .find(|&index| {
+                paths[index]
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|stem| !is_generic_camera_stem(stem))
+            })
+            .unwrap_or(group[0]);
+
+        for &index in group.iter().filter(|&&index| index != keeper) {
+            if mode == DuplicateMode::Report {
+                observer.on_event(&Event::Duplicate {
+                    path: &paths[index],
+                    original: &paths[keeper],
+                });
+            }
+            duplicate_of.insert(paths[index].clone(), paths[keeper].clone());
+        }
+    }
+
+    if !duplicate_of.is_empty() {
+        observer.on_event(&Event::DuplicatesFound {
+            count: duplicate_of.len(),
+        });
+    }
+    duplicate_of
+}
+
+/// Where a photo sits in its group of near-identical photos.
+struct SimilarMember {
+    group: usize,
+    position: usize,
+    size: usize,
+}
+
+/// Hashes the still images of a run and lists the groups of near-identical
+/// ones, e.g. bursts and re-encoded copies.
+fn find_similar_groups(
+    media_files: &[&MediaFile],
+    options: &ProcessingOptions<'_>,
+    observer: &dyn Observer,
+) -> HashMap<PathBuf, SimilarMember> {
+    observer.on_event(&Event::HashingSimilar);
+
+    let mut hashes = Vec::with_capacity(media_files.len());
+    for_each_ordered(
+        media_files,
+        options.jobs,
+        |_, media_file| match media_file.kind {
+            MediaKind::Image => dhash(&media_file.path),
+            MediaKind::Video => None,
+        },
+        |_, _, hash| hashes.push(hash),
+    );
+
+    let mut members = HashMap::new();
+    for (group, indices) in group_similar(&hashes, options.similar_distance)
+        .into_iter()
+        .enumerate()
+    {
+        observer.on_event(&Event::SimilarGroup {
+            group: group + 1,
+            paths: indices
+                .iter()
+                .map(|&index| media_files[index].path.as_path())
+                .collect(),
+        });
+
+        for (position, &index) in indices.iter().enumerate() {
+            members.insert(
+                media_files[index].path.clone(),
+                SimilarMember {
+                    group,
+                    position,
+                    size: indices.len(),
+                },
             );
-            let Some(new_name) = unique_filename(
-                &target_folder,
-                Some(&media_file.path),
-                &rename_plan.base_name,
-                &rename_plan.extension,
-            ) else {
-                eprintln!(
-                    "{} {}{}",
-                    "❌".bright_red(),
-                    "Failed to generate a unique filename for ".bright_red(),
-                    media_file.path.display().to_string().bright_white()
-                );
-                processed_count += 1;
-                continue;
-            };
+        }
+    }
+    members
+}
+
+/// Renames a group member after the first planned photo of its group, with a
+/// zero-padded sequence suffix, and optionally into a folder of its own.
+fn join_similar_group(
+    rename_plan: RenamePlan,
+    member: &SimilarMember,
+    group_names: &mut HashMap<usize, (String, Option<PathBuf>)>,
+    burst_folder: bool,
+) -> RenamePlan {
+    let (base_name, target_subfolder) = group_names
+        .entry(member.group)
+        .or_insert_with(|| {
+            (
+                rename_plan.base_name.clone(),
+                rename_plan.target_subfolder.clone(),
+            )
+        })
+        .clone();
+    let width = member.size.to_string().len().max(2);
+
+    RenamePlan {
+        base_name: format!("{}_{:0width$}", base_name, member.position + 1),
+        target_subfolder,
+        group_folder: burst_folder.then(|| base_name.clone()),
+        reasons: RenameReasons {
+            similar_group: Some(member.group + 1),
+            ..rename_plan.reasons
+        },
+        ..rename_plan
+    }
+}
+
+/// An already existing file at the planned name, or one of its suffixed
+/// variants, with the same contents as `media_file`.
+fn existing_copy(
+    media_file: &MediaFile,
+    rename_plan: &RenamePlan,
+    placement: &Placement,
+    layout: &Layout,
+) -> Option<PathBuf> {
+    let target_folder = get_target_folder(
+        &media_file.path,
+        placement,
+        rename_plan.target_subfolder.as_deref(),
+        layout.organizes_folders(),
+    );
+
+    candidate_filenames(&rename_plan.base_name, &rename_plan.extension)
+        .map(|(filename, _)| target_folder.join(filename))
+        .take_while(|candidate| candidate.exists() && *candidate != media_file.path)
+        .find(|candidate| same_contents(&media_file.path, candidate))
+}
+
+/// Turns a duplicate into a delete or a move; `None` leaves it alone for `skip`.
+/// Its sidecars go wherever the duplicate goes.
+fn plan_duplicate(
+    duplicate: &MediaFile,
+    kept: &Path,
+    mode: DuplicateMode,
+    duplicates_dir: Option<&Path>,
+    reserved_paths: &mut HashSet<PathBuf>,
+    observer: &dyn Observer,
+) -> Option<PlannedRename> {
+    let reasons = RenameReasons {
+        duplicate_of: Some(kept.to_path_buf()),
+        ..RenameReasons::default()
+    };
+
+    match (mode, duplicates_dir) {
+        (DuplicateMode::Delete, _) => Some(PlannedRename {
+            source: duplicate.path.clone(),
+            destination: duplicate.path.clone(),
+            action: PlannedAction::Delete,
+            reasons,
+            sidecars: duplicate
+                .sidecars
+                .iter()
+                .map(|sidecar| SidecarMove {
+                    source: sidecar.path.clone(),
+                    destination: sidecar.path.clone(),
+                })
+                .collect(),
+            xmp: None,
+        }),
+        (DuplicateMode::MoveTo, Some(dir)) => {
+            let stem = duplicate.path.file_stem()?.to_str()?;
+            let extension = duplicate.path.extension()?.to_str()?;
+            let (name, collision_suffix) =
+                unique_filename(dir, None, stem, extension, reserved_paths)?;
+            let destination = dir.join(&name);
+            reserved_paths.insert(destination.clone());
+
+            Some(PlannedRename {
+                source: duplicate.path.clone(),
+                destination,
+                action: PlannedAction::Rename,
+                reasons: RenameReasons {
+                    collision_suffix,
+                    ..reasons
+                },
+                sidecars: plan_sidecars(duplicate, dir, &name, reserved_paths, observer),
+                xmp: None,
+            })
+        }
+        _ => None,
+    }
+}
 
-            let new_path = get_target_path(
+/// Picks the final, collision-free paths for a file or a pair and records why.
+/// Members of a pair share one collision suffix so their names stay together.
+fn assign_destinations(
+    members: Vec<(&MediaFile, RenamePlan)>,
+    placement: &Placement,
+    pipeline: &Pipeline<'_>,
+    action: PlannedAction,
+    reserved_paths: &mut HashSet<PathBuf>,
+) -> Vec<PlannedRename> {
+    let layout = &pipeline.layout;
+    let Some(base_name) = members
+        .first()
+        .map(|(_, rename_plan)| rename_plan.base_name.clone())
+    else {
+        return Vec::new();
+    };
+    let target_folders: Vec<PathBuf> = members
+        .iter()
+        .map(|(media_file, rename_plan)| {
+            let mut target_folder = get_target_folder(
                 &media_file.path,
-                &base_folder,
-                rename_plan.date_folder.as_deref(),
+                placement,
+                rename_plan.target_subfolder.as_deref(),
+                layout.organizes_folders(),
+            );
+            if let Some(group_folder) = &rename_plan.group_folder {
+                target_folder.push(group_folder);
+            }
+            target_folder
+        })
+        .collect();
+    // A file may keep its own name, but a copy must never land on its source.
+    let slots: Vec<(&Path, Option<&Path>, &str)> = members
+        .iter()
+        .zip(&target_folders)
+        .map(|((media_file, rename_plan), target_folder)| {
+            (
+                target_folder.as_path(),
+                (action == PlannedAction::Rename).then_some(media_file.path.as_path()),
+                rename_plan.extension.as_str(),
+            )
+        })
+        .collect();
+    let Some(collision_suffix) = unique_shared_suffix(&slots, &base_name, reserved_paths) else {
+        for (media_file, _) in &members {
+            pipeline.emit(Event::Failed {
+                path: Some(&media_file.path),
+                error: &Error::NoUniqueName(media_file.path.clone()),
+            });
+        }
+        return Vec::new();
+    };
+
+    members
+        .into_iter()
+        .zip(target_folders)
+        .map(|((media_file, rename_plan), target_folder)| {
+            let new_name =
+                filename_with_suffix(&base_name, &rename_plan.extension, collision_suffix);
+            let sidecars = plan_sidecars(
+                media_file,
+                &target_folder,
                 &new_name,
-                options.organize_by_date,
+                reserved_paths,
+                pipeline.observer,
             );
+            let destination = target_folder.join(new_name);
+            reserved_paths.insert(destination.clone());
+
+            PlannedRename {
+                source: media_file.path.clone(),
+                destination,
+                action,
+                reasons: RenameReasons {
+                    collision_suffix,
+                    ..rename_plan.reasons
+                },
+                sidecars,
+                xmp: rename_plan.xmp_update,
+            }
+        })
+        .collect()
+}
+
+/// Splits the files of a run into planning units: a RAW+JPEG or Live Photo
+/// pair is one unit, every other file is a unit of its own. Units are ordered
+/// by their first file.
+fn pair_units<'a>(
+    work: &[&'a MediaFile],
+    jobs: usize,
+    observer: &dyn Observer,
+) -> Vec<Vec<&'a MediaFile>> {
+    let mut content_identifiers = Vec::with_capacity(work.len());
+    for_each_ordered(
+        work,
+        jobs,
+        |_, media_file| read_content_identifier(media_file),
+        |_, _, identifier| content_identifiers.push(identifier),
+    );
+    let paths: Vec<&Path> = work
+        .iter()
+        .map(|media_file| media_file.path.as_path())
+        .collect();
+
+    let mut pair_of = HashMap::new();
+    for (pair, indices) in find_pairs(&paths, &content_identifiers)
+        .into_iter()
+        .enumerate()
+    {
+        observer.on_event(&Event::Paired {
+            paths: indices
+                .iter()
+                .map(|&index| work[index].path.as_path())
+                .collect(),
+        });
+        for &index in &indices {
+            pair_of.insert(index, pair);
+        }
+    }
+
+    let mut units: Vec<Vec<&MediaFile>> = Vec::new();
+    let mut unit_of_pair: HashMap<usize, usize> = HashMap::new();
+    for (index, &media_file) in work.iter().enumerate() {
+        match pair_of.get(&index) {
+            Some(pair) => match unit_of_pair.get(pair) {
+                Some(&unit) => units[unit].push(media_file),
+                None => {
+                    unit_of_pair.insert(*pair, units.len());
+                    units.push(vec![media_file]);
+                }
+            },
+            None => units.push(vec![media_file]),
+        }
+    }
+    units
+}
+
+/// The Live Photo identifier of a HEIC/JPEG still or its MOV clip.
+fn read_content_identifier(media_file: &MediaFile) -> Option<String> {
+    let extension = media_file.path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "heic" | "heif" | "jpg" | "jpeg" => {
+            get_content_identifier(&read_exif_data(&media_file.path)?)
+        }
+        "mov" => read_video_metadata(&media_file.path)?.content_identifier,
+        _ => None,
+    }
+}
+
+/// The member of a pair whose metadata describes it best: a capture date from
+/// the file itself or a JSON sidecar first, then a location, then photos over
+/// videos, then the first file. Decided before any lookup, so AI naming and
+/// geocoding only run for this member.
+fn pair_leader(unit: &[&MediaFile], facts: &[FileFacts]) -> usize {
+    unit.iter()
+        .zip(facts)
+        .enumerate()
+        .max_by_key(|(index, (media_file, facts))| {
+            (
+                match facts.dated.map(|(_, source)| source) {
+                    Some(DateSource::Exif | DateSource::Container) => 2,
+                    Some(DateSource::Xmp | DateSource::Sidecar) => 1,
+                    _ => 0,
+                },
+                facts.coordinates.is_some(),
+                media_file.kind == MediaKind::Image,
+                std::cmp::Reverse(*index),
+            )
+        })
+        .map_or(0, |(index, _)| index)
+}
+
+/// The member of a unit whose XMP sidecar gets the AI description: one that
+/// already has a sidecar, else the RAW of a RAW+JPEG pair, as Lightroom and
+/// darktable expect, else the leader. Writing it once keeps two members from
+/// sharing one new `.xmp`.
+fn xmp_holder(unit: &[&MediaFile], leader: usize) -> usize {
+    unit.iter()
+        .enumerate()
+        .filter(|(_, media_file)| media_file.kind == MediaKind::Image)
+        .max_by_key(|&(index, media_file)| {
+            (
+                media_file.sidecars.iter().any(Sidecar::is_xmp),
+                is_raw(&media_file.path),
+                index == leader,
+                std::cmp::Reverse(index),
+            )
+        })
+        .map_or(leader, |(index, _)| index)
+}
+
+fn is_raw(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| is_supported_extension(extension, RAW_EXTENSIONS))
+}
+
+/// Names a pair member after its leader, keeping its own extension.
+fn follow_pair_leader(
+    media_file: &MediaFile,
+    leader_path: &Path,
+    leader: &RenamePlan,
+) -> Result<RenamePlan, SkipReason> {
+    let extension = media_file
+        .path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .ok_or(SkipReason::NoExtension)?
+        .to_string();
+
+    Ok(RenamePlan {
+        base_name: leader.base_name.clone(),
+        extension,
+        target_subfolder: leader.target_subfolder.clone(),
+        group_folder: None,
+        gps_cache_updated: false,
+        reasons: RenameReasons {
+            named_after: Some(leader_path.to_path_buf()),
+            ..leader.reasons.clone()
+        },
+        xmp_update: None,
+    })
+}
 
-            if media_file.path == new_path {
-                print_skip_info(&media_file.path);
-            } else if options.dry_run {
-                print_dry_run_info(&media_file.path, &new_path);
-            } else if let Err(error) = execute_rename(&media_file.path, &new_path) {
-                eprintln!(
-                    "{} {}{} {} {}{}  {}{}",
-                    "❌".bright_red(),
-                    "Failed to rename ".bright_red(),
-                    media_file.path.display().to_string().bright_white(),
-                    "→".bright_red(),
-                    new_path.display().to_string().bright_white(),
-                    ": ".bright_red(),
-                    "Error: ".bright_red(),
-                    error.to_string().bright_white()
-                );
+/// Gives each sidecar the media file's new name. A sidecar whose new path is
+/// already taken stays where it is rather than overwriting another file.
+fn plan_sidecars(
+    media_file: &MediaFile,
+    target_folder: &Path,
+    new_name: &str,
+    reserved_paths: &mut HashSet<PathBuf>,
+    observer: &dyn Observer,
+) -> Vec<SidecarMove> {
+    let mut moves = Vec::new();
+    for sidecar in &media_file.sidecars {
+        let destination = target_folder.join(sidecar.renamed(new_name));
+        if destination != sidecar.path
+            && (destination.exists() || reserved_paths.contains(&destination))
+        {
+            observer.on_event(&Event::Warning {
+                path: Some(&sidecar.path),
+                message: &format!(
+                    "leaving sidecar in place, {} already exists",
+                    destination.display()
+                ),
+            });
+            continue;
+        }
+        reserved_paths.insert(destination.clone());
+        moves.push(SidecarMove {
+            source: sidecar.path.clone(),
+            destination,
+        });
+    }
+    moves
+}
+
+/// What became of one file.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Status {
+    /// The planned action was carried out.
+    Done,
+    /// Nothing was changed because this is a dry run.
+    DryRun,
+    Skipped(SkipReason),
+    Failed(Error),
+}
+
+/// The result for one file of a run.
+#[derive(Debug)]
+pub struct FileOutcome {
+    pub source: PathBuf,
+    /// The rename decided for the file; missing when it was skipped before
+    /// a name was worked out.
+    pub planned: Option<PlannedRename>,
+    pub status: Status,
+}
+
+/// Everything a run did, in scan order.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub outcomes: Vec<FileOutcome>,
+    /// The journal that can undo this run, if anything was changed.
+    pub journal: Option<PathBuf>,
+}
+
+/// The renames worked out for a folder, before anything is changed.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub renames: Vec<PlannedRename>,
+    /// Files left alone, with the reason.
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+}
+
+fn skipped_outcome(source: PathBuf, reason: SkipReason) -> FileOutcome {
+    FileOutcome {
+        source,
+        planned: None,
+        status: Status::Skipped(reason),
+    }
+}
+
+/// Carries out (or previews) one planned rename.
+fn apply_planned(
+    planned: PlannedRename,
+    dry_run: bool,
+    journal: &mut Journal,
+    observer: &dyn Observer,
+) -> FileOutcome {
+    let status = if planned.is_noop() {
+        observer.on_event(&Event::Skipped {
+            path: &planned.source,
+            reason: SkipReason::AlreadyNamed,
+        });
+        Status::Skipped(SkipReason::AlreadyNamed)
+    } else if dry_run {
+        observer.on_event(&Event::DryRun(&planned));
+        Status::DryRun
+    } else {
+        timed(observer, Step::Transfer, Some(&planned.source), || {
+            transfer_planned(&planned, journal, observer)
+        })
+    };
+
+    FileOutcome {
+        source: planned.source.clone(),
+        planned: Some(planned),
+        status,
+    }
+}
+
+/// Renames, copies or deletes the file of a plan, then its sidecars and XMP.
+fn transfer_planned(
+    planned: &PlannedRename,
+    journal: &mut Journal,
+    observer: &dyn Observer,
+) -> Status {
+    let result = match planned.action {
+        PlannedAction::Delete => journal.trash(&planned.source).map(drop),
+        PlannedAction::Copy | PlannedAction::CopyAndDelete => {
+            execute_copy(planned, journal, observer)
+        }
+        PlannedAction::Rename if planned.source == planned.destination => Ok(()),
+        PlannedAction::Rename => execute_rename(&planned.source, &planned.destination, journal),
+    };
+
+    match result {
+        Ok(()) => {
+            observer.on_event(&Event::Renamed {
+                source: &planned.source,
+                destination: &planned.destination,
+                action: planned.action,
+            });
+            // A renamed XMP sidecar is written before it moves; an imported
+            // one once it has been copied.
+            match planned.action {
+                PlannedAction::Rename => {
+                    write_planned_xmp(planned, journal, observer);
+                    transfer_sidecars(planned, journal, observer);
+                }
+                PlannedAction::Copy | PlannedAction::CopyAndDelete => {
+                    transfer_sidecars(planned, journal, observer);
+                    write_planned_xmp(planned, journal, observer);
+                }
+                PlannedAction::Delete => trash_sidecars(planned, journal, observer),
             }
+            Status::Done
+        }
+        Err(error) => {
+            observer.on_event(&Event::Failed {
+                path: Some(&planned.source),
+                error: &error,
+            });
+            Status::Failed(error)
+        }
+    }
+}
+
+/// Plans and carries out the renames for every media file under `input_path`.
+/// Each file is changed as soon as its name is known.
+pub fn process(
+    input_path: &Path,
+    options: &ProcessingOptions<'_>,
+    observer: &dyn Observer,
+) -> Result<Report, Error> {
+    let mut journal = Journal::new(observer);
+    let mut outcomes = Vec::new();
+
+    plan_folder(input_path, options, observer, |step| {
+        outcomes.push(match step {
+            PlanStep::Rename(planned) => match changed_kept_copy(&planned, options.dry_run) {
+                Some(reason) => {
+                    observer.on_event(&Event::Skipped {
+                        path: &planned.source,
+                        reason,
+                    });
+                    skipped_outcome(planned.source, reason)
+                }
+                None => apply_planned(*planned, options.dry_run, &mut journal, observer),
+            },
+            PlanStep::Skip(source, reason) => skipped_outcome(source, reason),
+        })
+    })?;
+
+    Ok(Report {
+        outcomes,
+        journal: journal.path().map(Path::to_path_buf),
+    })
+}
+
+/// Works out the renames for every media file under `input_path` without
+/// changing anything.
+pub fn plan(
+    input_path: &Path,
+    options: &ProcessingOptions<'_>,
+    observer: &dyn Observer,
+) -> Result<Plan, Error> {
+    let mut plan = Plan::default();
+
+    plan_folder(input_path, options, observer, |step| match step {
+        PlanStep::Rename(planned) => plan.renames.push(*planned),
+        PlanStep::Skip(source, reason) => plan.skipped.push((source, reason)),
+    })?;
+
+    Ok(plan)
+}
+
+/// Carries out planned renames exactly as they stand, for example from a plan
+/// file written by `nf plan`, skipping entries whose source is gone or whose
+/// destination is already taken. Sidecars follow each file's destination,
+/// even when only the file's name was edited in the plan.
+pub fn apply(renames: Vec<PlannedRename>, dry_run: bool, observer: &dyn Observer) -> Report {
+    let mut journal = Journal::new(observer);
+    let outcomes = renames
+        .into_iter()
+        .map(|mut planned| {
+            follow_destination(&mut planned, observer);
+            if let Some(reason) = stale_plan_reason(&planned, dry_run) {
+                observer.on_event(&Event::Skipped {
+                    path: &planned.source,
+                    reason,
+                });
+                return skipped_outcome(planned.source, reason);
+            }
+            observer.on_event(&Event::Planned(&planned));
+            apply_planned(planned, dry_run, &mut journal, observer)
+        })
+        .collect();
+
+    Report {
+        outcomes,
+        journal: journal.path().map(Path::to_path_buf),
+    }
+}
+
+/// Gives the sidecars of a plan entry the name and folder of its destination,
+/// which also moves the XMP description along. Sidecars that do not belong to
+/// the source by name are left as written, and one whose new path is taken
+/// stays where it is.
+fn follow_destination(planned: &mut PlannedRename, observer: &dyn Observer) {
+    if !planned.action.writes_destination() || planned.sidecars.is_empty() {
+        return;
+    }
+    let (Some(folder), Some(name)) = (
+        planned.destination.parent(),
+        planned
+            .destination
+            .file_name()
+            .and_then(|name| name.to_str()),
+    ) else {
+        return;
+    };
+
+    let sources: Vec<PathBuf> = planned
+        .sidecars
+        .iter()
+        .map(|sidecar| sidecar.source.clone())
+        .collect();
+    for sidecar in attach_sidecars(&[&planned.source], &sources).remove(0) {
+        let destination = folder.join(sidecar.renamed(name));
+        if let Some(planned_sidecar) = planned
+            .sidecars
+            .iter_mut()
+            .find(|planned_sidecar| planned_sidecar.source == sidecar.path)
+        {
+            planned_sidecar.destination = destination;
+        }
+    }
+
+    planned.sidecars.retain(|sidecar| {
+        let taken = sidecar.destination != sidecar.source && sidecar.destination.exists();
+        if taken {
+            observer.on_event(&Event::Warning {
+                path: Some(&sidecar.source),
+                message: &format!(
+                    "leaving sidecar in place, {} already exists",
+                    sidecar.destination.display()
+                ),
+            });
         }
+        !taken
+    });
+}
 
-        processed_count += 1;
+/// Explains why a planned rename can no longer be carried out as written.
+fn stale_plan_reason(planned: &PlannedRename, dry_run: bool) -> Option<SkipReason> {
+    if !planned.source.exists() {
+        Some(SkipReason::SourceMissing)
+    } else if planned.action.writes_destination()
+        && !planned.is_noop()
+        && planned.destination.exists()
+    {
+        Some(SkipReason::DestinationExists)
+    } else {
+        changed_kept_copy(planned, dry_run)
     }
+}
 
-    if cache_updated {
-        gps_cache.save();
+/// A duplicate is only deleted while the file it duplicates is still there
+/// with the same contents. A dry run accepts a kept copy that is missing,
+/// since the earlier rename that creates it was only previewed.
+fn changed_kept_copy(planned: &PlannedRename, dry_run: bool) -> Option<SkipReason> {
+    if planned.action != PlannedAction::Delete {
+        return None;
     }
+    let verified = match planned.reasons.duplicate_of.as_deref() {
+        Some(kept) if dry_run && !kept.exists() => true,
+        Some(kept) => kept != planned.source && same_contents(&planned.source, kept),
+        None => false,
+    };
+    (!verified).then_some(SkipReason::KeptCopyChanged)
+}
+
+/// Reverts the renames recorded in a journal, newest first. Defaults to the
+/// newest journal that has not been undone yet.
+pub fn undo_run(
+    journal_path: Option<&Path>,
+    dry_run: bool,
+    observer: &dyn Observer,
+) -> Result<UndoSummary, Error> {
+    journal::undo_journal(journal_path, dry_run, observer)
+}
+
+/// The rename journals kept from previous runs, oldest first.
+pub fn list_runs() -> Result<Vec<RunJournal>, Error> {
+    journal::list_journals()
 }
 
 fn resolve_gps_location(
-    exif_opt: &Option<::exif::Exif>,
-    cache: &mut GPSCache,
+    coordinates: Option<(f64, f64)>,
+    caches: &RunCaches,
+    pipeline: &Pipeline<'_>,
 ) -> (Option<String>, bool) {
-    exif_opt
-        .as_ref()
-        .and_then(extract_gps_coordinates)
-        .map(|(lat, lon)| gps_to_place(lat, lon, cache))
+    coordinates
+        .map(|(lat, lon)| {
+            // Named places never reach the geocoder, so their coordinates stay private.
+            if let Some(name) = pipeline.places.find(lat, lon) {
+                return (Some(name.to_string()), false);
+            }
+            gps_to_place(
+                lat,
+                lon,
+                &caches.gps,
+                &pipeline.geocoder,
+                &pipeline.geo_limit,
+                pipeline.observer,
+            )
+        })
         .unwrap_or((None, false))
 }
 
-fn generate_ai_content(
-    path: &Path,
-    ai_model: &str,
-    ai_max_chars: u32,
-    ai_case: &str,
-    ai_language: &str,
-) -> Option<String> {
-    get_ai_content_name(path, ai_model, ai_max_chars, ai_case, ai_language).or_else(|| {
-        eprintln!(
-            "{} {}{}  {}",
-            "⚠️".bright_yellow(),
-            "Failed to get AI content analysis for ".bright_yellow(),
-            path.display().to_string().bright_white(),
-            "using filename fallback".bright_yellow()
-        );
-        None
-    })
+/// XMP from the file's `.xmp` sidecar, completed by the packet embedded in
+/// the file itself. Sidecar values win because editors write their changes
+/// there.
+fn read_xmp_metadata(media_file: &MediaFile) -> XmpMetadata {
+    let sidecar = media_file
+        .sidecars
+        .iter()
+        .filter(|sidecar| sidecar.is_xmp())
+        .find_map(|sidecar| read_xmp_file(&sidecar.path))
+        .unwrap_or_default();
+    let embedded = match media_file.kind {
+        MediaKind::Image => read_embedded_xmp(&media_file.path).unwrap_or_default(),
+        MediaKind::Video => XmpMetadata::default(),
+    };
+    sidecar.or(embedded)
+}
+
+/// Capture times found outside EXIF and the video container.
+struct EmbeddedDates {
+    xmp: Option<CaptureTime>,
+    sidecar: Option<CaptureTime>,
 }
 
-fn get_media_date_string(
+/// Capture time from, in order: EXIF or the video container, XMP, a JSON
+/// sidecar, then the file system.
+fn get_media_date_time(
     media_kind: MediaKind,
     path: &Path,
     exif_opt: &Option<::exif::Exif>,
-    date_only: bool,
+    video_metadata: &Option<VideoMetadata>,
+    other_dates: EmbeddedDates,
     use_file_date: bool,
     prefer_modified: bool,
-) -> Option<String> {
+) -> Option<(CaptureTime, DateSource)> {
+    let has_embedded_date = match media_kind {
+        MediaKind::Image => exif_opt.as_ref().and_then(try_parse_exif_date).is_some(),
+        MediaKind::Video => video_metadata
+            .as_ref()
+            .is_some_and(|meta| meta.created_at.is_some()),
+    };
+    if !use_file_date && !has_embedded_date {
+        let other = (other_dates.xmp.map(|date| (date, DateSource::Xmp)))
+            .or(other_dates.sidecar.map(|date| (date, DateSource::Sidecar)));
+        if other.is_some() {
+            return other;
+        }
+    }
+
     if media_kind == MediaKind::Video {
-        return get_file_date_string(path, date_only, prefer_modified);
+        return get_video_date_time(path, video_metadata, use_file_date, prefer_modified);
     }
 
-    get_date_string(path, exif_opt, date_only, use_file_date, prefer_modified)
+    get_date_time(path, exif_opt, use_file_date, prefer_modified)
 }
 
-fn resolve_content_part(
+fn get_video_date_time(
+    path: &Path,
+    video_metadata: &Option<VideoMetadata>,
+    use_file_date: bool,
+    prefer_modified: bool,
+) -> Option<(CaptureTime, DateSource)> {
+    if use_file_date {
+        return get_file_date_time(path, prefer_modified);
+    }
+
+    if let Some(created_at) = video_metadata.as_ref().and_then(|meta| meta.created_at) {
+        return Some((created_at, DateSource::Container));
+    }
+
+    get_file_date_time(path, prefer_modified)
+}
+
+#[derive(Debug)]
+struct ContentParts {
+    place: Option<String>,
+    ai: Option<String>,
+    content: String,
+    gps_cache_updated: bool,
+}
+
+/// Resolves only the naming inputs the templates actually refer to, so GPS and
+/// AI lookups are skipped when their tokens are unused.
+fn resolve_content_parts(
     media_file: &MediaFile,
-    cache: &mut GPSCache,
+    caches: &RunCaches,
+    pipeline: &Pipeline<'_>,
     options: &ProcessingOptions<'_>,
-    exif_opt: &Option<::exif::Exif>,
-) -> (String, bool) {
-    if options.ai_content {
-        return resolve_ai_content_part(media_file, options);
-    }
-
-    if media_file.kind == MediaKind::Image {
-        let (place, updated) = resolve_gps_location(exif_opt, cache);
-        let content =
-            place.unwrap_or_else(|| fallback_name_from_path(&media_file.path, media_file.kind));
-        return (
-            sanitize_content_or_fallback(&content, &media_file.path, media_file.kind),
-            updated,
-        );
+    coordinates: Option<(f64, f64)>,
+    title: Option<&str>,
+) -> ContentParts {
+    let ai = pipeline.ai_provider.as_deref().and_then(|ai_provider| {
+        pipeline.timed(Step::AiNaming, Some(&media_file.path), || {
+            resolve_ai_name(media_file, ai_provider, caches, pipeline, options)
+        })
+    });
+    if let Some(name) = &ai {
+        pipeline.emit(Event::AiNamed {
+            path: &media_file.path,
+            name,
+        });
     }
 
-    (
-        fallback_name_from_path(&media_file.path, media_file.kind),
-        false,
-    )
+    let (place, gps_cache_updated) =
+        if coordinates.is_some() && pipeline.layout.needs_place(options.ai_content) {
+            pipeline.timed(Step::Geocoding, Some(&media_file.path), || {
+                resolve_gps_location(coordinates, caches, pipeline)
+            })
+        } else {
+            (None, false)
+        };
+    let place = place.and_then(|place| sanitize_non_empty(&place));
+    if let Some((lat, lon)) =
+        coordinates.filter(|_| pipeline.layout.needs_place(options.ai_content))
+    {
+        pipeline.emit(Event::GpsResolved {
+            path: &media_file.path,
+            lat,
+            lon,
+            place: place.as_deref(),
+        });
+    }
+
+    let content = if options.ai_content {
+        ai.clone()
+    } else {
+        place.clone()
+    }
+    .or_else(|| title.map(str::to_string))
+    .unwrap_or_else(|| fallback_name_from_path(&media_file.path, media_file.kind));
+
+    ContentParts {
+        place,
+        ai,
+        content,
+        gps_cache_updated,
+    }
 }
 
-fn resolve_ai_content_part(
+fn resolve_ai_name(
     media_file: &MediaFile,
+    ai_provider: &dyn AiProvider,
+    caches: &RunCaches,
+    pipeline: &Pipeline<'_>,
     options: &ProcessingOptions<'_>,
-) -> (String, bool) {
+) -> Option<String> {
     if media_file.kind == MediaKind::Video {
-        eprintln!(
-            "{} {}{}",
-            "⚠️".bright_yellow(),
-            "AI content analysis currently supports still images only; using filename fallback for "
-                .bright_yellow(),
-            media_file.path.display().to_string().bright_white()
-        );
-        return (
-            fallback_name_from_path(&media_file.path, media_file.kind),
-            false,
-        );
+        pipeline.emit(Event::AiFallback {
+            path: &media_file.path,
+            reason:
+                "AI content analysis currently supports still images only, using filename fallback",
+        });
+        return None;
     }
 
-    let content = generate_ai_content(
+    get_ai_content_name(
         &media_file.path,
-        options.ai_model,
+        &pipeline.ai_backend(ai_provider, caches),
         options.ai_max_chars,
         options.ai_case,
         options.ai_language,
     )
-    .unwrap_or_else(|| fallback_name_from_path(&media_file.path, media_file.kind));
-
-    (
-        sanitize_content_or_fallback(&content, &media_file.path, media_file.kind),
-        false,
-    )
+    .or_else(|| {
+        pipeline.emit(Event::AiFallback {
+            path: &media_file.path,
+            reason: "no AI content analysis, using filename fallback",
+        });
+        None
+    })
+    .and_then(|content| sanitize_non_empty(&content))
 }
 
-fn sanitize_content_or_fallback(content: &str, path: &Path, media_kind: MediaKind) -> String {
-    let sanitized = sanitize_filename_fragment(content);
-    if sanitized.is_empty() {
-        return fallback_name_from_path(path, media_kind);
-    }
-
-    sanitized
+fn sanitize_non_empty(content: &str) -> Option<String> {
+    Some(sanitize_filename_fragment(content)).filter(|sanitized| !sanitized.is_empty())
 }
 
 fn fallback_name_from_path(path: &Path, media_kind: MediaKind) -> String {
@@ -437,12 +1985,6 @@ fn is_generic_camera_stem(stem: &str) -> bool {
     })
 }
 
-fn create_base_filename(date_fmt: Option<String>, content_part: String) -> String {
-    date_fmt
-        .map(|date| format!("{}_{}", date, content_part))
-        .unwrap_or(content_part)
-}
-
 fn get_base_folder(input_path: &Path) -> &Path {
     if input_path.is_dir() {
         input_path
@@ -451,137 +1993,761 @@ fn get_base_folder(input_path: &Path) -> &Path {
     }
 }
 
+/// Where renamed files go: next to the originals, or into an import library.
+enum Placement {
+    /// Rename under the input's base folder, keeping unorganized files in place.
+    InPlace(PathBuf),
+    /// Copy into a library folder; unorganized files go to its top level.
+    Library(PathBuf),
+}
+
 fn get_target_folder(
     original_path: &Path,
-    base_folder: &Path,
-    date_folder: Option<&str>,
-    organize_by_date: bool,
+    placement: &Placement,
+    target_subfolder: Option<&Path>,
+    organize_folders: bool,
 ) -> PathBuf {
-    if organize_by_date {
-        if let Some(date_folder) = date_folder {
-            return base_folder.join(date_folder);
+    let base_folder = match placement {
+        Placement::InPlace(base_folder) | Placement::Library(base_folder) => base_folder,
+    };
+    if organize_folders {
+        if let Some(target_subfolder) = target_subfolder {
+            return base_folder.join(target_subfolder);
         }
     }
 
-    original_path.parent().unwrap_or(base_folder).to_path_buf()
+    match placement {
+        Placement::InPlace(_) => original_path.parent().unwrap_or(base_folder).to_path_buf(),
+        Placement::Library(library) => library.clone(),
+    }
 }
 
-fn get_target_path(
-    original_path: &Path,
-    base_folder: &Path,
-    date_folder: Option<&str>,
-    new_name: &str,
-    organize_by_date: bool,
-) -> PathBuf {
-    if organize_by_date {
-        if let Some(date_folder) = date_folder {
-            return create_date_folder_path(base_folder, date_folder, new_name);
+/// Copies a file, checks the copy against the source's SHA-256 and, for
+/// `CopyAndDelete`, removes the source afterwards. A copy that does not match
+/// is removed again and the source is always kept.
+fn execute_copy(
+    planned: &PlannedRename,
+    journal: &mut Journal,
+    observer: &dyn Observer,
+) -> Result<(), Error> {
+    let (source, destination) = (&planned.source, &planned.destination);
+    let mut created_dirs = Vec::new();
+    if let Some(parent) = destination.parent() {
+        if !parent.exists() {
+            created_dirs = missing_ancestors(parent);
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
         }
     }
 
-    original_path.with_file_name(new_name)
+    transfer_copy(
+        planned.action,
+        source,
+        destination,
+        created_dirs,
+        journal,
+        observer,
+    )
 }
 
-fn print_skip_info(path: &Path) {
-    println!(
-        "{}  {}{}",
-        "ℹ️".bright_cyan(),
-        "No rename needed for ".bright_cyan(),
-        path.display().to_string().bright_white()
-    );
+/// The copy, verify and journal steps shared by imported files and their
+/// sidecars. A verified copy counts as done even if its source cannot be
+/// deleted afterwards; that only earns a warning.
+fn transfer_copy(
+    action: PlannedAction,
+    source: &Path,
+    destination: &Path,
+    created_dirs: Vec<PathBuf>,
+    journal: &mut Journal,
+    observer: &dyn Observer,
+) -> Result<(), Error> {
+    fs::copy(source, destination).map_err(|error| Error::io(source, error))?;
+    // Keep the capture-time fallback intact for files without metadata.
+    if let Ok(modified) = fs::metadata(source).and_then(|metadata| metadata.modified()) {
+        let _ = fs::File::options()
+            .write(true)
+            .open(destination)
+            .and_then(|file| file.set_modified(modified));
+    }
+
+    let hash = |path: &Path| hash_file_contents(path).map_err(|error| Error::io(path, error));
+    if hash(source)? != hash(destination)? {
+        let _ = fs::remove_file(destination);
+        return Err(Error::ChecksumMismatch(destination.to_path_buf()));
+    }
+
+    // Journal the copy before touching the source, so an interrupted run
+    // can still be undone.
+    journal.record_copy(source, destination, created_dirs);
+    if action == PlannedAction::CopyAndDelete {
+        match fs::remove_file(source) {
+            // A verified copy plus delete is a move; undo copies the file back.
+            Ok(()) => journal.record(source, destination, Vec::new()),
+            Err(error) => observer.on_event(&Event::Warning {
+                path: Some(source),
+                message: &format!("copy verified, but the source was not deleted: {}", error),
+            }),
+        }
+    }
+    Ok(())
 }
 
-fn print_dry_run_info(original_path: &Path, new_path: &Path) {
-    println!(
-        "{}  {}{} {} {}",
-        "💁".bright_yellow(),
-        "Dry run: ".bright_yellow().bold(),
-        original_path.display().to_string().bright_white(),
-        "→".bright_yellow(),
-        new_path.display().to_string().bright_green()
-    );
+/// Moves or copies the sidecars of a file that was just renamed or imported.
+/// A sidecar that fails is reported and left where it is.
+fn transfer_sidecars(planned: &PlannedRename, journal: &mut Journal, observer: &dyn Observer) {
+    for sidecar in planned.moved_sidecars() {
+        let result = if planned.action == PlannedAction::Rename {
+            fs::rename(&sidecar.source, &sidecar.destination)
+                .map(|()| journal.record(&sidecar.source, &sidecar.destination, Vec::new()))
+                .map_err(|error| Error::io(&sidecar.source, error))
+        } else {
+            transfer_copy(
+                planned.action,
+                &sidecar.source,
+                &sidecar.destination,
+                Vec::new(),
+                journal,
+                observer,
+            )
+        };
+
+        match result {
+            Ok(()) => observer.on_event(&Event::SidecarMoved {
+                source: &sidecar.source,
+                destination: &sidecar.destination,
+            }),
+            Err(error) => observer.on_event(&Event::Failed {
+                path: Some(&sidecar.source),
+                error: &error,
+            }),
+        }
+    }
+}
+
+/// Sends the sidecars of a deleted duplicate to the trash after it.
+fn trash_sidecars(planned: &PlannedRename, journal: &mut Journal, observer: &dyn Observer) {
+    for sidecar in &planned.sidecars {
+        match journal.trash(&sidecar.source) {
+            Ok(trashed) => observer.on_event(&Event::SidecarMoved {
+                source: &sidecar.source,
+                destination: &trashed,
+            }),
+            Err(error) => observer.on_event(&Event::Failed {
+                path: Some(&sidecar.source),
+                error: &error,
+            }),
+        }
+    }
 }
 
-fn execute_rename(original_path: &Path, new_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+fn write_planned_xmp(planned: &PlannedRename, journal: &mut Journal, observer: &dyn Observer) {
+    let Some(update) = &planned.xmp else {
+        return;
+    };
+    let path = planned.xmp_target(planned.action == PlannedAction::Rename);
+
+    // An existing sidecar is backed up first, so undo restores it as it was.
+    let written = if path.exists() {
+        journal.replace(&path, |path| write_xmp_sidecar(path, update).map(drop))
+    } else {
+        write_xmp_sidecar(&path, update)
+            .map(|_| journal.record_created(&path))
+            .map_err(|error| Error::io(&path, error))
+    };
+
+    match written {
+        Ok(()) => observer.on_event(&Event::XmpWritten { path: &path }),
+        Err(error) => observer.on_event(&Event::Failed {
+            path: Some(&path),
+            error: &error,
+        }),
+    }
+}
+
+fn execute_rename(
+    original_path: &Path,
+    new_path: &Path,
+    journal: &mut Journal,
+) -> Result<(), Error> {
+    let mut created_dirs = Vec::new();
     if let Some(parent) = new_path.parent() {
         if !parent.exists() {
-            fs::create_dir_all(parent).inspect_err(|error| {
-                eprintln!(
-                    "{} {}{}{}  {}{}",
-                    "❌".bright_red(),
-                    "Failed to create directory ".bright_red(),
-                    parent.display().to_string().bright_white(),
-                    ": ".bright_red(),
-                    "Error: ".bright_red(),
-                    error.to_string().bright_white()
-                );
-            })?;
+            created_dirs = missing_ancestors(parent);
+            fs::create_dir_all(parent).map_err(|error| Error::io(parent, error))?;
         }
     }
 
-    println!(
-        "{}  {}{} {} {}",
-        "🔄".bright_green(),
-        "Renaming: ".bright_green(),
-        original_path.display().to_string().bright_white(),
-        "→".bright_green(),
-        new_path.display().to_string().bright_green().bold()
-    );
+    fs::rename(original_path, new_path).map_err(|error| Error::io(original_path, error))?;
+    journal.record(original_path, new_path, created_dirs);
+    Ok(())
+}
 
-    fs::rename(original_path, new_path)
-        .map_err(|error| Box::new(error) as Box<dyn std::error::Error>)?;
+/// What a file's own metadata and its sidecars say, read once per file.
+struct FileFacts {
+    exif: Option<::exif::Exif>,
+    xmp: XmpMetadata,
+    dated: Option<(CaptureTime, DateSource)>,
+    time_shift: Option<TimeShift>,
+    /// The capture time in the run's time zone, with any clock correction.
+    captured_at: Option<NaiveDateTime>,
+    coordinates: Option<(f64, f64)>,
+}
 
-    println!(
-        "{} {}{} {} {}",
-        "✅".bright_green(),
-        "Successfully renamed: ".bright_green(),
-        original_path.display().to_string().bright_white(),
-        "→".bright_green(),
-        new_path.display().to_string().bright_green().bold()
-    );
+fn read_file_facts(
+    media_file: &MediaFile,
+    pipeline: &Pipeline<'_>,
+    options: &ProcessingOptions<'_>,
+) -> FileFacts {
+    let (exif_opt, video_metadata, xmp_metadata, sidecar_metadata, dated) =
+        pipeline.timed(Step::Metadata, Some(&media_file.path), || {
+            let (exif_opt, video_metadata) = match media_file.kind {
+                MediaKind::Image => (read_exif_data(&media_file.path), None),
+                MediaKind::Video => (None, read_video_metadata(&media_file.path)),
+            };
+            let xmp_metadata = read_xmp_metadata(media_file);
+            let sidecar_metadata = read_sidecar_metadata(&media_file.sidecars);
+            let dated = get_media_date_time(
+                media_file.kind,
+                &media_file.path,
+                &exif_opt,
+                &video_metadata,
+                EmbeddedDates {
+                    xmp: xmp_metadata.taken_at,
+                    sidecar: sidecar_metadata.as_ref().and_then(|meta| meta.taken_at),
+                },
+                options.use_file_date,
+                options.prefer_modified,
+            );
+            (
+                exif_opt,
+                video_metadata,
+                xmp_metadata,
+                sidecar_metadata,
+                dated,
+            )
+        });
+    pipeline.emit(Event::Dated {
+        path: &media_file.path,
+        source: dated.map(|(_, source)| source),
+    });
+    if !options.use_file_date
+        && dated.is_some_and(|(_, source)| {
+            matches!(source, DateSource::FileCreated | DateSource::FileModified)
+        })
+    {
+        let missing = match (media_file.kind, &exif_opt) {
+            (MediaKind::Video, _) => "no container creation date",
+            (MediaKind::Image, None) => "no EXIF data",
+            (MediaKind::Image, Some(_)) => "no EXIF DateTimeOriginal",
+        };
+        pipeline.warn(
+            &media_file.path,
+            &format!("{}, falling back to file time", missing),
+        );
+    }
+    let time_shift = exif_opt
+        .as_ref()
+        .and_then(|exif| pipeline.clock_rules.shift_for(&get_camera_identity(exif)))
+        .or(options.time_shift)
+        .filter(|shift| !shift.is_zero());
+    let captured_at = dated.map(|(capture_time, _)| {
+        let date_time = capture_time.in_zone(options.timezone);
+        time_shift.map_or(date_time, |shift| shift.apply(date_time))
+    });
+    let coordinates = exif_opt
+        .as_ref()
+        .and_then(extract_gps_coordinates)
+        .or_else(|| video_metadata.as_ref().and_then(|meta| meta.gps))
+        .or(xmp_metadata.gps)
+        .or_else(|| sidecar_metadata.as_ref().and_then(|meta| meta.gps))
+        .or_else(|| {
+            let track = pipeline.gpx_track.as_ref()?;
+            let (capture_time, _) = dated?;
+            let utc = capture_time.in_zone(TimeZoneMode::Utc);
+            let utc = [time_shift, options.gpx_offset]
+                .into_iter()
+                .flatten()
+                .fold(utc, |time, shift| shift.apply(time));
+            track.position_at(utc, options.gpx_max_gap)
+        });
+
+    FileFacts {
+        exif: exif_opt,
+        xmp: xmp_metadata,
+        dated,
+        time_shift,
+        captured_at,
+        coordinates,
+    }
+}
 
-    Ok(())
+/// Whether a capture time passes `since`/`until`. Without a range every file
+/// does; with one, files without a capture time do not.
+fn in_date_range(options: &ProcessingOptions<'_>, captured_at: Option<NaiveDateTime>) -> bool {
+    if options.since.is_none() && options.until.is_none() {
+        return true;
+    }
+    captured_at.is_some_and(|date_time| {
+        options.since.is_none_or(|since| date_time >= since)
+            && options.until.is_none_or(|until| date_time <= until)
+    })
 }
 
 fn build_rename_plan(
     media_file: &MediaFile,
-    cache: &mut GPSCache,
+    facts: FileFacts,
+    caches: &RunCaches,
+    pipeline: &Pipeline<'_>,
     options: &ProcessingOptions<'_>,
-) -> Option<RenamePlan> {
-    let exif_opt = if media_file.kind == MediaKind::Image {
-        read_exif_data(&media_file.path)
-    } else {
-        None
-    };
-    let date_prefix = if options.no_date {
-        None
-    } else {
-        get_media_date_string(
-            media_file.kind,
-            &media_file.path,
-            &exif_opt,
-            options.date_only,
-            options.use_file_date,
-            options.prefer_modified,
-        )
-    };
-    let date_folder = get_media_date_string(
-        media_file.kind,
-        &media_file.path,
-        &exif_opt,
-        true,
-        options.use_file_date,
-        options.prefer_modified,
+    seq: usize,
+) -> Result<RenamePlan, SkipReason> {
+    let layout = &pipeline.layout;
+    let FileFacts {
+        exif: exif_opt,
+        xmp: xmp_metadata,
+        dated,
+        time_shift,
+        captured_at,
+        coordinates,
+    } = facts;
+    let title = xmp_metadata.title.as_deref().and_then(sanitize_non_empty);
+    let keywords = sanitize_non_empty(
+        &xmp_metadata
+            .keywords
+            .iter()
+            .take(MAX_KEYWORDS_IN_NAME)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    let extension = media_file
+        .path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .ok_or(SkipReason::NoExtension)?
+        .to_string();
+    let parts = resolve_content_parts(
+        media_file,
+        caches,
+        pipeline,
+        options,
+        coordinates,
+        title.as_deref(),
     );
-    let extension = media_file.path.extension()?.to_str()?.to_string();
-    let (content_part, gps_cache_updated) =
-        resolve_content_part(media_file, cache, options, &exif_opt);
-    let base_name = create_base_filename(date_prefix, content_part);
+    let xmp_update = pipeline
+        .ai_provider
+        .as_deref()
+        .filter(|_| options.write_xmp && media_file.kind == MediaKind::Image)
+        .and_then(|ai_provider| {
+            pipeline.timed(Step::AiDescription, Some(&media_file.path), || {
+                get_ai_description(
+                    &media_file.path,
+                    &pipeline.ai_backend(ai_provider, caches),
+                    options.ai_language,
+                )
+            })
+        });
+
+    let context = NamingContext {
+        captured_at,
+        date: captured_at
+            .as_ref()
+            .filter(|_| !options.no_date)
+            .map(|date_time| format_date_time(date_time, options.date_only, options.subseconds)),
+        time: captured_at.as_ref().map(|date_time| {
+            let format = if options.subseconds {
+                "%H-%M-%S-%3f"
+            } else {
+                "%H-%M-%S"
+            };
+            date_time.format(format).to_string()
+        }),
+        place: parts.place.clone(),
+        ai: parts.ai.clone(),
+        camera: exif_opt
+            .as_ref()
+            .and_then(get_camera_name)
+            .and_then(|camera| sanitize_non_empty(&camera)),
+        title,
+        keywords,
+        original_stem: media_file
+            .path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(sanitize_filename_fragment)
+            .unwrap_or_default(),
+        kind: media_file.kind.label(),
+        seq,
+        content: parts.content,
+    };
+    let mut base_name = layout.name.render(&context);
+    if base_name.is_empty() {
+        base_name = media_file.kind.fallback_label().to_string();
+    }
 
-    Some(RenamePlan {
+    // Folder names always use the plain date, whatever the filename date format is.
+    let target_subfolder = layout.folder.as_ref().and_then(|folder| {
+        folder.render(&NamingContext {
+            date: captured_at
+                .as_ref()
+                .map(|date_time| format_date_time(date_time, true, false)),
+            ..context.clone()
+        })
+    });
+
+    Ok(RenamePlan {
         base_name,
         extension,
-        date_folder,
-        gps_cache_updated,
+        target_subfolder,
+        group_folder: None,
+        gps_cache_updated: parts.gps_cache_updated,
+        xmp_update,
+        reasons: RenameReasons {
+            date_source: dated.map(|(_, source)| source),
+            captured_at,
+            time_shift,
+            place: parts.place,
+            ai_name: parts.ai,
+            collision_suffix: None,
+            duplicate_of: None,
+            similar_group: None,
+            named_after: None,
+        },
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+    use std::sync::Once;
+
+    /// Points HOME at a scratch folder, so the journals and caches these tests
+    /// write stay out of the real home folder.
+    fn isolate_home() {
+        static HOME: Once = Once::new();
+        HOME.call_once(|| std::env::set_var("HOME", create_temp_dir("home")));
+    }
+
+    /// Writes a file with an XMP sidecar (`name.xmp`) that dates it.
+    fn dated_file(path: &Path, contents: &[u8], date: &str) {
+        fs::write(path, contents).unwrap();
+        date_sidecar(path, date);
+    }
+
+    fn date_sidecar(path: &Path, date: &str) {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".xmp");
+        fs::write(
+            sidecar,
+            format!("<x:xmpmeta><exif:DateTimeOriginal>{date}</exif:DateTimeOriginal></x:xmpmeta>"),
+        )
+        .unwrap();
+    }
+
+    fn file_names(renames: &[PlannedRename]) -> Vec<(String, String)> {
+        let name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+        renames
+            .iter()
+            .map(|planned| (name(&planned.source), name(&planned.destination)))
+            .collect()
+    }
+
+    #[test]
+    fn plan_and_apply_rename_files_with_their_sidecars() {
+        isolate_home();
+        let dir = create_temp_dir("plan");
+        dated_file(&dir.join("IMG_0001.jpg"), b"one", "2024-05-01T10:00:00");
+        dated_file(&dir.join("IMG_0002.jpg"), b"two", "2023-01-01T10:00:00");
+        let options = ProcessingOptions::default();
+
+        let media_files = discover(&dir, &options.filter).unwrap();
+        assert_eq!(media_files.len(), 2);
+        assert_eq!(media_files[0].sidecars().count(), 1);
+
+        let plan = plan(&dir, &options, &()).unwrap();
+        assert_eq!(
+            file_names(&plan.renames),
+            [
+                ("IMG_0001.jpg".into(), "2024-05-01_photo.jpg".into()),
+                ("IMG_0002.jpg".into(), "2023-01-01_photo.jpg".into()),
+            ]
+        );
+        assert_eq!(plan.renames[0].reasons.date_source, Some(DateSource::Xmp));
+        assert_eq!(
+            plan.renames[0].sidecars[0].destination,
+            dir.join("2024-05-01_photo.jpg.xmp")
+        );
+
+        let dry_run = ProcessingOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = process(&dir, &dry_run, &()).unwrap();
+        assert!(report
+            .outcomes
+            .iter()
+            .all(|outcome| matches!(outcome.status, Status::DryRun)));
+        assert!(report.journal.is_none());
+        assert!(dir.join("IMG_0001.jpg").exists());
+
+        let report = apply(plan.renames, false, &());
+        assert!(report
+            .outcomes
+            .iter()
+            .all(|outcome| matches!(outcome.status, Status::Done)));
+        assert!(report.journal.is_some());
+        assert!(dir.join("2024-05-01_photo.jpg").exists());
+        assert!(dir.join("2024-05-01_photo.jpg.xmp").exists());
+        assert!(!dir.join("IMG_0001.jpg").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn date_range_leaves_other_files_alone() {
+        isolate_home();
+        let dir = create_temp_dir("since");
+        dated_file(&dir.join("IMG_0001.jpg"), b"one", "2024-05-01T10:00:00");
+        dated_file(&dir.join("IMG_0002.jpg"), b"two", "2023-01-01T10:00:00");
+        let options = ProcessingOptions {
+            since: filter::parse_since("2024-01-01").ok(),
+            until: filter::parse_until("2024-12-31").ok(),
+            ..Default::default()
+        };
+
+        let plan = plan(&dir, &options, &()).unwrap();
+        assert_eq!(
+            file_names(&plan.renames),
+            [("IMG_0001.jpg".into(), "2024-05-01_photo.jpg".into())]
+        );
+        assert_eq!(
+            plan.skipped,
+            [(dir.join("IMG_0002.jpg"), SkipReason::OutsideDateRange)]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn duplicates_are_removed_and_pairs_share_a_name() {
+        isolate_home();
+        let dir = create_temp_dir("duplicates");
+        // The JPEG has no date of its own; the RAW's sidecar dates the pair.
+        fs::write(dir.join("DSC_0001.JPG"), b"jpeg").unwrap();
+        dated_file(&dir.join("DSC_0001.NEF"), b"raw", "2024-05-01T10:00:00");
+        fs::write(dir.join("IMG_0002.JPG"), b"jpeg").unwrap();
+        let options = ProcessingOptions {
+            duplicates: Some(DuplicateMode::Delete),
+            ..Default::default()
+        };
+
+        let plan = plan(&dir, &options, &()).unwrap();
+        assert_eq!(
+            file_names(&plan.renames),
+            [
+                ("DSC_0001.JPG".into(), "2024-05-01_photo.JPG".into()),
+                ("DSC_0001.NEF".into(), "2024-05-01_photo.NEF".into()),
+                ("IMG_0002.JPG".into(), "IMG_0002.JPG".into()),
+            ]
+        );
+        assert_eq!(
+            plan.renames[0].reasons.named_after,
+            Some(dir.join("DSC_0001.NEF"))
+        );
+        let duplicate = &plan.renames[2];
+        assert_eq!(duplicate.action, PlannedAction::Delete);
+        assert_eq!(
+            duplicate.reasons.duplicate_of,
+            Some(dir.join("2024-05-01_photo.JPG"))
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn similar_photos_are_numbered_in_one_group() {
+        isolate_home();
+        let dir = create_temp_dir("similar");
+        let gradient = image::GrayImage::from_fn(90, 80, |x, _| image::Luma([255 - (x * 2) as u8]));
+        let flipped = image::imageops::flip_horizontal(&gradient);
+        for (name, image) in [
+            ("a.png", &gradient),
+            ("b.png", &gradient),
+            ("c.png", &flipped),
+        ] {
+            image.save(dir.join(name)).unwrap();
+            date_sidecar(&dir.join(name), "2024-05-01T10:00:00");
+        }
+        let options = ProcessingOptions {
+            group_similar: true,
+            ..Default::default()
+        };
+
+        let plan = plan(&dir, &options, &()).unwrap();
+        assert_eq!(
+            file_names(&plan.renames),
+            [
+                ("a.png".into(), "2024-05-01_a_01.png".into()),
+                ("b.png".into(), "2024-05-01_a_02.png".into()),
+                ("c.png".into(), "2024-05-01_c.png".into()),
+            ]
+        );
+        assert_eq!(plan.renames[1].reasons.similar_group, Some(1));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_copies_and_verifies_before_deleting_the_source() {
+        isolate_home();
+        let card = create_temp_dir("card");
+        let library = create_temp_dir("library");
+        dated_file(&card.join("IMG_0001.jpg"), b"one", "2024-05-01T10:00:00");
+        let mut options = ProcessingOptions {
+            import_to: Some(&library),
+            duplicates: Some(DuplicateMode::Skip),
+            ..Default::default()
+        };
+
+        let report = process(&card, &options, &()).unwrap();
+        assert!(matches!(report.outcomes[0].status, Status::Done));
+        assert_eq!(
+            fs::read(library.join("2024-05-01_photo.jpg")).unwrap(),
+            b"one"
+        );
+        assert!(library.join("2024-05-01_photo.jpg.xmp").exists());
+        assert!(card.join("IMG_0001.jpg").exists());
+
+        // The copy is already there, so a second import with delete only
+        // skips it and leaves the card alone.
+        options.delete_source_after_verify = true;
+        let report = process(&card, &options, &()).unwrap();
+        assert!(matches!(
+            report.outcomes[0].status,
+            Status::Skipped(SkipReason::Duplicate)
+        ));
+        fs::remove_dir_all(&library).unwrap();
+        fs::create_dir_all(&library).unwrap();
+
+        let report = process(&card, &options, &()).unwrap();
+        assert!(matches!(report.outcomes[0].status, Status::Done));
+        assert!(library.join("2024-05-01_photo.jpg").exists());
+        assert!(!card.join("IMG_0001.jpg").exists());
+
+        fs::remove_dir_all(card).unwrap();
+        fs::remove_dir_all(library).unwrap();
+    }
+
+    #[test]
+    fn apply_moves_sidecars_to_an_edited_destination() {
+        isolate_home();
+        let dir = create_temp_dir("apply");
+        fs::write(dir.join("IMG_0001.jpg"), b"photo").unwrap();
+        fs::write(dir.join("IMG_0001.xmp"), b"<x:xmpmeta/>").unwrap();
+        // The plan said 2024-05-01_paris; the reviewer renamed only the photo.
+        let planned = PlannedRename {
+            source: dir.join("IMG_0001.jpg"),
+            destination: dir.join("Trip").join("eiffel.jpg"),
+            action: PlannedAction::Rename,
+            reasons: RenameReasons::default(),
+            sidecars: vec![SidecarMove {
+                source: dir.join("IMG_0001.xmp"),
+                destination: dir.join("2024-05-01_paris.xmp"),
+            }],
+            xmp: None,
+        };
+
+        let report = apply(vec![planned], false, &());
+
+        assert!(matches!(report.outcomes[0].status, Status::Done));
+        assert!(dir.join("Trip").join("eiffel.jpg").exists());
+        assert!(dir.join("Trip").join("eiffel.xmp").exists());
+        assert!(!dir.join("2024-05-01_paris.xmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pair_leader_is_chosen_from_metadata() {
+        let facts = |source: Option<DateSource>, coordinates: Option<(f64, f64)>| FileFacts {
+            exif: None,
+            xmp: XmpMetadata::default(),
+            dated: source.map(|source| (CaptureTime::Floating(NaiveDateTime::default()), source)),
+            time_shift: None,
+            captured_at: None,
+            coordinates,
+        };
+        let media = |path: &str, kind| MediaFile {
+            path: PathBuf::from(path),
+            kind,
+            sidecars: Vec::new(),
+        };
+        let jpeg = media("DSC_0001.JPG", MediaKind::Image);
+        let raw = media("DSC_0001.NEF", MediaKind::Image);
+        let clip = media("IMG_0001.MOV", MediaKind::Video);
+
+        // A date from the file itself beats a location, which beats the first file.
+        let unit = [&jpeg, &raw];
+        let jpeg_facts = facts(Some(DateSource::FileModified), Some((48.86, 2.29)));
+        assert_eq!(
+            pair_leader(&unit, &[jpeg_facts, facts(Some(DateSource::Exif), None)]),
+            1
+        );
+        let jpeg_facts = facts(None, Some((48.86, 2.29)));
+        assert_eq!(pair_leader(&unit, &[jpeg_facts, facts(None, None)]), 0);
+        assert_eq!(
+            pair_leader(&unit, &[facts(None, None), facts(None, None)]),
+            0
+        );
+        // Photos lead videos with equally good metadata.
+        let unit = [&clip, &jpeg];
+        let container = facts(Some(DateSource::Container), None);
+        assert_eq!(
+            pair_leader(&unit, &[container, facts(Some(DateSource::Exif), None)]),
+            1
+        );
+    }
+
+    #[test]
+    fn xmp_description_goes_to_one_member_of_a_pair() {
+        fn member<'a>(files: &'a [MediaFile], name: &str) -> &'a MediaFile {
+            files
+                .iter()
+                .find(|file| file.path.file_name() == Some(name.as_ref()))
+                .unwrap()
+        }
+        let dir = create_temp_dir("xmp-holder");
+        for name in [
+            "DSC_0001.JPG",
+            "DSC_0001.NEF",
+            "IMG_0002.HEIC",
+            "IMG_0002.MOV",
+        ] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        let files = discover(&dir, &MediaFilter::default()).unwrap();
+        let (jpeg, raw) = (
+            member(&files, "DSC_0001.JPG"),
+            member(&files, "DSC_0001.NEF"),
+        );
+        let (still, clip) = (
+            member(&files, "IMG_0002.HEIC"),
+            member(&files, "IMG_0002.MOV"),
+        );
+
+        // The RAW, whichever member leads, and never the video of a Live Photo.
+        assert_eq!(xmp_holder(&[jpeg, raw], 0), 1);
+        assert_eq!(xmp_holder(&[clip, still], 0), 1);
+
+        // A member that already has a sidecar keeps the description there.
+        fs::write(dir.join("DSC_0001.JPG.xmp"), "<x:xmpmeta/>").unwrap();
+        let files = discover(&dir, &MediaFilter::default()).unwrap();
+        let (jpeg, raw) = (
+            member(&files, "DSC_0001.JPG"),
+            member(&files, "DSC_0001.NEF"),
+        );
+        assert_eq!(xmp_holder(&[jpeg, raw], 1), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,10 @@
-use clap::{ArgAction, Parser, Subcommand};
+mod progress;
+
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
 use colored::*;
-use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use progress::{OutputFormat, Progress};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 #[derive(Parser, Debug)]
 #[command(name = "nameforge", author, version, about = "Rename photos and videos by context", long_about = None)]
@@ -13,6 +16,26 @@ struct Args {
     #[arg(short, long, global = true)]
     input: Option<PathBuf>,
 
+    /// Apply the [profile.<name>] settings from the config files
+    #[arg(long, env = "NAMEFORGE_PROFILE", global = true)]
+    profile: Option<String>,
+
+    /// Ignore config files and NAMEFORGE_* environment settings
+    #[arg(long, default_value_t = false, global = true)]
+    no_config: bool,
+
+    /// Config files the settings were read from
+    #[arg(skip)]
+    config_files: Vec<PathBuf>,
+
+    /// Progress on stdout: coloured text, or one JSON event per line for scripts
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, global = true)]
+    output: OutputFormat,
+
+    /// Also write the end-of-run summary to this file (Markdown for .md, else JSON)
+    #[arg(long, global = true)]
+    report: Option<PathBuf>,
+
     /// Perform a dry run without making changes
     #[arg(short, long, default_value_t = false, global = true)]
     dry_run: bool,
@@ -21,10 +44,38 @@ struct Args {
     #[arg(short, long, default_value_t = false, global = true)]
     organize_by_date: bool,
 
+    /// Folder layout under the input folder, e.g. "{year}/{month}-{month_name}/{date}_{place}"
+    /// (implies --organize-by-date)
+    #[arg(long, value_parser = nameforge::FolderTemplate::parse, global = true)]
+    folder_template: Option<nameforge::FolderTemplate>,
+
     /// Enable AI content analysis
     #[arg(long, default_value_t = false, global = true)]
     ai_content: bool,
 
+    /// AI backend to use for content analysis
+    #[arg(long, value_enum, default_value_t = nameforge::AiProviderKind::Ollama, global = true)]
+    ai_provider: nameforge::AiProviderKind,
+
+    /// Base URL of the AI backend (defaults to http://localhost:11434 for Ollama,
+    /// http://localhost:8080 for OpenAI-compatible servers)
+    #[arg(long, global = true)]
+    ai_host: Option<String>,
+
+    /// API key sent as a bearer token to OpenAI-compatible servers
+    #[arg(
+        long,
+        env = "NAMEFORGE_AI_API_KEY",
+        hide_env_values = true,
+        global = true
+    )]
+    ai_api_key: Option<String>,
+
+    /// Command for the command provider; the prompt is sent on stdin, {image} and
+    /// {model} are substituted and the filename is read from stdout
+    #[arg(long, global = true)]
+    ai_command: Option<String>,
+
     /// AI model to use for content analysis
     #[arg(long, default_value = "llava-llama3:latest", global = true)]
     ai_model: String,
@@ -41,10 +92,74 @@ struct Args {
     #[arg(long, default_value = "English", global = true)]
     ai_language: String,
 
+    /// Write the AI description and keywords to each photo's XMP sidecar
+    #[arg(long, default_value_t = false, global = true)]
+    write_xmp: bool,
+
     /// Use full timestamp (YYYY-MM-DD_HH-MM-SS) instead of date only
     #[arg(long, default_value_t = false, global = true)]
     full_timestamp: bool,
 
+    /// Add milliseconds to full timestamps and {time}, so burst shots get distinct names
+    #[arg(long, default_value_t = false, global = true)]
+    subseconds: bool,
+
+    /// Clock for dates in names and folders: utc, local, or the capture location's time
+    #[arg(long, value_enum, default_value_t = nameforge::TimeZoneMode::Capture, global = true)]
+    timezone: nameforge::TimeZoneMode,
+
+    /// Correct a wrong camera clock by this much, e.g. +1h, -1y or +2d3h30m
+    #[arg(long, value_parser = nameforge::TimeShift::parse, allow_hyphen_values = true, global = true)]
+    time_shift: Option<nameforge::TimeShift>,
+
+    /// TOML file mapping camera make, model or serial number to a clock correction
+    #[arg(long, global = true)]
+    clock_rules: Option<PathBuf>,
+
+    /// GPX track log for placing files without GPS (repeat for several files)
+    #[arg(long, action = ArgAction::Append, global = true)]
+    gpx: Vec<PathBuf>,
+
+    /// Longest gap between track points to interpolate over, e.g. 10m or 1h
+    #[arg(long, value_parser = nameforge::parse_duration, default_value = "10m", global = true)]
+    gpx_max_gap: u64,
+
+    /// Added to capture times before looking them up in the GPX track, e.g. -2h
+    #[arg(long, value_parser = nameforge::TimeShift::parse, allow_hyphen_values = true, global = true)]
+    gpx_offset: Option<nameforge::TimeShift>,
+
+    /// Only take files matching this glob, e.g. "*.jpg" or "2024/**" (repeatable)
+    #[arg(long, value_parser = nameforge::PathGlob::parse, action = ArgAction::Append, global = true)]
+    include: Vec<nameforge::PathGlob>,
+
+    /// Leave out files and folders matching this glob, e.g. "Exports/" (repeatable)
+    #[arg(long, value_parser = nameforge::PathGlob::parse, action = ArgAction::Append, global = true)]
+    exclude: Vec<nameforge::PathGlob>,
+
+    /// Only rename files captured on or after this date (YYYY-MM-DD or YYYY-MM-DDTHH:MM)
+    #[arg(long, value_parser = nameforge::parse_since, global = true)]
+    since: Option<chrono::NaiveDateTime>,
+
+    /// Only rename files captured on or before this date (a date includes the whole day)
+    #[arg(long, value_parser = nameforge::parse_until, global = true)]
+    until: Option<chrono::NaiveDateTime>,
+
+    /// Only take photos or only videos
+    #[arg(long, value_enum, global = true)]
+    only: Option<nameforge::MediaKind>,
+
+    /// Skip files smaller than this, e.g. 100k or 2MB
+    #[arg(long, value_parser = nameforge::parse_size, global = true)]
+    min_size: Option<u64>,
+
+    /// Skip files larger than this, e.g. 500MB or 4GiB
+    #[arg(long, value_parser = nameforge::parse_size, global = true)]
+    max_size: Option<u64>,
+
+    /// Folder levels to scan; 1 is the input folder without subfolders
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..), global = true)]
+    max_depth: Option<u16>,
+
     /// Use file system date instead of EXIF date for filename
     #[arg(short = 'f', long, default_value_t = false, global = true)]
     use_file_date: bool,
@@ -56,31 +171,69 @@ struct Args {
     /// Skip date prefix in filename (use only AI-generated name)
     #[arg(short = 'n', long, default_value_t = false, global = true)]
     no_date: bool,
-}
 
-fn format_duration(duration: Duration) -> String {
-    let total_secs = duration.as_secs();
-    let millis = duration.subsec_millis();
+    /// Reverse geocoding backend for GPS place names
+    #[arg(long, value_enum, default_value_t = nameforge::GeocoderBackend::Nominatim, global = true)]
+    geocoder: nameforge::GeocoderBackend,
 
-    if total_secs >= 60 {
-        let mins = total_secs / 60;
-        let secs = total_secs % 60;
-        format!("{}m {}s", mins, secs)
-    } else if total_secs > 0 {
-        format!("{}.{:03}s", total_secs, millis)
-    } else {
-        format!("{}ms", millis)
-    }
+    /// GeoNames dump (cities*.txt, allCountries.txt) or index for --geocoder offline
+    #[arg(long, global = true)]
+    geonames: Option<PathBuf>,
+
+    /// TOML file of named places (circles or polygons) used before any geocoder lookup
+    #[arg(long, global = true)]
+    places: Option<PathBuf>,
+
+    /// Filename template, e.g. "{date}_{camera}_{place}"
+    /// (tokens: date, time, year, month, month_name, day, place, ai, camera,
+    /// original_stem, kind, seq, content, title, keywords)
+    #[arg(long, value_parser = nameforge::NameTemplate::parse, global = true)]
+    name_template: Option<nameforge::NameTemplate>,
+
+    /// Worker threads for metadata extraction, image resizing and lookups
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), global = true)]
+    jobs: u16,
+
+    /// What to do with byte-identical copies of a file
+    #[arg(long, value_enum, global = true)]
+    duplicates: Option<nameforge::DuplicateMode>,
+
+    /// Folder that duplicates are moved into with --duplicates move-to
+    #[arg(long, global = true)]
+    duplicates_dir: Option<PathBuf>,
+
+    /// Group near-identical photos (bursts, re-encodes) under a shared name with sequence suffixes
+    #[arg(long, default_value_t = false, global = true)]
+    group_similar: bool,
+
+    /// Maximum perceptual hash distance (0-64) for photos to count as similar
+    #[arg(long, default_value_t = nameforge::DEFAULT_SIMILAR_DISTANCE, value_parser = clap::value_parser!(u32).range(0..=64), global = true)]
+    similar_distance: u32,
+
+    /// Move each group of similar photos into its own subfolder (implies --group-similar)
+    #[arg(long, default_value_t = false, global = true)]
+    burst_folder: bool,
+
+    /// Maximum concurrent requests to the AI backend
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), global = true)]
+    ai_jobs: u16,
+
+    /// Maximum concurrent reverse geocoding lookups (Nominatim requests are always sent one second apart)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), global = true)]
+    geo_jobs: u16,
 }
 
-fn display_completion_time(start_time: Instant) {
+fn display_completion_time(args: &Args, start_time: Instant) {
+    if args.output == OutputFormat::Json {
+        return;
+    }
     let duration = start_time.elapsed();
     println!("{}", "─".repeat(50).bright_black());
     println!(
         "{}  {}{}",
         "⏱️".bright_cyan(),
         "Completed in: ".bright_cyan(),
-        format_duration(duration).bright_white().bold()
+        nameforge::format_duration(duration).bright_white().bold()
     );
     println!();
 }
@@ -97,56 +250,315 @@ enum Commands {
         #[arg(short = 'm', long = "max-media", alias = "max-images", action = ArgAction::Set)]
         max_media: Option<usize>,
     },
+    /// Revert the renames recorded by a previous run
+    Undo {
+        /// Journal file to replay (defaults to the most recent run that was not undone)
+        #[arg(short, long)]
+        journal: Option<PathBuf>,
+
+        /// List the available rename journals instead of undoing
+        #[arg(short, long, default_value_t = false)]
+        list: bool,
+    },
+    /// Write the planned renames and their reasons to a JSON file for review
+    Plan {
+        /// Where to write the plan
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Carry out a plan written by `nf plan`, exactly as it stands
+    Apply {
+        /// Plan file to apply
+        plan: PathBuf,
+    },
+    /// Copy media from a card or folder into a library, verifying every copy
+    Import {
+        /// Folder to import from (left untouched unless --delete-source-after-verify)
+        #[arg(long = "from")]
+        from: PathBuf,
+
+        /// Library folder to copy into; naming and folder rules apply here
+        #[arg(long)]
+        to: PathBuf,
+
+        /// Delete each source file once its copy's checksum matches
+        #[arg(long, default_value_t = false)]
+        delete_source_after_verify: bool,
+    },
+    /// Work out how far a camera's clock is off from two photos of the same moment
+    ClockOffset {
+        /// Photo from the camera whose clock is right
+        reference: PathBuf,
+
+        /// Photo of the same moment from the camera to correct
+        photo: PathBuf,
+    },
+    /// Build a compact offline geocoder index from a GeoNames dump
+    GeonamesIndex {
+        /// GeoNames dump to read (cities*.txt or allCountries.txt)
+        #[arg(long = "from")]
+        from: PathBuf,
+
+        /// Where to write the compact index
+        #[arg(long)]
+        out: PathBuf,
+    },
 }
 
 fn main() {
     let start_time = Instant::now();
-    let args = Args::parse();
+    let args = parse_args();
 
     match &args.command {
         Some(Commands::Prompt { input, max_media }) => {
             // For prompt command, force AI content analysis
             display_prompt_config(&args, input, *max_media);
-            let options = nameforge::ProcessingOptions {
-                dry_run: args.dry_run,
-                organize_by_date: args.organize_by_date,
-                ai_content: true,
-                ai_model: &args.ai_model,
-                ai_max_chars: args.ai_max_chars,
-                ai_case: &args.ai_case,
-                ai_language: &args.ai_language,
-                date_only: !args.full_timestamp,
-                max_media: *max_media,
-                use_file_date: args.use_file_date,
-                prefer_modified: args.prefer_modified,
-                no_date: args.no_date,
+            let options = processing_options(&args, true, *max_media);
+            run_process(&args, input, &options);
+        }
+        Some(Commands::Undo { journal, list }) => {
+            let progress = Progress::new(args.output, false);
+            let result = if *list {
+                nameforge::list_runs().map(|runs| progress.runs(&runs))
+            } else {
+                nameforge::undo_run(journal.as_deref(), args.dry_run, &progress)
+                    .map(|summary| progress.undo_summary(&summary, args.dry_run))
             };
-            nameforge::process_folder(input, &options);
+            if let Err(error) = result {
+                progress.fail(&error);
+            }
+
+            display_completion_time(&args, start_time);
+        }
+        Some(Commands::Plan { out }) => {
+            let input = args
+                .input
+                .as_ref()
+                .expect("Input path is required for planning. Use --input <path>");
+            display_config(&args, input);
+            let options = processing_options(&args, args.ai_content, None);
+            run_plan(&args, input, &options, out);
+        }
+        Some(Commands::Apply { plan }) => {
+            run_apply(&args, plan);
+        }
+        Some(Commands::Import {
+            from,
+            to,
+            delete_source_after_verify,
+        }) => {
+            display_import_config(&args, from, to, *delete_source_after_verify);
+            let mut options = processing_options(&args, args.ai_content, None);
+            options.import_to = Some(to);
+            options.delete_source_after_verify = *delete_source_after_verify;
+            run_process(&args, from, &options);
+        }
+        Some(Commands::ClockOffset { reference, photo }) => {
+            let progress = Progress::new(args.output, false);
+            match nameforge::measure_clock_offset(reference, photo, args.timezone) {
+                Ok(offset) => progress.clock_offset(&offset),
+                Err(error) => progress.fail(&error),
+            }
 
-            display_completion_time(start_time);
+            display_completion_time(&args, start_time);
+        }
+        Some(Commands::GeonamesIndex { from, out }) => {
+            let progress = Progress::new(args.output, false);
+            match nameforge::build_geonames_index(from, out, &progress) {
+                Ok(places) => progress.geonames_index(out, places),
+                Err(error) => progress.fail(&error),
+            }
+
+            display_completion_time(&args, start_time);
         }
         None => {
             // Default processing - require input argument
             let input = args.input.as_ref().expect("Input path is required for default processing. Use --input or run 'nf prompt --input <path> --max-media <n>'");
             display_config(&args, input);
-            let options = nameforge::ProcessingOptions {
-                dry_run: args.dry_run,
-                organize_by_date: args.organize_by_date,
-                ai_content: args.ai_content,
-                ai_model: &args.ai_model,
-                ai_max_chars: args.ai_max_chars,
-                ai_case: &args.ai_case,
-                ai_language: &args.ai_language,
-                date_only: !args.full_timestamp,
-                max_media: None,
-                use_file_date: args.use_file_date,
-                prefer_modified: args.prefer_modified,
-                no_date: args.no_date,
-            };
-            nameforge::process_folder(input, &options);
+            let options = processing_options(&args, args.ai_content, None);
+            run_process(&args, input, &options);
+        }
+    }
+}
+
+/// Renames (or imports) everything under `input`, showing progress as it goes
+fn run_process(args: &Args, input: &Path, options: &nameforge::ProcessingOptions<'_>) {
+    let progress = Progress::new(args.output, false);
+    match nameforge::process(input, options, &progress) {
+        Ok(report) => progress.finish(
+            args.dry_run,
+            report.journal.as_deref(),
+            args.report.as_deref(),
+        ),
+        Err(error) => {
+            progress.fail(&error);
+            progress.finish(args.dry_run, None, args.report.as_deref());
+        }
+    }
+}
+
+/// Writes the planned renames for `input` to a plan file for review
+fn run_plan(args: &Args, input: &Path, options: &nameforge::ProcessingOptions<'_>, out: &Path) {
+    let progress = Progress::new(args.output, true);
+    let result = nameforge::plan(input, options, &progress).and_then(|plan| {
+        let unchanged = plan
+            .skipped
+            .iter()
+            .filter(|(_, reason)| *reason == nameforge::SkipReason::AlreadyNamed)
+            .count();
+        let count = plan.renames.len();
+        nameforge::RenamePlanFile::new(input, plan.renames)
+            .save(out)
+            .map(|()| (count, unchanged))
+    });
+
+    match result {
+        Ok((count, unchanged)) if progress.is_text() => {
+            progress::print_plan_written(out, count, unchanged)
+        }
+        Ok(_) => {}
+        Err(error) => progress.fail(&error),
+    }
+    progress.finish(true, None, args.report.as_deref());
+}
+
+/// Carries out a plan file, skipping entries that no longer fit the folder
+fn run_apply(args: &Args, plan_path: &Path) {
+    let progress = Progress::new(args.output, false);
+    let plan = match nameforge::RenamePlanFile::load(plan_path) {
+        Ok(plan) => plan,
+        Err(error) => {
+            progress.fail(&error);
+            progress.finish(args.dry_run, None, args.report.as_deref());
+            return;
+        }
+    };
+    if progress.is_text() {
+        progress::print_applying(plan_path, plan.renames.len());
+    }
+
+    let report = nameforge::apply(plan.renames, args.dry_run, &progress);
+    let stale = report
+        .outcomes
+        .iter()
+        .filter(|outcome| {
+            matches!(
+                outcome.status,
+                nameforge::Status::Skipped(
+                    nameforge::SkipReason::SourceMissing
+                        | nameforge::SkipReason::DestinationExists
+                        | nameforge::SkipReason::KeptCopyChanged
+                )
+            )
+        })
+        .count();
+    if stale > 0 {
+        progress::print_stale_entries(stale);
+    }
+    progress.finish(
+        args.dry_run,
+        report.journal.as_deref(),
+        args.report.as_deref(),
+    );
+}
+
+/// Parses the command line, then fills in what it leaves out from
+/// NAMEFORGE_* variables, the project's .nameforge.toml and the user config
+fn parse_args() -> Args {
+    let command = Args::command();
+    let matches = command.clone().ignore_errors(true).get_matches();
+    // An invalid value stops the lenient parse early; the full parse below reports it.
+    let no_config = matches.try_get_one::<bool>("no_config").ok().flatten();
+    if no_config.copied().unwrap_or(false) {
+        return Args::parse();
+    }
+
+    let start = match matches.subcommand() {
+        Some(("prompt", sub_matches)) => sub_matches.get_one::<PathBuf>("input"),
+        Some(("import", sub_matches)) => sub_matches.get_one::<PathBuf>("from"),
+        _ => None,
+    }
+    .or_else(|| matches.get_one::<PathBuf>("input"))
+    .cloned()
+    .unwrap_or_else(|| PathBuf::from("."));
+    let profile = matches.get_one::<String>("profile").map(String::as_str);
 
-            display_completion_time(start_time);
+    match nameforge::config_arguments(&command, &matches, &start, profile) {
+        Ok(config) => {
+            let mut args = Args::parse_from(std::env::args_os().chain(config.args));
+            args.config_files = config.files;
+            args
         }
+        Err(error) => {
+            eprintln!("{} {}", "❌".bright_red(), error.bright_white());
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Builds the library options shared by every processing command
+fn processing_options(
+    args: &Args,
+    ai_content: bool,
+    max_media: Option<usize>,
+) -> nameforge::ProcessingOptions<'_> {
+    let mut options = nameforge::ProcessingOptions::default();
+    options.dry_run = args.dry_run;
+    options.organize_by_date = args.organize_by_date;
+    options.ai_content = ai_content;
+    options.ai_provider = args.ai_provider;
+    options.ai_model = &args.ai_model;
+    options.ai_host = args.ai_host.as_deref();
+    options.ai_api_key = args.ai_api_key.as_deref();
+    options.ai_command = args.ai_command.as_deref();
+    options.ai_max_chars = args.ai_max_chars;
+    options.ai_case = &args.ai_case;
+    options.ai_language = &args.ai_language;
+    options.date_only = !args.full_timestamp;
+    options.subseconds = args.subseconds;
+    options.timezone = args.timezone;
+    options.time_shift = args.time_shift;
+    options.clock_rules = args.clock_rules.as_deref();
+    options.gpx = &args.gpx;
+    options.gpx_max_gap = args.gpx_max_gap;
+    options.gpx_offset = args.gpx_offset;
+    options.max_media = max_media;
+    options.jobs = args.jobs.into();
+    options.ai_jobs = args.ai_jobs.into();
+    options.geo_jobs = args.geo_jobs.into();
+    options.filter = nameforge::MediaFilter {
+        include: &args.include,
+        exclude: &args.exclude,
+        only: args.only,
+        min_size: args.min_size,
+        max_size: args.max_size,
+        max_depth: args.max_depth.map(usize::from),
+    };
+    options.since = args.since;
+    options.until = args.until;
+    options.use_file_date = args.use_file_date;
+    options.prefer_modified = args.prefer_modified;
+    options.no_date = args.no_date;
+    options.name_template = args.name_template.as_ref();
+    options.folder_template = args.folder_template.as_ref();
+    options.geocoder = args.geocoder;
+    options.geonames_path = args.geonames.as_deref();
+    options.places = args.places.as_deref();
+    options.duplicates = duplicate_mode(args);
+    options.duplicates_dir = args.duplicates_dir.as_deref();
+    options.group_similar = args.group_similar;
+    options.similar_distance = args.similar_distance;
+    options.burst_folder = args.burst_folder;
+    options.write_xmp = args.write_xmp;
+    options
+}
+
+/// The duplicate handling in effect; imports skip files already in the library
+/// unless told otherwise.
+fn duplicate_mode(args: &Args) -> Option<nameforge::DuplicateMode> {
+    match args.command {
+        Some(Commands::Import { .. }) => args.duplicates.or(Some(nameforge::DuplicateMode::Skip)),
+        _ => args.duplicates,
     }
 }
 
@@ -168,15 +580,58 @@ fn display_enabled_status(enabled: bool) -> colored::ColoredString {
     }
 }
 
-/// Helper function to display date format
-fn display_date_format(full_timestamp: bool) -> colored::ColoredString {
-    if full_timestamp {
-        "FULL TIMESTAMP (YYYY-MM-DD_HH-MM-SS)".bright_cyan()
-    } else {
-        "DATE ONLY (YYYY-MM-DD)".bright_cyan().bold()
+/// Helper function to display date format and time zone
+fn display_date_format(args: &Args) -> colored::ColoredString {
+    let zone = match args.timezone {
+        nameforge::TimeZoneMode::Utc => "UTC",
+        nameforge::TimeZoneMode::Local => "LOCAL TIME",
+        nameforge::TimeZoneMode::Capture => "CAPTURE TIME",
+    };
+    match (args.full_timestamp, args.subseconds) {
+        (true, false) => format!("FULL TIMESTAMP (YYYY-MM-DD_HH-MM-SS), {}", zone).bright_cyan(),
+        (true, true) => format!("FULL TIMESTAMP (YYYY-MM-DD_HH-MM-SS-mmm), {}", zone).bright_cyan(),
+        (false, _) => format!("DATE ONLY (YYYY-MM-DD), {}", zone)
+            .bright_cyan()
+            .bold(),
     }
 }
 
+/// Helper function to display GPX geotagging
+fn display_gpx(args: &Args) -> colored::ColoredString {
+    let files = match args.gpx.as_slice() {
+        [file] => file.display().to_string(),
+        files => format!("{} files", files.len()),
+    };
+    let offset = args
+        .gpx_offset
+        .map(|offset| format!(", offset {}", offset))
+        .unwrap_or_default();
+    format!(
+        "{} (max gap {}{})",
+        files,
+        nameforge::TimeShift::from_seconds(args.gpx_max_gap as i64)
+            .to_string()
+            .trim_start_matches('+'),
+        offset
+    )
+    .bright_cyan()
+    .bold()
+}
+
+/// Helper function to display camera clock corrections
+fn display_clock_fix(args: &Args) -> colored::ColoredString {
+    match (&args.time_shift, &args.clock_rules) {
+        (Some(shift), Some(rules)) => {
+            format!("{} (unless {} has a rule)", shift, rules.display())
+        }
+        (Some(shift), None) => shift.to_string(),
+        (None, Some(rules)) => format!("RULES FROM {}", rules.display()),
+        (None, None) => "NONE".to_string(),
+    }
+    .bright_cyan()
+    .bold()
+}
+
 /// Helper function to display date source
 fn display_date_source(args: &Args) -> colored::ColoredString {
     if args.no_date {
@@ -188,9 +643,149 @@ fn display_date_source(args: &Args) -> colored::ColoredString {
             "FILE CREATION".bright_green().bold()
         }
     } else {
-        "EXIF FOR IMAGES, FILE DATES FOR VIDEOS"
+        "EXIF FOR IMAGES, CONTAINER METADATA FOR VIDEOS"
+            .bright_cyan()
+            .bold()
+    }
+}
+
+/// Helper function to display the reverse geocoding backend
+fn display_geocoder(args: &Args) -> colored::ColoredString {
+    let geocoder = display_geocoder_backend(args);
+    match &args.places {
+        Some(places) => format!("{} after places from {}", geocoder, places.display())
+            .bright_cyan()
+            .bold(),
+        None => geocoder,
+    }
+}
+
+/// Helper function to display the reverse geocoding backend alone
+fn display_geocoder_backend(args: &Args) -> colored::ColoredString {
+    match (args.geocoder, &args.geonames) {
+        (nameforge::GeocoderBackend::Offline, Some(path)) => {
+            format!("OFFLINE ({})", path.display()).bright_cyan().bold()
+        }
+        (nameforge::GeocoderBackend::Offline, None) => "OFFLINE (missing --geonames)".bright_red(),
+        (nameforge::GeocoderBackend::Nominatim, _) => "NOMINATIM".bright_cyan(),
+    }
+}
+
+/// Helper function to display how deep folders are scanned
+fn display_search(args: &Args) -> colored::ColoredString {
+    match args.max_depth {
+        Some(1) => "INPUT FOLDER ONLY".bright_cyan().bold(),
+        Some(depth) => format!("RECURSIVE ({} levels)", depth).bright_cyan().bold(),
+        None => "RECURSIVE".bright_cyan().bold(),
+    }
+}
+
+/// Helper function to display the file filters, if any are set
+fn display_filters(args: &Args) -> Option<colored::ColoredString> {
+    let globs = |globs: &[nameforge::PathGlob]| {
+        globs
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let date = |date_time: chrono::NaiveDateTime| date_time.format("%Y-%m-%d %H:%M").to_string();
+    let filters: Vec<String> = [
+        (!args.include.is_empty()).then(|| format!("include {}", globs(&args.include))),
+        (!args.exclude.is_empty()).then(|| format!("exclude {}", globs(&args.exclude))),
+        args.only.map(|kind| format!("{}s only", kind.label())),
+        args.since.map(|since| format!("since {}", date(since))),
+        args.until.map(|until| format!("until {}", date(until))),
+        args.min_size.map(|size| format!("at least {} bytes", size)),
+        args.max_size.map(|size| format!("at most {} bytes", size)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    (!filters.is_empty()).then(|| filters.join("; ").bright_cyan().bold())
+}
+
+/// Helper function to display the folder layout
+fn display_folder_layout(args: &Args) -> colored::ColoredString {
+    match &args.folder_template {
+        Some(template) => template.to_string().bright_cyan().bold(),
+        None => display_enabled_status(args.organize_by_date),
+    }
+}
+
+/// Helper function to display the filename template
+fn display_name_template(args: &Args) -> colored::ColoredString {
+    match &args.name_template {
+        Some(template) => template.to_string().bright_cyan().bold(),
+        None => "DEFAULT ({date}_{content})".bright_cyan(),
+    }
+}
+
+/// Helper function to display duplicate handling
+fn display_duplicates(args: &Args) -> colored::ColoredString {
+    match (duplicate_mode(args), &args.duplicates_dir) {
+        (None, _) => "KEEP ALL (suffixed names)".bright_cyan(),
+        (Some(nameforge::DuplicateMode::MoveTo), Some(dir)) => {
+            format!("MOVE TO {}", dir.display()).bright_cyan().bold()
+        }
+        (Some(nameforge::DuplicateMode::MoveTo), None) => {
+            "MOVE TO (missing --duplicates-dir)".bright_red()
+        }
+        (Some(mode), _) => format!("{:?}", mode).to_uppercase().bright_cyan().bold(),
+    }
+}
+
+/// Helper function to display near-duplicate grouping
+fn display_similar(args: &Args) -> colored::ColoredString {
+    if args.burst_folder {
+        format!("GROUP INTO FOLDERS (distance {})", args.similar_distance)
             .bright_cyan()
             .bold()
+    } else if args.group_similar {
+        format!("GROUP (distance {})", args.similar_distance)
+            .bright_cyan()
+            .bold()
+    } else {
+        display_enabled_status(false)
+    }
+}
+
+/// Helper function to display worker and backend concurrency
+fn display_jobs(args: &Args) -> colored::ColoredString {
+    format!(
+        "{} (AI: {}, geocoder: {})",
+        args.jobs, args.ai_jobs, args.geo_jobs
+    )
+    .bright_cyan()
+}
+
+/// Helper function to display the AI backend and where it runs
+fn display_ai_provider(args: &Args) -> colored::ColoredString {
+    let target = match args.ai_provider {
+        nameforge::AiProviderKind::Command => args.ai_command.as_deref(),
+        _ => args.ai_host.as_deref(),
+    };
+    let kind = format!("{:?}", args.ai_provider).to_uppercase();
+
+    match target {
+        Some(target) => format!("{} ({})", kind, target).bright_white(),
+        None => kind.bright_white(),
+    }
+}
+
+/// Helper function to display the config files and profile in use
+fn display_config_files(args: &Args) -> colored::ColoredString {
+    let files = args
+        .config_files
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match &args.profile {
+        Some(profile) => format!("{} (profile {})", files, profile).bright_white(),
+        None => files.bright_white(),
     }
 }
 
@@ -202,6 +797,11 @@ fn display_ai_settings(args: &Args) {
             "🤖 AI Analysis:".bright_green(),
             "ENABLED".bright_green().bold()
         );
+        println!(
+            "{}     {}",
+            "   Provider:".bright_blue(),
+            display_ai_provider(args)
+        );
         println!(
             "{}        {}",
             "   Model:".bright_blue(),
@@ -222,6 +822,12 @@ fn display_ai_settings(args: &Args) {
             "   Language:".bright_blue(),
             args.ai_language.bright_white()
         );
+        if args.write_xmp {
+            println!(
+                "{}",
+                "   Descriptions written to XMP sidecars".bright_black()
+            );
+        }
         println!("{}", "   Video files use filename fallback".bright_black());
     } else {
         println!(
@@ -231,7 +837,8 @@ fn display_ai_settings(args: &Args) {
         );
         println!(
             "{}",
-            "   Using GPS for images and filename fallback for videos".bright_black()
+            "   Using GPS place names, with filename fallback when there is no location"
+                .bright_black()
         );
     }
 }
@@ -243,11 +850,17 @@ fn display_common_config(args: &Args, input: &std::path::Path) {
         "📁 Input path:".bright_green(),
         input.display().to_string().bright_white()
     );
-    println!(
-        "{}   {}",
-        "🔎 Search:".bright_green(),
-        "RECURSIVE".bright_cyan().bold()
-    );
+    if !args.config_files.is_empty() {
+        println!(
+            "{}        {}",
+            "🗂️ Config:".bright_green(),
+            display_config_files(args)
+        );
+    }
+    println!("{}   {}", "🔎 Search:".bright_green(), display_search(args));
+    if let Some(filters) = display_filters(args) {
+        println!("{}   {}", "🧹 Filters:".bright_green(), filters);
+    }
     println!(
         "{}   {}",
         "🔧 Mode:".bright_green(),
@@ -256,21 +869,63 @@ fn display_common_config(args: &Args, input: &std::path::Path) {
     println!(
         "{}  {}",
         "📅 Date folders:".bright_green(),
-        display_enabled_status(args.organize_by_date)
+        display_folder_layout(args)
     );
     println!(
         "{}    {}",
         "📆 Date format:".bright_green(),
-        display_date_format(args.full_timestamp)
+        display_date_format(args)
     );
     println!(
         "{}   {}",
         "📅 Date source:".bright_green(),
         display_date_source(args)
     );
+    if args.time_shift.is_some() || args.clock_rules.is_some() {
+        println!(
+            "{}     {}",
+            "🕰️ Clock fix:".bright_green(),
+            display_clock_fix(args)
+        );
+    }
+    println!(
+        "{}      {}",
+        "🌍 Geocoder:".bright_green(),
+        display_geocoder(args)
+    );
+    if !args.gpx.is_empty() {
+        println!(
+            "{}    {}",
+            "🛰️ GPX tracks:".bright_green(),
+            display_gpx(args)
+        );
+    }
+    println!(
+        "{}  {}",
+        "📝 Name template:".bright_green(),
+        display_name_template(args)
+    );
+    println!(
+        "{}          {}",
+        "⚙️ Jobs:".bright_green(),
+        display_jobs(args)
+    );
+    println!(
+        "{}    {}",
+        "🔁 Duplicates:".bright_green(),
+        display_duplicates(args)
+    );
+    println!(
+        "{}  {}",
+        "📚 Similar photos:".bright_green(),
+        display_similar(args)
+    );
 }
 
 fn display_config(args: &Args, input: &std::path::Path) {
+    if args.output == OutputFormat::Json {
+        return;
+    }
     println!("{}", "📸 NameForge Configuration".bright_cyan().bold());
     println!("{}", "─".repeat(50).bright_black());
 
@@ -296,6 +951,11 @@ fn display_prompt_ai_settings(args: &Args) {
         "🤖 AI Analysis:".bright_green(),
         "ENABLED".bright_green().bold()
     );
+    println!(
+        "{} {}",
+        "   Provider:".bright_blue(),
+        display_ai_provider(args)
+    );
     println!(
         "{} {}",
         "   Model:".bright_blue(),
@@ -319,6 +979,9 @@ fn display_prompt_ai_settings(args: &Args) {
 }
 
 fn display_prompt_config(args: &Args, input: &std::path::Path, max_media: Option<usize>) {
+    if args.output == OutputFormat::Json {
+        return;
+    }
     println!("{}", "🤖 NameForge - Prompt Mode".bright_magenta().bold());
     println!("{}", "─".repeat(50).bright_black());
 
@@ -333,3 +996,47 @@ fn display_prompt_config(args: &Args, input: &std::path::Path, max_media: Option
     println!("{}", "─".repeat(50).bright_black());
     println!();
 }
+
+fn display_import_config(
+    args: &Args,
+    from: &std::path::Path,
+    to: &std::path::Path,
+    delete_source: bool,
+) {
+    if args.output == OutputFormat::Json {
+        return;
+    }
+    println!("{}", "📥 NameForge - Import".bright_cyan().bold());
+    println!("{}", "─".repeat(50).bright_black());
+
+    display_common_config(args, from);
+    println!(
+        "{}       {}",
+        "📚 Library:".bright_green(),
+        to.display().to_string().bright_white()
+    );
+    println!(
+        "{} {}",
+        "🗑️ Delete source:".bright_green(),
+        if delete_source {
+            "AFTER VERIFY".bright_yellow().bold()
+        } else {
+            "NO (copy only)".bright_cyan()
+        }
+    );
+    display_ai_settings(args);
+
+    println!("{}", "─".repeat(50).bright_black());
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Args::command().debug_assert();
+    }
+}
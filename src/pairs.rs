@@ -0,0 +1,62 @@
+use crate::union_find::UnionFind;
+use std::{collections::HashMap, path::Path};
+
+/// Groups files that are parts of one shot: files in the same folder with the
+/// same stem (`DSC_0001.NEF` + `DSC_0001.JPG`, `IMG_0001.HEIC` + `IMG_0001.MOV`)
+/// and files sharing a Live Photo content identifier, wherever they are.
+/// Only groups with two or more members are returned, in order of their first
+/// member, and members keep input order.
+pub fn find_pairs(paths: &[&Path], content_identifiers: &[Option<String>]) -> Vec<Vec<usize>> {
+    let mut sets = UnionFind::new(paths.len());
+    let mut first_by_key: HashMap<String, usize> = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        let stem_key = path.parent().zip(path.file_stem()).map(|(folder, stem)| {
+            format!(
+                "stem:{}/{}",
+                folder.display(),
+                stem.to_string_lossy().to_lowercase()
+            )
+        });
+        let content_key = content_identifiers
+            .get(index)
+            .and_then(Option::as_ref)
+            .map(|identifier| format!("live:{}", identifier));
+
+        for key in stem_key.into_iter().chain(content_key) {
+            let first = *first_by_key.entry(key).or_insert(index);
+            sets.union(first, index);
+        }
+    }
+
+    sets.groups(0..paths.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_by_stem_within_a_folder_and_by_live_photo_identifier() {
+        let paths = [
+            Path::new("card/DSC_0001.JPG"),
+            Path::new("card/DSC_0001.NEF"),
+            Path::new("card/DSC_0002.JPG"),
+            Path::new("other/DSC_0002.NEF"),
+            Path::new("phone/IMG_0001.HEIC"),
+            Path::new("export/Live video.MOV"),
+        ];
+        let identifiers = [
+            None,
+            None,
+            None,
+            None,
+            Some("5F3C1A2E".to_string()),
+            Some("5F3C1A2E".to_string()),
+        ];
+
+        assert_eq!(
+            find_pairs(&paths, &identifiers),
+            vec![vec![0, 1], vec![4, 5]]
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// One `[[place]]` entry of a places file: a circle given by `lat`, `lon`
+/// and `radius` in metres, or a `polygon` of `[lat, lon]` corners.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlaceEntry {
+    name: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    radius: Option<f64>,
+    polygon: Option<Vec<[f64; 2]>>,
+}
+
+#[derive(Debug)]
+enum Fence {
+    Circle { lat: f64, lon: f64, radius: f64 },
+    Polygon(Vec<[f64; 2]>),
+}
+
+impl Fence {
+    fn contains(&self, lat: f64, lon: f64) -> bool {
+        match self {
+            Fence::Circle {
+                lat: center_lat,
+                lon: center_lon,
+                radius,
+            } => distance_m(*center_lat, *center_lon, lat, lon) <= *radius,
+            Fence::Polygon(corners) => polygon_contains(corners, lat, lon),
+        }
+    }
+}
+
+/// User-defined places that take precedence over reverse geocoding.
+#[derive(Debug, Default)]
+pub struct NamedPlaces {
+    places: Vec<(String, Fence)>,
+}
+
+impl NamedPlaces {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("Could not read places {}: {}", path.display(), error))?;
+        Self::parse(&text).map_err(|error| format!("Invalid places {}: {}", path.display(), error))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct PlacesFile {
+            #[serde(default)]
+            place: Vec<PlaceEntry>,
+        }
+
+        let file: PlacesFile = toml::from_str(text).map_err(|error| error.to_string())?;
+        let places = file
+            .place
+            .into_iter()
+            .map(|entry| {
+                let fence = match (entry.lat, entry.lon, entry.radius, entry.polygon) {
+                    (Some(lat), Some(lon), Some(radius), None) if radius > 0.0 => {
+                        Fence::Circle { lat, lon, radius }
+                    }
+                    (None, None, None, Some(corners)) if corners.len() >= 3 => {
+                        Fence::Polygon(corners)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "place \"{}\" needs either lat, lon and a radius in metres, or a polygon of at least three [lat, lon] corners",
+                            entry.name
+                        ))
+                    }
+                };
+                Ok((entry.name.trim().replace(' ', "_"), fence))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(NamedPlaces { places })
+    }
+
+    /// The name of the first place whose fence contains the position.
+    pub fn find(&self, lat: f64, lon: f64) -> Option<&str> {
+        self.places
+            .iter()
+            .find(|(_, fence)| fence.contains(lat, lon))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Great-circle distance in metres.
+fn distance_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let half_dlat = (lat2 - lat1) / 2.0;
+    let half_dlon = (lon2 - lon1).to_radians() / 2.0;
+    let a = half_dlat.sin().powi(2) + lat1.cos() * lat2.cos() * half_dlon.sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+/// Even-odd ray casting; treating degrees as flat is fine at geofence sizes.
+fn polygon_contains(corners: &[[f64; 2]], lat: f64, lon: f64) -> bool {
+    let mut inside = false;
+    let mut previous = corners[corners.len() - 1];
+    for &corner in corners {
+        let ([lat_a, lon_a], [lat_b, lon_b]) = (corner, previous);
+        if (lat_a > lat) != (lat_b > lat)
+            && lon < lon_a + (lat - lat_a) * (lon_b - lon_a) / (lat_b - lat_a)
+        {
+            inside = !inside;
+        }
+        previous = corner;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circles_and_polygons_match_in_file_order() {
+        let places = NamedPlaces::parse(
+            r#"
+            [[place]]
+            name = "Home"
+            lat = 48.8584
+            lon = 2.2945
+            radius = 150
+
+            [[place]]
+            name = "Summer Cabin"
+            polygon = [[61.10, 10.40], [61.20, 10.40], [61.20, 10.60], [61.10, 10.60]]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(places.find(48.8594, 2.2945), Some("Home"));
+        assert_eq!(places.find(48.8700, 2.2945), None);
+        assert_eq!(places.find(61.15, 10.50), Some("Summer_Cabin"));
+        assert_eq!(places.find(61.25, 10.50), None);
+        assert!(NamedPlaces::parse("[[place]]\nname = \"Office\"\nlat = 1.0\nlon = 2.0").is_err());
+    }
+}
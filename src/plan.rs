@@ -0,0 +1,257 @@
+use crate::{clock::TimeShift, exif::DateSource, xmp::XmpUpdate, Error};
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// Format version written to plan files; bumped on incompatible changes.
+const PLAN_VERSION: u32 = 1;
+
+/// Why a file is getting its new name, kept in plans for reviewers.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RenameReasons {
+    pub date_source: Option<DateSource>,
+    pub captured_at: Option<NaiveDateTime>,
+    /// Camera clock correction already included in `captured_at`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_shift: Option<TimeShift>,
+    pub place: Option<String>,
+    pub ai_name: Option<String>,
+    /// Counter appended to avoid an existing or earlier planned name.
+    pub collision_suffix: Option<u32>,
+    /// The kept file this one is a byte-identical copy of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<PathBuf>,
+    /// Number of the near-identical photo group, as listed in the run output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similar_group: Option<usize>,
+    /// The RAW+JPEG or Live Photo partner this file's name was taken from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub named_after: Option<PathBuf>,
+}
+
+/// What `nf apply` does with a planned entry.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedAction {
+    /// Move `source` to `destination`.
+    #[default]
+    Rename,
+    /// Delete `source`; `destination` is ignored.
+    Delete,
+    /// Copy `source` to `destination` and verify the copy's checksum.
+    Copy,
+    /// Like `Copy`, then delete `source` once the copy is verified.
+    CopyAndDelete,
+}
+
+impl PlannedAction {
+    /// Whether the action writes a file at `destination`.
+    pub fn writes_destination(self) -> bool {
+        self != PlannedAction::Delete
+    }
+}
+
+/// A sidecar file that moves or is copied along with its media file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SidecarMove {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+/// A single source → destination decision.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlannedRename {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    #[serde(default)]
+    pub action: PlannedAction,
+    #[serde(default)]
+    pub reasons: RenameReasons,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<SidecarMove>,
+    /// AI description and keywords to write to the file's XMP sidecar.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xmp: Option<XmpUpdate>,
+}
+
+impl PlannedRename {
+    pub fn is_noop(&self) -> bool {
+        self.action == PlannedAction::Rename
+            && self.source == self.destination
+            && self.xmp.is_none()
+            && self
+                .sidecars
+                .iter()
+                .all(|sidecar| sidecar.source == sidecar.destination)
+    }
+
+    /// Sidecars whose name or folder changes with their media file.
+    pub fn moved_sidecars(&self) -> impl Iterator<Item = &SidecarMove> {
+        self.sidecars
+            .iter()
+            .filter(|sidecar| sidecar.source != sidecar.destination)
+    }
+
+    /// Where the file's XMP description goes: its own XMP sidecar if it has
+    /// one, otherwise a new `.xmp` next to its destination. A sidecar that is
+    /// renamed along with its file is written `before_move`, so the journal
+    /// records its final contents.
+    pub fn xmp_target(&self, before_move: bool) -> PathBuf {
+        self.sidecars
+            .iter()
+            .find(|sidecar| {
+                sidecar
+                    .destination
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("xmp"))
+            })
+            .map(|sidecar| {
+                if before_move {
+                    sidecar.source.clone()
+                } else {
+                    sidecar.destination.clone()
+                }
+            })
+            .unwrap_or_else(|| self.destination.with_extension("xmp"))
+    }
+}
+
+/// The renames written by `nf plan` and carried out by `nf apply`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenamePlanFile {
+    pub version: u32,
+    pub created_at: NaiveDateTime,
+    pub input: PathBuf,
+    pub renames: Vec<PlannedRename>,
+}
+
+impl RenamePlanFile {
+    pub fn new(input: &Path, renames: Vec<PlannedRename>) -> Self {
+        RenamePlanFile {
+            version: PLAN_VERSION,
+            created_at: Local::now().naive_local(),
+            input: input.to_path_buf(),
+            renames,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let file = File::create(path).map_err(|error| {
+            Error::Plan(format!("Could not create {}: {}", path.display(), error))
+        })?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .map_err(|error| Error::Plan(format!("Could not write {}: {}", path.display(), error)))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|error| {
+            Error::Plan(format!("Could not open plan {}: {}", path.display(), error))
+        })?;
+        let plan: RenamePlanFile = serde_json::from_reader(BufReader::new(file))
+            .map_err(|error| Error::Plan(format!("Invalid plan {}: {}", path.display(), error)))?;
+
+        if plan.version != PLAN_VERSION {
+            return Err(Error::Plan(format!(
+                "Plan {} has version {}, expected {}",
+                path.display(),
+                plan.version,
+                PLAN_VERSION
+            )));
+        }
+        plan.validate().map_err(Error::Plan)?;
+        Ok(plan)
+    }
+
+    /// Rejects plans that would move one file twice or two files onto one path,
+    /// which can easily happen when a plan is edited by hand.
+    fn validate(&self) -> Result<(), String> {
+        let mut sources = HashSet::new();
+        let mut destinations = HashSet::new();
+
+        for rename in &self.renames {
+            if !sources.insert(&rename.source) {
+                return Err(format!(
+                    "{} appears more than once as a source",
+                    rename.source.display()
+                ));
+            }
+            if rename.action.writes_destination() && !destinations.insert(&rename.destination) {
+                return Err(format!(
+                    "{} is the destination of more than one rename or copy",
+                    rename.destination.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename(source: &str, destination: &str) -> PlannedRename {
+        PlannedRename {
+            source: source.into(),
+            destination: destination.into(),
+            action: PlannedAction::Rename,
+            reasons: RenameReasons::default(),
+            sidecars: Vec::new(),
+            xmp: None,
+        }
+    }
+
+    #[test]
+    fn validate_rejects_shared_destinations() {
+        let plan = RenamePlanFile::new(
+            Path::new("photos"),
+            vec![
+                rename("photos/a.jpg", "photos/beach.jpg"),
+                rename("photos/b.jpg", "photos/beach.jpg"),
+            ],
+        );
+
+        assert!(plan.validate().is_err());
+
+        let copy = |source: &str| PlannedRename {
+            action: PlannedAction::Copy,
+            ..rename(source, "library/beach.jpg")
+        };
+        let plan = RenamePlanFile::new(
+            Path::new("photos"),
+            vec![copy("photos/a.jpg"), copy("photos/b.jpg")],
+        );
+        assert!(plan.validate().is_err());
+
+        // Deletes ignore their destination, so they may share one.
+        let delete = |source: &str| PlannedRename {
+            action: PlannedAction::Delete,
+            ..rename(source, "photos/beach.jpg")
+        };
+        let plan = RenamePlanFile::new(
+            Path::new("photos"),
+            vec![delete("photos/a.jpg"), delete("photos/b.jpg")],
+        );
+        assert!(plan.validate().is_ok());
+    }
+
+    #[test]
+    fn hand_written_entries_may_omit_reasons() {
+        let json = r#"{
+            "version": 1,
+            "created_at": "2024-05-01T10:00:00",
+            "input": "photos",
+            "renames": [{"source": "photos/a.jpg", "destination": "photos/b.jpg"}]
+        }"#;
+
+        let plan: RenamePlanFile = serde_json::from_str(json).unwrap();
+
+        assert_eq!(plan.renames, vec![rename("photos/a.jpg", "photos/b.jpg")]);
+        assert!(plan.validate().is_ok());
+    }
+}
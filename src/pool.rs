@@ -0,0 +1,143 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Condvar, Mutex,
+    },
+    thread,
+};
+
+/// Counting semaphore used to cap concurrent calls to a shared backend.
+pub struct Semaphore {
+    permits: Mutex<usize>,
+    available: Condvar,
+}
+
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: Mutex::new(permits.max(1)),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a permit is free; it is returned when the guard drops.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.available.wait(permits).unwrap();
+        }
+        *permits -= 1;
+        SemaphorePermit { semaphore: self }
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.permits.lock().unwrap() += 1;
+        self.semaphore.available.notify_one();
+    }
+}
+
+/// Runs `work` over `items` on up to `jobs` threads and hands every result to
+/// `consume` on the calling thread, in the original order of `items`.
+///
+/// Results are consumed as soon as all earlier ones are in, so long runs make
+/// progress without waiting for the whole batch.
+pub fn for_each_ordered<T, R, W, C>(items: &[T], jobs: usize, work: W, mut consume: C)
+where
+    T: Sync,
+    R: Send,
+    W: Fn(usize, &T) -> R + Sync,
+    C: FnMut(usize, &T, R),
+{
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        for (index, item) in items.iter().enumerate() {
+            consume(index, item, work(index, item));
+        }
+        return;
+    }
+
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (next, work) = (&next, &work);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                if sender.send((index, work(index, item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut expected = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&expected) {
+                consume(expected, &items[expected], result);
+                expected += 1;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn for_each_ordered_keeps_input_order() {
+        let items: Vec<u64> = (0..40).collect();
+        let mut seen = Vec::new();
+
+        for_each_ordered(
+            &items,
+            8,
+            |_, item| {
+                thread::sleep(Duration::from_millis((40 - item) % 7));
+                item * 2
+            },
+            |index, item, doubled| seen.push((index, *item, doubled)),
+        );
+
+        let expected: Vec<_> = items
+            .iter()
+            .map(|&item| (item as usize, item, item * 2))
+            .collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn semaphore_caps_concurrency() {
+        let semaphore = Semaphore::new(2);
+        let active = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..6 {
+                scope.spawn(|| {
+                    let _permit = semaphore.acquire();
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    active.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+}
@@ -0,0 +1,886 @@
+use colored::*;
+use nameforge::{
+    format_duration, CameraIdentity, ClockOffset, Error, Event, JournalOp, Observer, PlannedAction,
+    PlannedRename, RunJournal, RunReport, RunStats, SkipReason, TimeShift, UndoSummary,
+};
+use serde::Serialize;
+use std::path::Path;
+
+/// How run progress is shown on stdout.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Coloured, human readable progress
+    #[default]
+    Text,
+    /// One JSON event per line (JSON Lines), ending with a summary
+    Json,
+}
+
+/// Prints a run's events in the chosen output format and collects the
+/// statistics for the summary at the end.
+pub struct Progress {
+    printer: Printer,
+    stats: RunStats,
+}
+
+enum Printer {
+    Text(TextObserver),
+    Json(JsonObserver),
+}
+
+impl Progress {
+    /// `show_plan` also prints every planned rename in text output, for `nf plan`.
+    pub fn new(format: OutputFormat, show_plan: bool) -> Self {
+        let printer = match format {
+            OutputFormat::Text => Printer::Text(TextObserver { show_plan }),
+            OutputFormat::Json => Printer::Json(JsonObserver),
+        };
+        Progress {
+            printer,
+            stats: RunStats::new(),
+        }
+    }
+
+    /// Reports an error that stopped the whole run.
+    pub fn fail(&self, error: &Error) {
+        eprintln!("{} {}", "❌".bright_red(), error.to_string().bright_white());
+        self.stats.fail(error);
+        if let Printer::Json(observer) = &self.printer {
+            observer.on_event(&Event::Failed { path: None, error });
+        }
+    }
+
+    /// Points at the run's journal and prints the summary, or ends the JSON
+    /// stream with the summary line. With `report`, also writes the summary
+    /// there as JSON or Markdown.
+    pub fn finish(&self, dry_run: bool, journal: Option<&Path>, report: Option<&Path>) {
+        let run_report = self.stats.report(dry_run);
+        let saved = report.map(|path| run_report.save(path).map(|()| path));
+        if let Some(Err(error)) = &saved {
+            eprintln!("{} {}", "❌".bright_red(), error.to_string().bright_white());
+        }
+
+        match &self.printer {
+            Printer::Text(_) => {
+                if let Some(journal) = journal {
+                    println!(
+                        "{}  {}{}  {}",
+                        "📒".bright_blue(),
+                        "Rename journal saved to ".bright_blue(),
+                        journal.display().to_string().bright_white(),
+                        "(revert with `nf undo`)".bright_black()
+                    );
+                }
+                if let Some(Ok(path)) = saved {
+                    print_report_written(path);
+                }
+                print_run_report(&run_report);
+            }
+            Printer::Json(_) => print_json(&RunSummary {
+                report: &run_report,
+                journal,
+            }),
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self.printer, Printer::Text(_))
+    }
+
+    /// Lists the journals kept from previous runs, one `run` line each in JSON.
+    pub fn runs(&self, runs: &[RunJournal]) {
+        match &self.printer {
+            Printer::Text(_) => print_runs(runs),
+            Printer::Json(_) => {
+                for run in runs {
+                    print_json(&Tagged {
+                        event: "run",
+                        body: run,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Ends an undo with its counts, or with a `summary` line in JSON.
+    pub fn undo_summary(&self, summary: &UndoSummary, dry_run: bool) {
+        match &self.printer {
+            Printer::Text(_) => print_undo_summary(summary),
+            Printer::Json(_) => print_json(&UndoSummaryLine {
+                event: "summary",
+                summary,
+                dry_run,
+            }),
+        }
+    }
+
+    pub fn clock_offset(&self, offset: &ClockOffset) {
+        match &self.printer {
+            Printer::Text(_) => print_clock_offset(offset),
+            Printer::Json(_) => print_json(&ClockOffsetLine {
+                event: "clock-offset",
+                shift: offset.shift,
+                camera: &offset.camera,
+            }),
+        }
+    }
+
+    pub fn geonames_index(&self, out: &Path, places: usize) {
+        match &self.printer {
+            Printer::Text(_) => print_geonames_index(out, places),
+            Printer::Json(_) => print_json(&GeonamesIndexLine {
+                event: "geonames-index",
+                out,
+                places,
+            }),
+        }
+    }
+}
+
+/// A JSON line made of an `event` tag and the fields of `body`.
+#[derive(Serialize)]
+struct Tagged<'a, T: Serialize> {
+    event: &'static str,
+    #[serde(flatten)]
+    body: &'a T,
+}
+
+#[derive(Serialize)]
+struct UndoSummaryLine<'a> {
+    event: &'static str,
+    #[serde(flatten)]
+    summary: &'a UndoSummary,
+    dry_run: bool,
+}
+
+#[derive(Serialize)]
+struct ClockOffsetLine<'a> {
+    event: &'static str,
+    shift: TimeShift,
+    camera: &'a CameraIdentity,
+}
+
+#[derive(Serialize)]
+struct GeonamesIndexLine<'a> {
+    event: &'static str,
+    out: &'a Path,
+    places: usize,
+}
+
+impl Observer for Progress {
+    fn on_event(&self, event: &Event<'_>) {
+        self.stats.on_event(event);
+        match &self.printer {
+            Printer::Text(observer) => observer.on_event(event),
+            Printer::Json(observer) => observer.on_event(event),
+        }
+    }
+}
+
+/// The coloured progress messages of a normal run.
+pub struct TextObserver {
+    pub show_plan: bool,
+}
+
+impl Observer for TextObserver {
+    fn on_event(&self, event: &Event<'_>) {
+        match event {
+            Event::Planned(planned) if self.show_plan => print_planned_info(planned),
+            Event::Renamed {
+                source,
+                destination,
+                action,
+            } => print_renamed_info(source, destination, *action),
+            Event::Skipped { path, reason } => print_skip_info(path, *reason),
+            Event::Failed { error, .. } => {
+                eprintln!("{} {}", "❌".bright_red(), error.to_string().bright_white())
+            }
+            Event::AiNamed { name, .. } => println!(
+                "{}  {}{}{}{}",
+                "✨".bright_yellow(),
+                "AI generated filename: ".bright_yellow(),
+                "'".bright_white(),
+                name.bright_green().bold(),
+                "'".bright_white()
+            ),
+            Event::Scanned { images, videos } => print_scan_info(*images, *videos),
+            Event::MediaLimit { max } => println!(
+                "{}  {}{}{}",
+                "🎯".bright_cyan(),
+                "Limiting this run to the first ".bright_cyan(),
+                max.to_string().bright_white().bold(),
+                " files.".bright_cyan()
+            ),
+            Event::GeoNamesLoading { path } => println!(
+                "{}  {}{}",
+                "🗺️".bright_blue(),
+                "Loading GeoNames data from ".bright_blue(),
+                path.display().to_string().bright_white()
+            ),
+            Event::GeoNamesLoaded { places } => println!(
+                "{}  {}{}",
+                "🗺️".bright_green(),
+                "Loaded offline geocoder with ".bright_green(),
+                format!("{} places", places).bright_white().bold()
+            ),
+            Event::GpxLoaded { points } => println!(
+                "{}  {}{}",
+                "🛰️".bright_blue(),
+                "GPX track points loaded: ".bright_blue(),
+                points.to_string().bright_white()
+            ),
+            Event::GpsCacheLoaded { entries } => println!(
+                "{}  {}{}",
+                "💾".bright_green(),
+                "Loaded GPS cache with ".bright_green(),
+                format!("{} entries", entries).bright_white().bold()
+            ),
+            Event::GpsCacheSaved { entries } => println!(
+                "{}  {}{}",
+                "💾".bright_green(),
+                "Saved GPS cache with ".bright_green(),
+                format!("{} entries", entries).bright_white().bold()
+            ),
+            Event::Processing { path, kind } => println!(
+                "{}  {}{}{}",
+                "📷".bright_blue(),
+                "Processing ".bright_blue(),
+                kind.label().bright_white().bold(),
+                format!(" file: {}", path.display()).bright_blue()
+            ),
+            Event::Paired { paths } => println!(
+                "{}  {}{}",
+                "🔗".bright_blue(),
+                "Paired: ".bright_blue(),
+                join_paths(paths).bright_white()
+            ),
+            Event::DuplicatesFound { count } => println!(
+                "{}  {}{}",
+                "🔁".bright_blue(),
+                "Exact duplicates found: ".bright_blue(),
+                count.to_string().bright_white().bold()
+            ),
+            Event::Duplicate { path, original } => println!(
+                "{}  {}{}{}{}",
+                "🔁".bright_blue(),
+                "Duplicate: ".bright_blue(),
+                path.display().to_string().bright_white(),
+                " is identical to ".bright_blue(),
+                original.display().to_string().bright_white()
+            ),
+            Event::HashingSimilar => println!(
+                "{}  {}",
+                "🔍".bright_blue(),
+                "Computing perceptual hashes to find similar photos...".bright_blue()
+            ),
+            Event::SimilarGroup { group, paths } => println!(
+                "{}  {}{}{}",
+                "📚".bright_blue(),
+                format!("Similar group {} ", group).bright_blue(),
+                format!("({} photos): ", paths.len()).bright_white().bold(),
+                join_paths(paths).bright_white()
+            ),
+            Event::GeocoderLookup { lat, lon } => println!(
+                "{}  {}({}, {})...",
+                "🌍".bright_blue(),
+                "Resolving GPS coordinates ".bright_blue(),
+                lat.to_string().bright_white(),
+                lon.to_string().bright_white()
+            ),
+            Event::AiRequest { model, .. } => println!(
+                "{}  {}{}{}",
+                "🤖".bright_magenta(),
+                "Analyzing image content with AI model: ".bright_magenta(),
+                model.bright_white().bold(),
+                "...".bright_magenta()
+            ),
+            Event::AiRetry { .. } => println!(
+                "{} {}  {}",
+                "⚠️".bright_yellow(),
+                "First attempt failed, retrying...".bright_yellow(),
+                "(model might be loading)".bright_black()
+            ),
+            Event::AiCacheStats { hits, misses } => println!(
+                "{}  {}{}{}{}",
+                "🧠".bright_magenta(),
+                "AI cache: ".bright_magenta(),
+                format!("{} hits", hits).bright_white().bold(),
+                ", ".bright_magenta(),
+                format!("{} misses", misses).bright_white().bold()
+            ),
+            Event::DryRun(planned) => print_dry_run_info(planned),
+            Event::SidecarMoved {
+                source,
+                destination,
+            } => println!(
+                "{}  {}{} {} {}",
+                "📎".bright_green(),
+                "Sidecar: ".bright_green(),
+                source.display().to_string().bright_white(),
+                "→".bright_green(),
+                destination.display().to_string().bright_green()
+            ),
+            Event::XmpWritten { path } => println!(
+                "{}  {}{}",
+                "🏷️".bright_green(),
+                "XMP description: ".bright_green(),
+                path.display().to_string().bright_white()
+            ),
+            Event::Warning { path, message } => print_warning(*path, message),
+            Event::AiFallback { path, reason } => print_warning(Some(path), reason),
+            Event::UndoStarted { journal, entries } => println!(
+                "{}  {}{}{}{}",
+                "⏪".bright_blue(),
+                "Undoing ".bright_blue(),
+                entries.to_string().bright_white().bold(),
+                " renames from ".bright_blue(),
+                journal.display().to_string().bright_white()
+            ),
+            Event::Restored {
+                op,
+                from,
+                to,
+                dry_run,
+            } => print_restored_info(*op, from, to, *dry_run),
+            _ => {}
+        }
+    }
+}
+
+/// Per-file events as JSON Lines, with warnings kept on stderr.
+pub struct JsonObserver;
+
+/// The last line of a JSON run: the run report with the journal.
+#[derive(Serialize)]
+#[serde(tag = "event", rename = "summary")]
+struct RunSummary<'a> {
+    #[serde(flatten)]
+    report: &'a RunReport,
+    journal: Option<&'a Path>,
+}
+
+impl Observer for JsonObserver {
+    fn on_event(&self, event: &Event<'_>) {
+        match event {
+            Event::Warning { path, message } => print_warning(*path, message),
+            Event::AiFallback { path, reason } => print_warning(Some(path), reason),
+            _ => {}
+        }
+        if !event.is_json_event() {
+            return;
+        }
+
+        print_json(event);
+    }
+}
+
+fn print_json(value: &impl Serialize) {
+    match serde_json::to_string(value) {
+        Ok(line) => println!("{}", line),
+        Err(error) => eprintln!("Could not serialize event: {}", error),
+    }
+}
+
+fn join_paths(paths: &[&Path]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_warning(path: Option<&Path>, message: &str) {
+    match path {
+        Some(path) => eprintln!(
+            "{} {}{}",
+            "⚠️".bright_yellow(),
+            format!("{}: ", path.display()).bright_white(),
+            message.bright_yellow()
+        ),
+        None => eprintln!("{} {}", "⚠️".bright_yellow(), message.bright_yellow()),
+    }
+}
+
+fn print_scan_info(images: usize, videos: usize) {
+    if images + videos == 0 {
+        println!(
+            "{}  {}",
+            "📊".bright_blue(),
+            "Found 0 supported media files to process".bright_blue()
+        );
+        return;
+    }
+
+    println!(
+        "{}  {}{}{}{}{}{}{}{}",
+        "📊".bright_blue(),
+        "Found ".bright_blue(),
+        (images + videos).to_string().bright_white().bold(),
+        " supported media files to process ".bright_blue(),
+        "(".bright_black(),
+        images.to_string().bright_white().bold(),
+        " images, ".bright_black(),
+        videos.to_string().bright_white().bold(),
+        " videos)".bright_black()
+    );
+}
+
+fn print_skip_info(path: &Path, reason: SkipReason) {
+    let warning = match reason {
+        SkipReason::AlreadyNamed => {
+            println!(
+                "{}  {}{}",
+                "ℹ️".bright_cyan(),
+                "No rename needed for ".bright_cyan(),
+                path.display().to_string().bright_white()
+            );
+            return;
+        }
+        SkipReason::Duplicate => {
+            println!(
+                "{}  {}{}",
+                "⏭️".bright_cyan(),
+                "Leaving duplicate untouched: ".bright_cyan(),
+                path.display().to_string().bright_white()
+            );
+            return;
+        }
+        SkipReason::SourceMissing => "source no longer exists",
+        SkipReason::DestinationExists => "destination already exists",
+        SkipReason::RenamedFileMissing => "renamed file no longer exists",
+        SkipReason::RenamedFileChanged => "renamed file changed since the run",
+        SkipReason::OriginalTaken => "original path is taken by another file",
+        SkipReason::LastCopy => "original is gone, keeping the only remaining copy",
+        SkipReason::BackupMissing => "its earlier version is gone from the journal's trash folder",
+        SkipReason::KeptCopyChanged => "the kept copy is gone or no longer identical, not deleting",
+        // Files beyond --max-media or without an extension are not mentioned.
+        _ => return,
+    };
+
+    eprintln!(
+        "{} {}{}{}{}",
+        "⚠️".bright_yellow(),
+        "Skipping ".bright_yellow(),
+        path.display().to_string().bright_white(),
+        ": ".bright_yellow(),
+        warning.bright_yellow()
+    );
+}
+
+fn print_planned_info(planned: &PlannedRename) {
+    if planned.action == PlannedAction::Delete {
+        println!(
+            "{}  {}{}",
+            "📝".bright_blue(),
+            "Planned delete: ".bright_blue().bold(),
+            planned.source.display().to_string().bright_white()
+        );
+        return;
+    }
+
+    println!(
+        "{}  {}{} {} {}",
+        "📝".bright_blue(),
+        "Planned: ".bright_blue().bold(),
+        planned.source.display().to_string().bright_white(),
+        "→".bright_blue(),
+        planned.destination.display().to_string().bright_green()
+    );
+    for sidecar in planned.moved_sidecars() {
+        println!(
+            "{}  {}{} {} {}",
+            "📎".bright_blue(),
+            "Planned sidecar: ".bright_blue(),
+            sidecar.source.display().to_string().bright_white(),
+            "→".bright_blue(),
+            sidecar.destination.display().to_string().bright_green()
+        );
+    }
+}
+
+fn print_renamed_info(source: &Path, destination: &Path, action: PlannedAction) {
+    match action {
+        PlannedAction::Rename => println!(
+            "{} {}{} {} {}",
+            "✅".bright_green(),
+            "Successfully renamed: ".bright_green(),
+            source.display().to_string().bright_white(),
+            "→".bright_green(),
+            destination.display().to_string().bright_green().bold()
+        ),
+        PlannedAction::Delete => println!(
+            "{}  {}{}",
+            "🗑️".bright_green(),
+            "Deleted duplicate: ".bright_green(),
+            source.display().to_string().bright_white()
+        ),
+        PlannedAction::Copy | PlannedAction::CopyAndDelete => println!(
+            "{} {}{} {} {}{}",
+            "✅".bright_green(),
+            "Copied and verified: ".bright_green(),
+            source.display().to_string().bright_white(),
+            "→".bright_green(),
+            destination.display().to_string().bright_green().bold(),
+            if action == PlannedAction::CopyAndDelete {
+                " (source deleted)"
+            } else {
+                ""
+            }
+            .bright_black()
+        ),
+    }
+}
+
+fn print_dry_run_info(planned: &PlannedRename) {
+    match planned.action {
+        PlannedAction::Delete => {
+            let kept = planned
+                .reasons
+                .duplicate_of
+                .as_deref()
+                .map(|kept| format!(" (copy of {})", kept.display()))
+                .unwrap_or_default();
+            println!(
+                "{}  {}{}{}",
+                "💁".bright_yellow(),
+                "Dry run: would delete ".bright_yellow().bold(),
+                planned.source.display().to_string().bright_white(),
+                kept.bright_black()
+            );
+            for sidecar in &planned.sidecars {
+                println!(
+                    "{}  {}{}",
+                    "💁".bright_yellow(),
+                    "Dry run (sidecar): would delete ".bright_yellow(),
+                    sidecar.source.display().to_string().bright_white()
+                );
+            }
+            return;
+        }
+        PlannedAction::Rename if planned.source == planned.destination => {}
+        action => {
+            let verb = match action {
+                PlannedAction::Copy => "Dry run (copy): ",
+                PlannedAction::CopyAndDelete => "Dry run (copy, then delete source): ",
+                _ => "Dry run: ",
+            };
+            println!(
+                "{}  {}{} {} {}",
+                "💁".bright_yellow(),
+                verb.bright_yellow().bold(),
+                planned.source.display().to_string().bright_white(),
+                "→".bright_yellow(),
+                planned.destination.display().to_string().bright_green()
+            );
+        }
+    }
+
+    for sidecar in planned.moved_sidecars() {
+        println!(
+            "{}  {}{} {} {}",
+            "💁".bright_yellow(),
+            "Dry run (sidecar): ".bright_yellow(),
+            sidecar.source.display().to_string().bright_white(),
+            "→".bright_yellow(),
+            sidecar.destination.display().to_string().bright_green()
+        );
+    }
+    if let Some(update) = &planned.xmp {
+        println!(
+            "{}  {}{} {}",
+            "💁".bright_yellow(),
+            "Dry run (XMP): ".bright_yellow(),
+            planned
+                .xmp_target(false)
+                .display()
+                .to_string()
+                .bright_white(),
+            format!(
+                "← \"{}\" [{}]",
+                update.description,
+                update.keywords.join(", ")
+            )
+            .bright_black()
+        );
+    }
+}
+
+/// `renamed` is where the run left the file, `original` where undo puts it back.
+fn print_restored_info(op: JournalOp, renamed: &Path, original: &Path, dry_run: bool) {
+    let (icon, label) = match (op, dry_run) {
+        (JournalOp::Move, true) => ("💁", "Dry run: "),
+        (JournalOp::Copy, true) => ("💁", "Dry run: would remove copy "),
+        (JournalOp::Create, true) => ("💁", "Dry run: would remove "),
+        (JournalOp::Trash, true) => ("💁", "Dry run: would restore deleted "),
+        (JournalOp::Replace, true) => ("💁", "Dry run: would restore earlier version of "),
+        (JournalOp::Move, false) => ("✅", "Restored: "),
+        (JournalOp::Copy, false) => ("✅", "Removed copy: "),
+        (JournalOp::Create, false) => ("✅", "Removed: "),
+        (JournalOp::Trash, false) => ("✅", "Restored deleted: "),
+        (JournalOp::Replace, false) => ("✅", "Restored earlier version of "),
+    };
+    let (icon, label) = if dry_run {
+        (icon.bright_yellow(), label.bright_yellow().bold())
+    } else {
+        (icon.bright_green(), label.bright_green())
+    };
+
+    if matches!(op, JournalOp::Move | JournalOp::Trash) {
+        println!(
+            "{}  {}{} {} {}",
+            icon,
+            label,
+            renamed.display().to_string().bright_white(),
+            "→".bright_green(),
+            original.display().to_string().bright_green().bold()
+        );
+    } else {
+        println!(
+            "{}  {}{}",
+            icon,
+            label,
+            renamed.display().to_string().bright_white()
+        );
+    }
+}
+
+/// Lists the journals kept from previous runs.
+fn print_runs(runs: &[RunJournal]) {
+    if runs.is_empty() {
+        println!(
+            "{}  {}",
+            "📒".bright_blue(),
+            "No rename journals yet".bright_blue()
+        );
+        return;
+    }
+
+    for run in runs {
+        let count = run
+            .entries
+            .map(|entries| entries.to_string())
+            .unwrap_or_else(|| "?".to_string());
+        let status = if run.undone {
+            "undone".bright_black()
+        } else {
+            "active".bright_green()
+        };
+        println!(
+            "{}  {}  {}{}  {}",
+            "📒".bright_blue(),
+            run.path.display().to_string().bright_white(),
+            count.bright_white().bold(),
+            " renames".bright_blue(),
+            status
+        );
+    }
+}
+
+fn print_undo_summary(summary: &UndoSummary) {
+    println!(
+        "{}  {}{}{}{}{}{}",
+        "📊".bright_blue(),
+        "Restored ".bright_blue(),
+        summary.restored.to_string().bright_white().bold(),
+        ", skipped ".bright_blue(),
+        summary.skipped.to_string().bright_white().bold(),
+        ", removed empty folders: ".bright_blue(),
+        summary.removed_dirs.to_string().bright_white().bold()
+    );
+}
+
+/// Prints the `--time-shift` and clock rule that fix a camera's clock.
+fn print_clock_offset(offset: &ClockOffset) {
+    let camera = &offset.camera;
+    let camera_name = camera
+        .model
+        .clone()
+        .or_else(|| camera.make.clone())
+        .unwrap_or_else(|| "this camera".to_string());
+    if offset.shift.is_zero() {
+        println!(
+            "{}  {}{}",
+            "✅".bright_green(),
+            "Clocks agree: ".bright_green(),
+            camera_name.bright_white()
+        );
+        return;
+    }
+
+    println!(
+        "{}  {}{}{}",
+        "🕰️".bright_cyan(),
+        format!("Clock of {} is off: use ", camera_name).bright_cyan(),
+        format!("--time-shift={}", offset.shift)
+            .bright_white()
+            .bold(),
+        " or add this rule to a --clock-rules file:".bright_cyan()
+    );
+    let fields = [
+        ("make", &camera.make),
+        ("model", &camera.model),
+        ("serial", &camera.serial),
+    ];
+    println!("{}", "[[camera]]".bright_white());
+    for (key, value) in fields {
+        if let Some(value) = value {
+            println!("{}", format!("{} = {:?}", key, value).bright_white());
+        }
+    }
+    println!("{}", format!("shift = \"{}\"", offset.shift).bright_white());
+}
+
+/// Where a plan was written, for `nf plan`.
+pub fn print_plan_written(out: &Path, renames: usize, unchanged: usize) {
+    println!(
+        "{}  {}{}{}  {}",
+        "📝".bright_green(),
+        format!("Wrote {} planned renames to ", renames).bright_green(),
+        out.display().to_string().bright_white(),
+        format!(" ({} already named)", unchanged).bright_black(),
+        format!("(carry out with `nf apply {}`)", out.display()).bright_black()
+    );
+}
+
+pub fn print_applying(plan: &Path, renames: usize) {
+    println!(
+        "{}  {}{}{}",
+        "📝".bright_blue(),
+        "Applying ".bright_blue(),
+        format!("{} planned renames", renames).bright_white().bold(),
+        format!(" from {}", plan.display()).bright_blue()
+    );
+}
+
+pub fn print_stale_entries(skipped: usize) {
+    eprintln!(
+        "{} {}",
+        "⚠️".bright_yellow(),
+        format!("{} planned renames were skipped", skipped).bright_yellow()
+    );
+}
+
+fn print_geonames_index(out: &Path, places: usize) {
+    println!(
+        "{}  {}{}{}",
+        "✅".bright_green(),
+        "Wrote ".bright_green(),
+        format!("{} places", places).bright_white().bold(),
+        format!(" to {}", out.display()).bright_green()
+    );
+}
+
+/// The end-of-run summary, with the failures last so they are not lost in
+/// the per-file output above.
+fn print_run_report(report: &RunReport) {
+    let files = &report.files;
+    println!("{}", "─".repeat(50).bright_black());
+    let counts = [
+        (files.planned, "planned"),
+        (
+            files.renamed,
+            if report.dry_run {
+                "would be renamed"
+            } else {
+                "renamed"
+            },
+        ),
+        (files.unchanged, "unchanged"),
+        (files.skipped, "skipped"),
+        (files.failed, "failed"),
+        (report.warnings, "warnings"),
+    ]
+    .iter()
+    .map(|(count, label)| format!("{} {}", count.to_string().bright_white().bold(), label))
+    .collect::<Vec<_>>();
+    println!(
+        "{}  {}{}",
+        "📊".bright_blue(),
+        "Summary: ".bright_blue(),
+        counts.join(", ")
+    );
+
+    let ai = &report.ai;
+    if ai.named + ai.fallbacks > 0 {
+        println!(
+            "{}  {}{}{}",
+            "🤖".bright_magenta(),
+            "AI names: ".bright_magenta(),
+            format!("{} generated", ai.named).bright_white().bold(),
+            format!(", {} fallbacks", ai.fallbacks).bright_magenta()
+        );
+    }
+    let gps = &report.gps;
+    if gps.cache_hits + gps.lookups > 0 {
+        println!(
+            "{}  {}{}{}",
+            "🌍".bright_blue(),
+            "Places: ".bright_blue(),
+            format!("{} from cache", gps.cache_hits)
+                .bright_white()
+                .bold(),
+            format!(", {} geocoder lookups", gps.lookups).bright_blue()
+        );
+    }
+    if !report.date_sources.is_empty() {
+        let sources = report
+            .date_sources
+            .iter()
+            .map(|count| {
+                format!(
+                    "{} {}",
+                    count.files,
+                    nameforge::date_source_label(count.source)
+                )
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "{}  {}{}",
+            "📅".bright_blue(),
+            "Dates: ".bright_blue(),
+            sources.join(", ").bright_white()
+        );
+    }
+    if !report.slowest_steps.is_empty() {
+        let steps = report
+            .slowest_steps
+            .iter()
+            .map(|timing| format!("{} {}", timing.step.label(), format_duration(timing.total)))
+            .collect::<Vec<_>>();
+        println!(
+            "{}  {}{}",
+            "🐢".bright_blue(),
+            "Slowest steps: ".bright_blue(),
+            steps.join(", ").bright_white()
+        );
+    }
+
+    if !report.failures.is_empty() {
+        println!(
+            "{}  {}",
+            "❌".bright_red(),
+            format!("{} failures:", report.failures.len())
+                .bright_red()
+                .bold()
+        );
+        for failure in &report.failures {
+            println!("    {}", failure.error.bright_white());
+        }
+    }
+    println!(
+        "{}  {}{}",
+        "⏱️".bright_cyan(),
+        "Completed in: ".bright_cyan(),
+        format_duration(report.elapsed).bright_white().bold()
+    );
+    println!();
+}
+
+fn print_report_written(path: &Path) {
+    println!(
+        "{}  {}{}",
+        "📄".bright_green(),
+        "Run report written to ".bright_green(),
+        path.display().to_string().bright_white()
+    );
+}
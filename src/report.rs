@@ -0,0 +1,426 @@
+use crate::{
+    events::{Event, Observer, SkipReason, Step},
+    exif::DateSource,
+    Error,
+};
+use serde::{Serialize, Serializer};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How many steps the summary lists as the slowest.
+const SLOWEST_STEPS: usize = 5;
+
+/// Collects the statistics of a run from its events.
+pub struct RunStats {
+    started: Instant,
+    tally: Mutex<Tally>,
+}
+
+#[derive(Default)]
+struct Tally {
+    counts: FileCounts,
+    warnings: usize,
+    ai: AiCounts,
+    gps: GpsCounts,
+    date_sources: HashMap<Option<DateSource>, usize>,
+    steps: HashMap<Step, StepTiming>,
+    failures: Vec<Failure>,
+}
+
+/// What happened to the files of a run.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct FileCounts {
+    pub discovered: usize,
+    pub planned: usize,
+    /// Renamed, copied or deleted; in a dry run, what would be.
+    pub renamed: usize,
+    /// Files that already had the name they would get.
+    pub unchanged: usize,
+    /// Files left alone for any other reason.
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct AiCounts {
+    /// Files named by the AI backend.
+    pub named: usize,
+    /// Files named without it because it gave no answer.
+    pub fallbacks: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct GpsCounts {
+    pub cache_hits: usize,
+    /// Queries to the geocoder (API calls with Nominatim).
+    pub lookups: usize,
+}
+
+/// How many files got their capture time from one source.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct DateCount {
+    /// `null` for files without any capture time.
+    pub source: Option<DateSource>,
+    pub files: usize,
+}
+
+/// Time spent in one step, summed over files and worker threads.
+#[derive(Clone, Debug, Serialize)]
+pub struct StepTiming {
+    pub step: Step,
+    /// How often the step ran.
+    pub runs: usize,
+    #[serde(rename = "total_ms", serialize_with = "millis")]
+    pub total: Duration,
+    /// The file the step took longest on, with that time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slowest_file: Option<PathBuf>,
+    #[serde(rename = "slowest_ms", serialize_with = "millis")]
+    pub slowest: Duration,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Failure {
+    /// Missing when the whole run could not start.
+    pub path: Option<PathBuf>,
+    pub error: String,
+}
+
+/// The end-of-run summary, as printed and written by `--report`.
+#[derive(Clone, Debug, Serialize)]
+pub struct RunReport {
+    pub dry_run: bool,
+    #[serde(rename = "elapsed_ms", serialize_with = "millis")]
+    pub elapsed: Duration,
+    #[serde(flatten)]
+    pub files: FileCounts,
+    pub warnings: usize,
+    pub ai: AiCounts,
+    pub gps: GpsCounts,
+    /// Most used source first.
+    pub date_sources: Vec<DateCount>,
+    /// Slowest first.
+    pub slowest_steps: Vec<StepTiming>,
+    pub failures: Vec<Failure>,
+}
+
+impl RunStats {
+    /// Starts the run's clock.
+    pub fn new() -> Self {
+        RunStats {
+            started: Instant::now(),
+            tally: Mutex::new(Tally::default()),
+        }
+    }
+
+    /// Records a failure that stopped the whole run.
+    pub fn fail(&self, error: &Error) {
+        self.on_event(&Event::Failed { path: None, error });
+    }
+
+    pub fn report(&self, dry_run: bool) -> RunReport {
+        let tally = self.tally.lock().unwrap();
+        let mut date_sources: Vec<DateCount> = tally
+            .date_sources
+            .iter()
+            .map(|(&source, &files)| DateCount { source, files })
+            .collect();
+        date_sources.sort_by_key(|count| (std::cmp::Reverse(count.files), count.source.is_none()));
+        let mut slowest_steps: Vec<StepTiming> = tally.steps.values().cloned().collect();
+        slowest_steps.sort_by_key(|timing| (std::cmp::Reverse(timing.total), timing.step));
+        slowest_steps.truncate(SLOWEST_STEPS);
+
+        RunReport {
+            dry_run,
+            elapsed: self.started.elapsed(),
+            files: tally.counts,
+            warnings: tally.warnings,
+            ai: tally.ai,
+            gps: tally.gps,
+            date_sources,
+            slowest_steps,
+            failures: tally.failures.clone(),
+        }
+    }
+}
+
+impl Default for RunStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Observer for RunStats {
+    fn on_event(&self, event: &Event<'_>) {
+        let mut tally = self.tally.lock().unwrap();
+        let counts = &mut tally.counts;
+        match event {
+            Event::Discovered { .. } => counts.discovered += 1,
+            Event::Planned(_) => counts.planned += 1,
+            Event::Renamed { .. } | Event::DryRun(_) => counts.renamed += 1,
+            Event::Skipped {
+                reason: SkipReason::AlreadyNamed,
+                ..
+            } => counts.unchanged += 1,
+            Event::Skipped { .. } => counts.skipped += 1,
+            Event::Failed { path, error } => {
+                counts.failed += 1;
+                tally.failures.push(Failure {
+                    path: path.map(Path::to_path_buf),
+                    error: error.to_string(),
+                });
+            }
+            Event::Warning { .. } => tally.warnings += 1,
+            Event::AiNamed { .. } => tally.ai.named += 1,
+            Event::AiFallback { .. } => tally.ai.fallbacks += 1,
+            Event::GpsCacheStats { hits, lookups } => {
+                tally.gps = GpsCounts {
+                    cache_hits: *hits,
+                    lookups: *lookups,
+                }
+            }
+            Event::Dated { source, .. } => *tally.date_sources.entry(*source).or_default() += 1,
+            Event::StepTimed {
+                step,
+                path,
+                elapsed,
+            } => {
+                let timing = tally.steps.entry(*step).or_insert_with(|| StepTiming {
+                    step: *step,
+                    runs: 0,
+                    total: Duration::ZERO,
+                    slowest_file: None,
+                    slowest: Duration::ZERO,
+                });
+                timing.runs += 1;
+                timing.total += *elapsed;
+                if *elapsed >= timing.slowest {
+                    timing.slowest = *elapsed;
+                    timing.slowest_file = path.map(Path::to_path_buf);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl RunReport {
+    /// Writes the report as Markdown for a `.md` or `.markdown` path, else as JSON.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let markdown = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("markdown")
+            });
+        if markdown {
+            return fs::write(path, self.to_markdown()).map_err(|error| Error::io(path, error));
+        }
+
+        let file = File::create(path).map_err(|error| Error::io(path, error))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .map_err(|error| Error::io(path, error.into()))
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# NameForge run report\n\n");
+        let files = &self.files;
+        let _ = writeln!(
+            out,
+            "{} in {}.\n",
+            if self.dry_run { "Dry run" } else { "Run" },
+            format_duration(self.elapsed)
+        );
+        out.push_str("| Files | Count |\n| --- | ---: |\n");
+        let renamed = if self.dry_run {
+            "Would rename"
+        } else {
+            "Renamed"
+        };
+        for (label, count) in [
+            ("Discovered", files.discovered),
+            ("Planned", files.planned),
+            (renamed, files.renamed),
+            ("Unchanged", files.unchanged),
+            ("Skipped", files.skipped),
+            ("Failed", files.failed),
+            ("Warnings", self.warnings),
+        ] {
+            let _ = writeln!(out, "| {} | {} |", label, count);
+        }
+
+        if self.ai.named + self.ai.fallbacks > 0 || self.gps.cache_hits + self.gps.lookups > 0 {
+            out.push_str("\n## Lookups\n\n");
+            if self.ai.named + self.ai.fallbacks > 0 {
+                let _ = writeln!(
+                    out,
+                    "- AI names: {} generated, {} fallbacks",
+                    self.ai.named, self.ai.fallbacks
+                );
+            }
+            if self.gps.cache_hits + self.gps.lookups > 0 {
+                let _ = writeln!(
+                    out,
+                    "- Places: {} from the GPS cache, {} geocoder lookups",
+                    self.gps.cache_hits, self.gps.lookups
+                );
+            }
+        }
+
+        if !self.date_sources.is_empty() {
+            out.push_str("\n## Capture dates\n\n| Source | Files |\n| --- | ---: |\n");
+            for count in &self.date_sources {
+                let _ = writeln!(
+                    out,
+                    "| {} | {} |",
+                    date_source_label(count.source),
+                    count.files
+                );
+            }
+        }
+
+        if !self.slowest_steps.is_empty() {
+            out.push_str("\n## Slowest steps\n\n| Step | Runs | Total | Slowest file |\n| --- | ---: | ---: | --- |\n");
+            for timing in &self.slowest_steps {
+                let slowest = timing
+                    .slowest_file
+                    .as_ref()
+                    .map(|path| {
+                        format!(
+                            "{} ({})",
+                            markdown_cell(&path.display().to_string()),
+                            format_duration(timing.slowest)
+                        )
+                    })
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} | {} |",
+                    timing.step.label(),
+                    timing.runs,
+                    format_duration(timing.total),
+                    slowest
+                );
+            }
+        }
+
+        if !self.failures.is_empty() {
+            out.push_str("\n## Failures\n\n| File | Error |\n| --- | --- |\n");
+            for failure in &self.failures {
+                let path = failure
+                    .path
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "| {} | {} |",
+                    markdown_cell(&path),
+                    markdown_cell(&failure.error)
+                );
+            }
+        }
+        out
+    }
+}
+
+/// `EXIF`, `XMP`, ... or `no date`.
+pub fn date_source_label(source: Option<DateSource>) -> &'static str {
+    source.map_or("no date", DateSource::label)
+}
+
+/// `2m 5s`, `1.250s` or `80ms`.
+pub fn format_duration(duration: Duration) -> String {
+    let total_secs = duration.as_secs();
+    let millis = duration.subsec_millis();
+
+    if total_secs >= 60 {
+        let mins = total_secs / 60;
+        let secs = total_secs % 60;
+        format!("{}m {}s", mins, secs)
+    } else if total_secs > 0 {
+        format!("{}.{:03}s", total_secs, millis)
+    } else {
+        format!("{}ms", millis)
+    }
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+fn millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_count_outcomes_sources_and_step_times() {
+        let stats = RunStats::new();
+        let photo = Path::new("/photos/IMG_1.jpg");
+        let other = Path::new("/photos/IMG|2.jpg");
+        for (path, source) in [
+            (photo, Some(DateSource::Exif)),
+            (other, Some(DateSource::Exif)),
+            (photo, None),
+        ] {
+            stats.on_event(&Event::Dated { path, source });
+        }
+        stats.on_event(&Event::Skipped {
+            path: photo,
+            reason: SkipReason::AlreadyNamed,
+        });
+        stats.on_event(&Event::Skipped {
+            path: photo,
+            reason: SkipReason::Duplicate,
+        });
+        stats.on_event(&Event::Failed {
+            path: Some(other),
+            error: &Error::NoUniqueName(other.to_path_buf()),
+        });
+        for (step, millis) in [
+            (Step::AiNaming, 900),
+            (Step::AiNaming, 300),
+            (Step::Scan, 50),
+        ] {
+            stats.on_event(&Event::StepTimed {
+                step,
+                path: (step == Step::AiNaming).then_some(photo),
+                elapsed: Duration::from_millis(millis),
+            });
+        }
+
+        let report = stats.report(false);
+        assert_eq!(
+            (
+                report.files.unchanged,
+                report.files.skipped,
+                report.files.failed
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(report.date_sources[0].source, Some(DateSource::Exif));
+        assert_eq!(report.date_sources[0].files, 2);
+        assert_eq!(report.slowest_steps[0].step, Step::AiNaming);
+        assert_eq!(report.slowest_steps[0].total, Duration::from_millis(1200));
+        assert_eq!(report.slowest_steps[0].slowest, Duration::from_millis(900));
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| Unchanged | 1 |"));
+        assert!(markdown.contains("| AI naming | 2 | 1.200s | /photos/IMG_1.jpg (900ms) |"));
+        assert!(markdown.contains("| /photos/IMG\\|2.jpg |"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["slowest_steps"][0]["total_ms"], 1200);
+        assert_eq!(json["date_sources"][1]["source"], serde_json::Value::Null);
+    }
+}
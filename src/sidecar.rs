@@ -0,0 +1,299 @@
+use crate::timezone::{parse_utc_offset, CaptureTime};
+use chrono::{DateTime, NaiveDateTime};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Extensions of files that belong to a photo or video of the same name:
+/// XMP edits (Lightroom, Darktable), Apple `.AAE` adjustments, camera `.THM`
+/// thumbnails, GoPro `.LRV` proxies and Google Takeout `.json` metadata.
+const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "aae", "thm", "lrv", "json"];
+
+/// How a sidecar's name is built from its media file's name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SidecarStyle {
+    /// `IMG_1234.xmp` next to `IMG_1234.JPG`
+    Stem,
+    /// `IMG_1234.JPG.json`, `IMG_1234.JPG.supplemental-metadata.json` or
+    /// `IMG_1234.JPG.xmp` next to `IMG_1234.JPG`
+    FullName,
+}
+
+/// Capture time and place read from a JSON sidecar.
+#[derive(Debug, Default, PartialEq)]
+pub struct SidecarMetadata {
+    pub taken_at: Option<CaptureTime>,
+    pub gps: Option<(f64, f64)>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sidecar {
+    pub path: PathBuf,
+    style: SidecarStyle,
+    extension: String,
+}
+
+impl Sidecar {
+    /// This sidecar's file name once its media file is named `media_file_name`.
+    /// The sidecar keeps its own extension, including its case.
+    pub fn renamed(&self, media_file_name: &str) -> String {
+        let media_name = match self.style {
+            SidecarStyle::Stem => Path::new(media_file_name)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or(media_file_name),
+            SidecarStyle::FullName => media_file_name,
+        };
+        format!("{}.{}", media_name, self.extension)
+    }
+
+    fn is_json(&self) -> bool {
+        self.extension.to_ascii_lowercase().ends_with("json")
+    }
+
+    pub fn is_xmp(&self) -> bool {
+        self.extension.eq_ignore_ascii_case("xmp")
+    }
+}
+
+pub fn is_sidecar(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            SIDECAR_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
+
+/// Matches the sidecars of one folder to the media files in it. Each sidecar
+/// goes to the first media file it fits, so an `IMG_1.AAE` shared by
+/// `IMG_1.JPG` and `IMG_1.MOV` follows only one of them. Returns one list per
+/// media file; sidecars that fit nothing are left out.
+pub fn attach_sidecars(media_paths: &[&Path], sidecar_paths: &[PathBuf]) -> Vec<Vec<Sidecar>> {
+    let mut attached = vec![Vec::new(); media_paths.len()];
+
+    for sidecar_path in sidecar_paths {
+        let Some((name, extension)) = sidecar_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.rsplit_once('.'))
+        else {
+            continue;
+        };
+
+        let matched = media_paths.iter().enumerate().find_map(|(index, media)| {
+            sidecar_style(name, extension, media)
+                .map(|(style, extension)| (index, style, extension))
+        });
+        if let Some((index, style, extension)) = matched {
+            attached[index].push(Sidecar {
+                path: sidecar_path.clone(),
+                style,
+                extension,
+            });
+        }
+    }
+
+    attached
+}
+
+/// How a sidecar name fits a media file, and the part of the sidecar name
+/// that is kept after the media file's new name.
+fn sidecar_style(
+    sidecar_name: &str,
+    extension: &str,
+    media: &Path,
+) -> Option<(SidecarStyle, String)> {
+    let media_file_name = media.file_name()?.to_str()?;
+    let media_stem = media.file_stem()?.to_str()?;
+
+    if sidecar_name.eq_ignore_ascii_case(media_file_name) {
+        Some((SidecarStyle::FullName, extension.to_string()))
+    } else if sidecar_name.eq_ignore_ascii_case(media_stem)
+        || is_gopro_proxy(sidecar_name, extension, media_stem)
+    {
+        Some((SidecarStyle::Stem, extension.to_string()))
+    } else {
+        takeout_qualifier(sidecar_name, extension, media_file_name).map(|qualifier| {
+            (
+                SidecarStyle::FullName,
+                format!("{}.{}", qualifier, extension),
+            )
+        })
+    }
+}
+
+/// Newer Takeout exports name sidecars `IMG_1234.JPG.supplemental-metadata.json`,
+/// cutting the qualifier short when the whole name gets too long.
+fn takeout_qualifier<'a>(
+    sidecar_name: &'a str,
+    extension: &str,
+    media_file_name: &str,
+) -> Option<&'a str> {
+    let qualifier = sidecar_name
+        .get(..media_file_name.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(media_file_name))
+        .and_then(|_| sidecar_name.get(media_file_name.len()..))?
+        .strip_prefix('.')?;
+    (extension.eq_ignore_ascii_case("json")
+        && !qualifier.is_empty()
+        && "supplemental-metadata".starts_with(qualifier))
+    .then_some(qualifier)
+}
+
+/// Reads capture time and location from the first JSON sidecar that has them.
+/// Understands Google Takeout (`photoTakenTime`, `geoData`) and `exiftool -json -n`
+/// output (`DateTimeOriginal`, `GPSLatitude`, `GPSLongitude`).
+pub fn read_sidecar_metadata(sidecars: &[Sidecar]) -> Option<SidecarMetadata> {
+    sidecars
+        .iter()
+        .filter(|sidecar| sidecar.is_json())
+        .filter_map(|sidecar| fs::read_to_string(&sidecar.path).ok())
+        .filter_map(|text| serde_json::from_str::<Value>(&text).ok())
+        .map(|json| parse_sidecar_metadata(&json))
+        .find(|metadata| metadata.taken_at.is_some() || metadata.gps.is_some())
+}
+
+fn parse_sidecar_metadata(json: &Value) -> SidecarMetadata {
+    // exiftool writes an array with one object per file.
+    let json = match json {
+        Value::Array(items) => items.first().unwrap_or(&Value::Null),
+        _ => json,
+    };
+
+    let takeout_time = json
+        .pointer("/photoTakenTime/timestamp")
+        .and_then(|timestamp| match timestamp {
+            Value::String(text) => text.parse::<i64>().ok(),
+            _ => timestamp.as_i64(),
+        })
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .map(CaptureTime::Instant);
+    let exiftool_time = json
+        .get("DateTimeOriginal")
+        .and_then(Value::as_str)
+        .and_then(|text| NaiveDateTime::parse_from_str(text.get(..19)?, "%Y:%m:%d %H:%M:%S").ok())
+        .map(|date_time| {
+            let offset = json
+                .get("OffsetTimeOriginal")
+                .and_then(Value::as_str)
+                .and_then(parse_utc_offset);
+            CaptureTime::new(date_time, offset)
+        });
+
+    let coordinates = |object: &Value, lat: &str, lon: &str| {
+        Some((object.get(lat)?.as_f64()?, object.get(lon)?.as_f64()?))
+    };
+    // Takeout writes 0.0/0.0 when a photo has no location.
+    let gps = ["geoData", "geoDataExif"]
+        .iter()
+        .filter_map(|key| json.get(key))
+        .filter_map(|geo| coordinates(geo, "latitude", "longitude"))
+        .chain(coordinates(json, "GPSLatitude", "GPSLongitude"))
+        .find(|&(lat, lon)| lat != 0.0 || lon != 0.0);
+
+    SidecarMetadata {
+        taken_at: takeout_time.or(exiftool_time),
+        gps,
+    }
+}
+
+/// GoPro writes `GL010123.LRV` next to `GX010123.MP4` (or `GH...` on older
+/// cameras): the proxy swaps the second letter of the prefix for `L`.
+fn is_gopro_proxy(sidecar_name: &str, extension: &str, media_stem: &str) -> bool {
+    let (Some(sidecar_rest), Some(media_prefix)) = (
+        sidecar_name
+            .get(..2)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("GL"))
+            .and_then(|_| sidecar_name.get(2..)),
+        media_stem.get(..2),
+    ) else {
+        return false;
+    };
+
+    matches!(extension.to_ascii_lowercase().as_str(), "lrv" | "thm")
+        && (media_prefix.eq_ignore_ascii_case("GX") || media_prefix.eq_ignore_ascii_case("GH"))
+        && media_stem
+            .get(2..)
+            .is_some_and(|media_rest| media_rest.eq_ignore_ascii_case(sidecar_rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_follow_the_first_matching_media_file() {
+        let media = [
+            Path::new("card/GX010123.MP4"),
+            Path::new("card/IMG_1234.JPG"),
+            Path::new("card/IMG_1234.MOV"),
+        ];
+        let sidecars: Vec<PathBuf> = [
+            "card/GL010123.LRV",
+            "card/IMG_1234.AAE",
+            "card/IMG_1234.JPG.supplemental-metad.json",
+            "card/IMG_1234.MOV.xmp",
+            "card/IMG_9999.xmp",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+
+        let attached = attach_sidecars(&media, &sidecars);
+        let renamed: Vec<Vec<String>> = attached
+            .iter()
+            .map(|sidecars| {
+                sidecars
+                    .iter()
+                    .map(|sidecar| sidecar.renamed("2024-05-01_beach.jpg"))
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(
+            renamed,
+            vec![
+                vec!["2024-05-01_beach.LRV".to_string()],
+                vec![
+                    "2024-05-01_beach.AAE".to_string(),
+                    "2024-05-01_beach.jpg.supplemental-metad.json".to_string(),
+                ],
+                vec!["2024-05-01_beach.jpg.xmp".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_takeout_and_exiftool_json() {
+        let takeout = serde_json::json!({
+            "title": "IMG_1234.JPG",
+            "photoTakenTime": {"timestamp": "1600000000", "formatted": "13 Sep 2020"},
+            "geoData": {"latitude": 0.0, "longitude": 0.0},
+            "geoDataExif": {"latitude": 48.8584, "longitude": 2.2945}
+        });
+        let exiftool = serde_json::json!([{
+            "DateTimeOriginal": "2021:07:04 18:30:00",
+            "GPSLatitude": 37.3317,
+            "GPSLongitude": -122.0307
+        }]);
+
+        let metadata = parse_sidecar_metadata(&takeout);
+        assert_eq!(
+            metadata.taken_at,
+            DateTime::from_timestamp(1_600_000_000, 0).map(CaptureTime::Instant)
+        );
+        assert_eq!(metadata.gps, Some((48.8584, 2.2945)));
+
+        assert_eq!(
+            parse_sidecar_metadata(&exiftool),
+            SidecarMetadata {
+                taken_at: NaiveDateTime::parse_from_str("2021-07-04 18:30:00", "%Y-%m-%d %H:%M:%S")
+                    .ok()
+                    .map(CaptureTime::Floating),
+                gps: Some((37.3317, -122.0307)),
+            }
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::union_find::UnionFind;
+use image::{imageops::FilterType, GrayImage};
+use std::path::Path;
+
+/// Default Hamming distance between dHashes for photos to count as similar.
+pub const DEFAULT_SIMILAR_DISTANCE: u32 = 10;
+
+/// 64-bit difference hash: each bit tells whether a pixel of a 9x8 grayscale
+/// thumbnail is brighter than its right neighbour.
+pub fn dhash(path: &Path) -> Option<u64> {
+    let image = image::open(path).ok()?;
+    let thumbnail = image.grayscale().resize_exact(9, 8, FilterType::Triangle);
+    Some(dhash_of(&thumbnail.to_luma8()))
+}
+
+fn dhash_of(thumbnail: &GrayImage) -> u64 {
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = thumbnail.get_pixel(x, y)[0];
+            let right = thumbnail.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    hash
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Groups hashes that are within `max_distance` of any other member
+/// (single linkage). Only groups with two or more members are returned, in
+/// order of their first member, and members keep input order.
+pub fn group_similar(hashes: &[Option<u64>], max_distance: u32) -> Vec<Vec<usize>> {
+    let mut sets = UnionFind::new(hashes.len());
+    let hashed: Vec<(usize, u64)> = hashes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, hash)| hash.map(|hash| (index, hash)))
+        .collect();
+    for (position, &(a, a_hash)) in hashed.iter().enumerate() {
+        for &(b, b_hash) in &hashed[position + 1..] {
+            if hamming_distance(a_hash, b_hash) <= max_distance {
+                sets.union(a, b);
+            }
+        }
+    }
+
+    sets.groups(hashed.iter().map(|&(index, _)| index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thumbnail_hash(image: &GrayImage) -> u64 {
+        dhash_of(&image::imageops::resize(image, 9, 8, FilterType::Triangle))
+    }
+
+    #[test]
+    fn dhash_ignores_scale_but_not_content() {
+        let gradient = GrayImage::from_fn(90, 80, |x, _| image::Luma([255 - (x * 2) as u8]));
+        let scaled = image::imageops::resize(&gradient, 45, 40, FilterType::Triangle);
+        let flipped = image::imageops::flip_horizontal(&gradient);
+
+        let hash = thumbnail_hash(&gradient);
+
+        assert!(hamming_distance(hash, thumbnail_hash(&scaled)) <= 2);
+        assert!(hamming_distance(hash, thumbnail_hash(&flipped)) > DEFAULT_SIMILAR_DISTANCE);
+    }
+
+    #[test]
+    fn groups_chain_through_close_neighbours() {
+        let hashes = [
+            Some(0b0000),
+            Some(u64::MAX),
+            Some(0b0011),
+            None,
+            Some(0b1111),
+            Some(u64::MAX ^ 1),
+        ];
+
+        assert_eq!(group_similar(&hashes, 2), vec![vec![0, 2, 4], vec![1, 5]]);
+        assert!(group_similar(&hashes, 0).is_empty());
+    }
+}
@@ -0,0 +1,491 @@
+use chrono::{Datelike, NaiveDateTime};
+use std::{fmt, path::PathBuf, str::FromStr};
+
+/// Default layout, equivalent to the historic `{date}_{content}` naming.
+const DEFAULT_NAME_TEMPLATE: &str = "{date}_{content}";
+/// Default folder layout, the flat `YYYY-MM-DD` folders of `--organize-by-date`.
+const DEFAULT_FOLDER_TEMPLATE: &str = "{date}";
+const SEPARATORS: &[char] = &['_', '-', ' ', '.'];
+const FORBIDDEN_LITERAL_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Token {
+    Date,
+    Time,
+    Year,
+    Month,
+    MonthName,
+    Day,
+    Place,
+    Ai,
+    Camera,
+    Title,
+    Keywords,
+    OriginalStem,
+    Kind,
+    Seq,
+    Content,
+}
+
+impl Token {
+    const ALL: &'static [Token] = &[
+        Token::Date,
+        Token::Time,
+        Token::Year,
+        Token::Month,
+        Token::MonthName,
+        Token::Day,
+        Token::Place,
+        Token::Ai,
+        Token::Camera,
+        Token::Title,
+        Token::Keywords,
+        Token::OriginalStem,
+        Token::Kind,
+        Token::Seq,
+        Token::Content,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Token::Date => "date",
+            Token::Time => "time",
+            Token::Year => "year",
+            Token::Month => "month",
+            Token::MonthName => "month_name",
+            Token::Day => "day",
+            Token::Place => "place",
+            Token::Ai => "ai",
+            Token::Camera => "camera",
+            Token::Title => "title",
+            Token::Keywords => "keywords",
+            Token::OriginalStem => "original_stem",
+            Token::Kind => "kind",
+            Token::Seq => "seq",
+            Token::Content => "content",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Token> {
+        Token::ALL
+            .iter()
+            .copied()
+            .find(|token| token.name() == name)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Segment {
+    Literal(String),
+    Token(Token),
+}
+
+/// Values a template can refer to, resolved for a single media file.
+#[derive(Clone, Debug, Default)]
+pub struct NamingContext {
+    pub captured_at: Option<NaiveDateTime>,
+    pub date: Option<String>,
+    pub time: Option<String>,
+    pub place: Option<String>,
+    pub ai: Option<String>,
+    pub camera: Option<String>,
+    /// `dc:title` from XMP
+    pub title: Option<String>,
+    /// The first few `dc:subject` keywords from XMP, joined with underscores
+    pub keywords: Option<String>,
+    pub original_stem: String,
+    pub kind: &'static str,
+    pub seq: usize,
+    pub content: String,
+}
+
+impl NamingContext {
+    fn value(&self, token: Token) -> Option<String> {
+        match token {
+            Token::Date => self.date.clone(),
+            Token::Time => self.time.clone(),
+            Token::Year => self.captured_at.map(|date| format!("{:04}", date.year())),
+            Token::Month => self.captured_at.map(|date| format!("{:02}", date.month())),
+            Token::MonthName => self.captured_at.map(|date| date.format("%B").to_string()),
+            Token::Day => self.captured_at.map(|date| format!("{:02}", date.day())),
+            Token::Place => self.place.clone(),
+            Token::Ai => self.ai.clone(),
+            Token::Camera => self.camera.clone(),
+            Token::Title => self.title.clone(),
+            Token::Keywords => self.keywords.clone(),
+            Token::OriginalStem => Some(self.original_stem.clone()),
+            Token::Kind => Some(self.kind.to_string()),
+            Token::Seq => Some(format!("{:04}", self.seq)),
+            Token::Content => Some(self.content.clone()),
+        }
+    }
+}
+
+/// A user supplied filename layout such as `{date}_{camera}_{place}`.
+///
+/// Templates are validated when parsed, so a bad template is reported before
+/// any file is touched. Tokens without a value render as empty and the
+/// separators around them are collapsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NameTemplate {
+    segments: Vec<Segment>,
+}
+
+impl NameTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let segments = parse_segments(template, template)?;
+
+        if !has_token(&segments) {
+            return Err(format!(
+                "Template '{}' must contain at least one token. Available tokens: {}",
+                template,
+                available_tokens()
+            ));
+        }
+
+        Ok(NameTemplate { segments })
+    }
+
+    fn uses(&self, token: Token) -> bool {
+        self.segments.contains(&Segment::Token(token))
+    }
+
+    pub fn uses_ai(&self) -> bool {
+        self.uses(Token::Ai)
+    }
+
+    pub fn uses_place(&self) -> bool {
+        self.uses(Token::Place)
+    }
+
+    pub fn uses_content(&self) -> bool {
+        self.uses(Token::Content)
+    }
+
+    pub fn uses_camera(&self) -> bool {
+        self.uses(Token::Camera)
+    }
+
+    /// Renders the template, returning an empty string when every token was empty.
+    pub fn render(&self, context: &NamingContext) -> String {
+        render_segments(&self.segments, context)
+    }
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        NameTemplate::parse(DEFAULT_NAME_TEMPLATE).expect("default template is valid")
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        NameTemplate::parse(template)
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_segments(f, &self.segments)
+    }
+}
+
+/// A folder layout such as `{year}/{month}-{month_name}/{date}_{place}`,
+/// rendered relative to the base folder.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FolderTemplate {
+    components: Vec<Vec<Segment>>,
+}
+
+impl FolderTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let components = template
+            .split('/')
+            .map(|component| match component.trim() {
+                "" => Err(format!(
+                    "Empty folder name in template '{}' (use relative paths without '//')",
+                    template
+                )),
+                "." | ".." => Err(format!(
+                    "Folder template '{}' may not contain '.' or '..'",
+                    template
+                )),
+                _ => parse_segments(component, template),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !components.iter().any(|segments| has_token(segments)) {
+            return Err(format!(
+                "Folder template '{}' must contain at least one token. Available tokens: {}",
+                template,
+                available_tokens()
+            ));
+        }
+
+        Ok(FolderTemplate { components })
+    }
+
+    fn uses(&self, token: Token) -> bool {
+        self.components
+            .iter()
+            .any(|segments| segments.contains(&Segment::Token(token)))
+    }
+
+    pub fn uses_ai(&self) -> bool {
+        self.uses(Token::Ai)
+    }
+
+    pub fn uses_place(&self) -> bool {
+        self.uses(Token::Place)
+    }
+
+    /// Renders the relative folder path. Folders whose tokens were all empty
+    /// are dropped, and `None` means no tokenised folder had a value.
+    pub fn render(&self, context: &NamingContext) -> Option<PathBuf> {
+        let mut path = PathBuf::new();
+        let mut has_value = false;
+
+        for segments in &self.components {
+            let rendered = render_segments(segments, context);
+            if rendered.is_empty() {
+                continue;
+            }
+            has_value |= has_token(segments);
+            path.push(rendered);
+        }
+
+        Some(path).filter(|_| has_value)
+    }
+}
+
+impl Default for FolderTemplate {
+    fn default() -> Self {
+        FolderTemplate::parse(DEFAULT_FOLDER_TEMPLATE).expect("default template is valid")
+    }
+}
+
+impl FromStr for FolderTemplate {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        FolderTemplate::parse(template)
+    }
+}
+
+impl fmt::Display for FolderTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, segments) in self.components.iter().enumerate() {
+            if index > 0 {
+                write!(f, "/")?;
+            }
+            write_segments(f, segments)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses one file or folder name of a template; `template` is only used in errors.
+fn parse_segments(part: &str, template: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = part.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' => {
+                let mut name = String::new();
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+                if !closed {
+                    return Err(format!("Unclosed '{{{}' in template '{}'", name, template));
+                }
+                let token = Token::from_name(name.trim()).ok_or_else(|| {
+                    format!(
+                        "Unknown template token '{{{}}}'. Available tokens: {}",
+                        name,
+                        available_tokens()
+                    )
+                })?;
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Token(token));
+            }
+            '}' => return Err(format!("Unmatched '}}' in template '{}'", template)),
+            c if FORBIDDEN_LITERAL_CHARS.contains(&c) || c.is_control() => {
+                return Err(format!(
+                    "Character '{}' is not allowed in template '{}'",
+                    c.escape_default(),
+                    template
+                ));
+            }
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+
+    Ok(segments)
+}
+
+fn has_token(segments: &[Segment]) -> bool {
+    segments
+        .iter()
+        .any(|segment| matches!(segment, Segment::Token(_)))
+}
+
+fn render_segments(segments: &[Segment], context: &NamingContext) -> String {
+    let rendered: String = segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Literal(text) => text.clone(),
+            Segment::Token(token) => context.value(*token).unwrap_or_default(),
+        })
+        .collect();
+
+    collapse_separators(&rendered)
+}
+
+fn write_segments(f: &mut fmt::Formatter<'_>, segments: &[Segment]) -> fmt::Result {
+    for segment in segments {
+        match segment {
+            Segment::Literal(text) => write!(f, "{}", text)?,
+            Segment::Token(token) => write!(f, "{{{}}}", token.name())?,
+        }
+    }
+    Ok(())
+}
+
+fn available_tokens() -> String {
+    Token::ALL
+        .iter()
+        .map(|token| format!("{{{}}}", token.name()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Collapses separator runs left behind by empty tokens and trims the edges.
+fn collapse_separators(input: &str) -> String {
+    let mut collapsed = String::with_capacity(input.len());
+    let mut previous_was_separator = true;
+
+    for ch in input.chars() {
+        let is_separator = SEPARATORS.contains(&ch);
+        if is_separator && previous_was_separator {
+            continue;
+        }
+        collapsed.push(ch);
+        previous_was_separator = is_separator;
+    }
+
+    while collapsed.ends_with(SEPARATORS) {
+        collapsed.pop();
+    }
+
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> NamingContext {
+        NamingContext {
+            captured_at: NaiveDateTime::parse_from_str("2024-05-01 13:45:10", "%Y-%m-%d %H:%M:%S")
+                .ok(),
+            date: Some("2024-05-01".to_string()),
+            time: Some("13-45-10".to_string()),
+            place: Some("Paris".to_string()),
+            camera: Some("Canon_EOS_R5".to_string()),
+            original_stem: "IMG_0001".to_string(),
+            kind: "image",
+            seq: 7,
+            content: "Paris".to_string(),
+            ..NamingContext::default()
+        }
+    }
+
+    #[test]
+    fn default_template_matches_date_content_layout() {
+        let template = NameTemplate::default();
+
+        assert_eq!(template.render(&context()), "2024-05-01_Paris");
+        assert_eq!(
+            template.render(&NamingContext {
+                date: None,
+                ..context()
+            }),
+            "Paris"
+        );
+    }
+
+    #[test]
+    fn render_collapses_separators_around_empty_tokens() {
+        let template = NameTemplate::parse("{date}_{ai}_{camera}-{seq}").unwrap();
+
+        assert_eq!(template.render(&context()), "2024-05-01_Canon_EOS_R5-0007");
+    }
+
+    #[test]
+    fn parse_rejects_unknown_tokens_and_path_characters() {
+        assert!(NameTemplate::parse("{date}_{weather}").is_err());
+        assert!(NameTemplate::parse("{date}/{place}").is_err());
+        assert!(NameTemplate::parse("{date").is_err());
+        assert!(NameTemplate::parse("date}").is_err());
+        assert!(NameTemplate::parse("plain").is_err());
+    }
+
+    #[test]
+    fn display_round_trips_the_template() {
+        let template = NameTemplate::parse("{date} {time}_{original_stem}").unwrap();
+
+        assert_eq!(template.to_string(), "{date} {time}_{original_stem}");
+    }
+
+    #[test]
+    fn folder_template_builds_nested_paths() {
+        let template = FolderTemplate::parse("{year}/{month}-{month_name}/{date}_{place}").unwrap();
+
+        assert_eq!(
+            template.render(&context()),
+            Some(PathBuf::from("2024/05-May/2024-05-01_Paris"))
+        );
+    }
+
+    #[test]
+    fn folder_template_drops_empty_folders_and_needs_a_value() {
+        let template = FolderTemplate::parse("Archive/{year}/{camera}").unwrap();
+        let without_camera = NamingContext {
+            camera: None,
+            ..context()
+        };
+        let undated = NamingContext {
+            captured_at: None,
+            camera: None,
+            ..context()
+        };
+
+        assert_eq!(
+            template.render(&without_camera),
+            Some(PathBuf::from("Archive/2024"))
+        );
+        assert_eq!(template.render(&undated), None);
+    }
+
+    #[test]
+    fn folder_template_rejects_unsafe_paths() {
+        assert!(FolderTemplate::parse("/{year}").is_err());
+        assert!(FolderTemplate::parse("{year}/../{date}").is_err());
+        assert!(FolderTemplate::parse("Archive/Photos").is_err());
+    }
+}
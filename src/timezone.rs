@@ -0,0 +1,114 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+
+/// Which clock capture times are shown in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum TimeZoneMode {
+    /// Coordinated Universal Time
+    Utc,
+    /// This computer's time zone
+    Local,
+    /// The wall-clock time where the photo was taken
+    #[default]
+    Capture,
+}
+
+/// A capture time, as much of it as the source records.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptureTime {
+    /// Wall-clock time without a zone, such as EXIF without `OffsetTimeOriginal`.
+    Floating(NaiveDateTime),
+    /// Wall-clock time with the UTC offset of the place it was taken.
+    Zoned(DateTime<FixedOffset>),
+    /// A moment without the capture zone: MP4 `mvhd`, Takeout timestamps and file times.
+    Instant(DateTime<Utc>),
+}
+
+impl CaptureTime {
+    pub fn new(date_time: NaiveDateTime, offset: Option<FixedOffset>) -> Self {
+        match offset.and_then(|offset| offset.from_local_datetime(&date_time).single()) {
+            Some(zoned) => CaptureTime::Zoned(zoned),
+            None => CaptureTime::Floating(date_time),
+        }
+    }
+
+    /// The calendar time used for names and folders. A floating time is taken
+    /// to be in this computer's zone when converting to UTC, and a moment
+    /// without a capture zone is shown in local time.
+    pub fn in_zone(self, mode: TimeZoneMode) -> NaiveDateTime {
+        match (self, mode) {
+            (CaptureTime::Floating(date_time), TimeZoneMode::Utc) => Local
+                .from_local_datetime(&date_time)
+                .earliest()
+                .map_or(date_time, |local| local.naive_utc()),
+            (CaptureTime::Floating(date_time), _) => date_time,
+            (CaptureTime::Zoned(zoned), TimeZoneMode::Capture) => zoned.naive_local(),
+            (CaptureTime::Zoned(zoned), TimeZoneMode::Local) => {
+                zoned.with_timezone(&Local).naive_local()
+            }
+            (CaptureTime::Zoned(zoned), TimeZoneMode::Utc) => zoned.naive_utc(),
+            (CaptureTime::Instant(instant), TimeZoneMode::Utc) => instant.naive_utc(),
+            (CaptureTime::Instant(instant), _) => instant.with_timezone(&Local).naive_local(),
+        }
+    }
+}
+
+/// Parses a UTC offset as written by EXIF (`+02:00`), XMP and ISO 8601
+/// (`Z`, `+0200`).
+pub fn parse_utc_offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("z") {
+        return FixedOffset::east_opt(0);
+    }
+
+    let sign = match value.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits: String = value[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Turns EXIF `SubSecTime*` digits (`"5"`, `"123"`, `"04"`) into nanoseconds.
+pub fn parse_subseconds(digits: &str) -> Option<u32> {
+    let digits = digits.trim();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    format!("{:0<9}", digits.get(..9).unwrap_or(digits))
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    #[test]
+    fn zoned_times_convert_between_capture_zone_and_utc() {
+        let taken = NaiveDateTime::parse_from_str("2024-05-01 23:30:00", "%Y-%m-%d %H:%M:%S")
+            .unwrap()
+            .with_nanosecond(250_000_000)
+            .unwrap();
+        let zoned = CaptureTime::new(taken, parse_utc_offset("+09:00"));
+
+        assert_eq!(zoned.in_zone(TimeZoneMode::Capture), taken);
+        assert_eq!(
+            zoned.in_zone(TimeZoneMode::Utc).to_string(),
+            "2024-05-01 14:30:00.250"
+        );
+        assert_eq!(
+            CaptureTime::new(taken, None).in_zone(TimeZoneMode::Capture),
+            taken
+        );
+        assert_eq!(parse_utc_offset("-0330"), FixedOffset::west_opt(12_600));
+        assert_eq!(parse_utc_offset("Z"), FixedOffset::east_opt(0));
+        assert_eq!(parse_subseconds("25"), Some(250_000_000));
+    }
+}
@@ -0,0 +1,54 @@
+/// Disjoint sets over `0..len`. Each set's root is its smallest member, so
+/// groups come out in order of their first member.
+pub(crate) struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    pub fn new(len: usize) -> Self {
+        UnionFind {
+            parent: (0..len).collect(),
+        }
+    }
+
+    pub fn root(&mut self, mut index: usize) -> usize {
+        while self.parent[index] != index {
+            self.parent[index] = self.parent[self.parent[index]];
+            index = self.parent[index];
+        }
+        index
+    }
+
+    pub fn union(&mut self, a: usize, b: usize) {
+        let (root_a, root_b) = (self.root(a), self.root(b));
+        self.parent[root_a.max(root_b)] = root_a.min(root_b);
+    }
+
+    /// The sets of two or more of `members`, which keep their given order.
+    pub fn groups(mut self, members: impl IntoIterator<Item = usize>) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = vec![Vec::new(); self.parent.len()];
+        for index in members {
+            let group_root = self.root(index);
+            groups[group_root].push(index);
+        }
+        groups.retain(|members| members.len() > 1);
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_are_ordered_by_their_first_member() {
+        let mut sets = UnionFind::new(6);
+        sets.union(4, 1);
+        sets.union(5, 3);
+        sets.union(3, 1);
+        sets.union(2, 0);
+
+        assert_eq!(sets.root(4), 1);
+        assert_eq!(sets.groups(0..6), vec![vec![0, 2], vec![1, 3, 4, 5]]);
+    }
+}
@@ -1,11 +1,47 @@
-use std::path::Path;
-
-pub fn create_date_folder_path(
-    base_folder: &Path,
-    date_part: &str,
-    new_filename: &str,
-) -> std::path::PathBuf {
-    base_folder.join(date_part).join(new_filename)
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// A new empty folder under the system temp dir for a test. `name` keeps
+/// leftovers recognisable; the process id and a counter keep parallel tests apart.
+#[cfg(test)]
+pub(crate) fn create_temp_dir(name: &str) -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "nameforge-{}-{}-{}",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// SHA-256 of a file's contents as lowercase hex, read in chunks.
+pub fn hash_file_contents(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// SHA-256 of a string as lowercase hex.
+pub fn hash_text(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
 }
 
 pub fn sanitize_filename_fragment(input: &str) -> String {
@@ -53,44 +89,69 @@ fn generate_filename_with_counter(base_name: &str, ext: &str, counter: u32) -> S
     }
 }
 
-fn is_available_path(candidate_path: &Path, original_path: Option<&Path>) -> bool {
+fn is_available_path(
+    candidate_path: &Path,
+    original_path: Option<&Path>,
+    reserved: &HashSet<PathBuf>,
+) -> bool {
     match original_path {
         Some(original_path) if candidate_path == original_path => true,
-        _ => !candidate_path.exists(),
+        _ => !candidate_path.exists() && !reserved.contains(candidate_path),
+    }
+}
+
+/// Filename for `base_name` with an optional collision counter.
+pub fn filename_with_suffix(base_name: &str, ext: &str, suffix: Option<u32>) -> String {
+    match suffix {
+        None => normalize_filename_with_extension(base_name, ext),
+        Some(counter) => generate_filename_with_counter(base_name, ext, counter),
     }
 }
 
+/// Filenames tried for a base name, in order: the plain name, then `_1`, `_2`, ...
+pub fn candidate_filenames<'a>(
+    base_name: &'a str,
+    ext: &'a str,
+) -> impl Iterator<Item = (String, Option<u32>)> + 'a {
+    std::iter::once(None)
+        .chain((1..).map(Some))
+        .map(move |suffix| (filename_with_suffix(base_name, ext, suffix), suffix))
+}
+
 pub fn unique_filename(
     folder: &Path,
     original_path: Option<&Path>,
     base_name: &str,
     ext: &str,
-) -> Option<String> {
-    let initial_filename = normalize_filename_with_extension(base_name, ext);
+    reserved: &HashSet<PathBuf>,
+) -> Option<(String, Option<u32>)> {
+    unique_shared_suffix(&[(folder, original_path, ext)], base_name, reserved)
+        .map(|suffix| (filename_with_suffix(base_name, ext, suffix), suffix))
+}
 
-    // Generate sequence of potential filenames until we find one that doesn't exist
-    std::iter::once(initial_filename.clone())
-        .chain((1..).map(|counter| generate_filename_with_counter(base_name, ext, counter)))
-        .find(|filename| is_available_path(&folder.join(filename), original_path))
+/// The first collision suffix that is free for every file of a group that must
+/// share one name. Members are `(folder, current path, extension)`.
+pub fn unique_shared_suffix(
+    members: &[(&Path, Option<&Path>, &str)],
+    base_name: &str,
+    reserved: &HashSet<PathBuf>,
+) -> Option<Option<u32>> {
+    // Generate sequence of potential suffixes until one is free for all members
+    std::iter::once(None)
+        .chain((1..).map(Some))
+        .find(|&suffix| {
+            let mut taken = HashSet::new();
+            members.iter().all(|&(folder, original_path, ext)| {
+                let candidate = folder.join(filename_with_suffix(base_name, ext, suffix));
+                is_available_path(&candidate, original_path, reserved) && taken.insert(candidate)
+            })
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{
-        fs,
-        time::{SystemTime, UNIX_EPOCH},
-    };
-
-    fn create_temp_dir() -> std::path::PathBuf {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let temp_dir = std::env::temp_dir().join(format!("nameforge-test-{}", timestamp));
-        fs::create_dir_all(&temp_dir).unwrap();
-        temp_dir
-    }
+    use std::fs;
 
     #[test]
     fn sanitize_filename_fragment_normalizes_separators() {
@@ -102,13 +163,19 @@ mod tests {
 
     #[test]
     fn unique_filename_allows_the_current_path() {
-        let temp_dir = create_temp_dir();
+        let temp_dir = create_temp_dir("utils");
         let existing_path = temp_dir.join("2024-05-01_video.mp4");
         fs::write(&existing_path, b"video").unwrap();
 
-        let filename = unique_filename(&temp_dir, Some(&existing_path), "2024-05-01_video", "mp4");
+        let filename = unique_filename(
+            &temp_dir,
+            Some(&existing_path),
+            "2024-05-01_video",
+            "mp4",
+            &HashSet::new(),
+        );
 
-        assert_eq!(filename.as_deref(), Some("2024-05-01_video.mp4"));
+        assert_eq!(filename, Some(("2024-05-01_video.mp4".to_string(), None)));
 
         fs::remove_file(existing_path).unwrap();
         fs::remove_dir(temp_dir).unwrap();
@@ -116,15 +183,51 @@ mod tests {
 
     #[test]
     fn unique_filename_uses_a_counter_for_collisions() {
-        let temp_dir = create_temp_dir();
+        let temp_dir = create_temp_dir("utils");
         let existing_path = temp_dir.join("2024-05-01_video.mp4");
         fs::write(&existing_path, b"video").unwrap();
 
-        let filename = unique_filename(&temp_dir, None, "2024-05-01_video", "mp4");
+        let filename = unique_filename(&temp_dir, None, "2024-05-01_video", "mp4", &HashSet::new());
 
-        assert_eq!(filename.as_deref(), Some("2024-05-01_video_1.mp4"));
+        assert_eq!(
+            filename,
+            Some(("2024-05-01_video_1.mp4".to_string(), Some(1)))
+        );
 
         fs::remove_file(existing_path).unwrap();
         fs::remove_dir(temp_dir).unwrap();
     }
+
+    #[test]
+    fn unique_filename_skips_names_reserved_earlier_in_the_run() {
+        let temp_dir = create_temp_dir("utils");
+        let reserved = HashSet::from([temp_dir.join("2024-05-01_beach.jpg")]);
+
+        let filename = unique_filename(&temp_dir, None, "2024-05-01_beach", "jpg", &reserved);
+
+        assert_eq!(
+            filename,
+            Some(("2024-05-01_beach_1.jpg".to_string(), Some(1)))
+        );
+
+        fs::remove_dir(temp_dir).unwrap();
+    }
+
+    #[test]
+    fn shared_suffix_is_free_for_every_member() {
+        let temp_dir = create_temp_dir("utils");
+        let taken_raw = temp_dir.join("2024-05-01_beach.nef");
+        fs::write(&taken_raw, b"raw").unwrap();
+
+        let suffix = unique_shared_suffix(
+            &[(&temp_dir, None, "jpg"), (&temp_dir, None, "nef")],
+            "2024-05-01_beach",
+            &HashSet::new(),
+        );
+
+        assert_eq!(suffix, Some(Some(1)));
+
+        fs::remove_file(taken_raw).unwrap();
+        fs::remove_dir(temp_dir).unwrap();
+    }
 }
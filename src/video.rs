@@ -0,0 +1,446 @@
+use crate::timezone::CaptureTime;
+use chrono::{DateTime, NaiveDateTime};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Seconds between the ISO-BMFF epoch (1904-01-01) and the Unix epoch.
+const MP4_EPOCH_OFFSET: i64 = 2_082_844_800;
+/// Upper bound for the `moov` box we are willing to load into memory.
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+const TOP_LEVEL_BOX_TYPES: &[&[u8; 4]] = &[
+    b"ftyp", b"moov", b"mdat", b"free", b"skip", b"wide", b"pnot", b"uuid",
+];
+
+const CREATION_DATE_KEY: &str = "com.apple.quicktime.creationdate";
+const LOCATION_KEY: &str = "com.apple.quicktime.location.ISO6709";
+const CONTENT_IDENTIFIER_KEY: &str = "com.apple.quicktime.content.identifier";
+
+/// Capture metadata read from an MP4/MOV container.
+#[derive(Debug, Default, PartialEq)]
+pub struct VideoMetadata {
+    pub created_at: Option<CaptureTime>,
+    pub gps: Option<(f64, f64)>,
+    /// Live Photo identifier shared with the matching HEIC/JPEG.
+    pub content_identifier: Option<String>,
+}
+
+/// Reads capture time and location from an ISO-BMFF (MP4/MOV/3GP) file.
+///
+/// Apple's `com.apple.quicktime.*` keys win over the generic `mvhd` creation
+/// time and `©xyz` atom, because they keep the capture timezone.
+pub fn read_video_metadata(path: &Path) -> Option<VideoMetadata> {
+    let file = File::open(path).ok()?;
+    let moov = read_moov_box(&mut BufReader::new(file))?;
+    Some(parse_moov(&moov))
+}
+
+struct BoxHeader {
+    box_type: [u8; 4],
+    header_len: u64,
+    /// Total size including the header, `None` when the box runs to the end.
+    size: Option<u64>,
+}
+
+fn read_box_header<R: Read>(reader: &mut R) -> Option<BoxHeader> {
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header).ok()?;
+    let size = u32::from_be_bytes(header[0..4].try_into().ok()?) as u64;
+    let box_type: [u8; 4] = header[4..8].try_into().ok()?;
+
+    match size {
+        0 => Some(BoxHeader {
+            box_type,
+            header_len: 8,
+            size: None,
+        }),
+        1 => {
+            let mut large = [0u8; 8];
+            reader.read_exact(&mut large).ok()?;
+            Some(BoxHeader {
+                box_type,
+                header_len: 16,
+                size: Some(u64::from_be_bytes(large)),
+            })
+        }
+        size => Some(BoxHeader {
+            box_type,
+            header_len: 8,
+            size: Some(size),
+        }),
+    }
+}
+
+/// Walks the top-level boxes, skipping media data, until `moov` is found.
+fn read_moov_box<R: Read + Seek>(reader: &mut R) -> Option<Vec<u8>> {
+    let mut is_first_box = true;
+
+    loop {
+        let header = read_box_header(reader)?;
+        if is_first_box && !TOP_LEVEL_BOX_TYPES.contains(&&header.box_type) {
+            return None;
+        }
+        is_first_box = false;
+
+        let body_len = header.size?.checked_sub(header.header_len)?;
+        if &header.box_type == b"moov" {
+            if body_len > MAX_MOOV_SIZE {
+                return None;
+            }
+            let mut body = vec![0u8; body_len as usize];
+            reader.read_exact(&mut body).ok()?;
+            return Some(body);
+        }
+
+        // A skip that does not fit an i64 would turn into a seek backwards.
+        reader
+            .seek(SeekFrom::Current(i64::try_from(body_len).ok()?))
+            .ok()?;
+    }
+}
+
+/// Iterates over the child boxes of an in-memory box body.
+fn child_boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut offset = 0usize;
+    std::iter::from_fn(move || {
+        let header = data.get(offset..offset.checked_add(8)?)?;
+        let size = u32::from_be_bytes(header[0..4].try_into().ok()?) as usize;
+        let box_type: [u8; 4] = header[4..8].try_into().ok()?;
+        let (header_len, size) = match size {
+            0 => (8, data.len() - offset),
+            1 => {
+                let large = data.get(offset.checked_add(8)?..offset.checked_add(16)?)?;
+                (
+                    16,
+                    usize::try_from(u64::from_be_bytes(large.try_into().ok()?)).ok()?,
+                )
+            }
+            size => (8, size),
+        };
+        if size < header_len {
+            return None;
+        }
+        let body = data.get(offset.checked_add(header_len)?..offset.checked_add(size)?)?;
+        offset = offset.checked_add(size)?;
+        Some((box_type, body))
+    })
+}
+
+fn find_child<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    child_boxes(data)
+        .find(|(child_type, _)| child_type == box_type)
+        .map(|(_, body)| body)
+}
+
+fn parse_moov(moov: &[u8]) -> VideoMetadata {
+    let apple = find_child(moov, b"meta")
+        .or_else(|| find_child(moov, b"udta").and_then(|udta| find_child(udta, b"meta")))
+        .map(parse_quicktime_keys)
+        .unwrap_or_default();
+
+    let created_at = apple
+        .created_at
+        .or_else(|| find_child(moov, b"mvhd").and_then(parse_mvhd_creation_time));
+    let gps = apple.gps.or_else(|| {
+        find_child(moov, b"udta")
+            .and_then(|udta| find_child(udta, b"\xa9xyz"))
+            .and_then(parse_user_data_text)
+            .and_then(|text| parse_iso6709(&text))
+    });
+
+    VideoMetadata {
+        created_at,
+        gps,
+        content_identifier: apple.content_identifier,
+    }
+}
+
+fn parse_mvhd_creation_time(mvhd: &[u8]) -> Option<CaptureTime> {
+    let seconds = match mvhd.first()? {
+        0 => u32::from_be_bytes(mvhd.get(4..8)?.try_into().ok()?) as i64,
+        1 => i64::try_from(u64::from_be_bytes(mvhd.get(4..12)?.try_into().ok()?)).ok()?,
+        _ => return None,
+    };
+
+    // Many encoders leave the creation time at zero.
+    if seconds == 0 {
+        return None;
+    }
+
+    // `mvhd` is in UTC and does not say where the video was taken.
+    DateTime::from_timestamp(seconds - MP4_EPOCH_OFFSET, 0).map(CaptureTime::Instant)
+}
+
+/// QuickTime user data text: 16-bit length, 16-bit language, then the text.
+fn parse_user_data_text(body: &[u8]) -> Option<String> {
+    let len = u16::from_be_bytes(body.get(0..2)?.try_into().ok()?) as usize;
+    let text = body.get(4..4 + len).or_else(|| body.get(4..))?;
+    Some(String::from_utf8_lossy(text).into_owned())
+}
+
+/// Parses the `keys`/`ilst` pair of a QuickTime `meta` box.
+fn parse_quicktime_keys(meta: &[u8]) -> VideoMetadata {
+    // QuickTime `meta` is a plain box, MP4 `meta` is a full box with 4 extra bytes.
+    let meta = if meta.get(4..8) == Some(&b"hdlr"[..]) {
+        meta
+    } else {
+        meta.get(4..).unwrap_or_default()
+    };
+
+    let keys = find_child(meta, b"keys")
+        .map(parse_keys)
+        .unwrap_or_default();
+    let Some(ilst) = find_child(meta, b"ilst") else {
+        return VideoMetadata::default();
+    };
+
+    let mut metadata = VideoMetadata::default();
+    for (index_bytes, item) in child_boxes(ilst) {
+        let index = u32::from_be_bytes(index_bytes) as usize;
+        let Some(key) = index.checked_sub(1).and_then(|index| keys.get(index)) else {
+            continue;
+        };
+        let Some(value) = find_child(item, b"data").and_then(parse_data_text) else {
+            continue;
+        };
+
+        match key.as_str() {
+            CREATION_DATE_KEY => metadata.created_at = parse_creation_date(&value),
+            LOCATION_KEY => metadata.gps = parse_iso6709(&value),
+            CONTENT_IDENTIFIER_KEY => metadata.content_identifier = Some(value),
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+fn parse_keys(keys: &[u8]) -> Vec<String> {
+    let Some(count) = keys
+        .get(4..8)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u32::from_be_bytes)
+    else {
+        return Vec::new();
+    };
+
+    // Each key entry is a box whose "type" is the namespace (usually `mdta`).
+    child_boxes(keys.get(8..).unwrap_or_default())
+        .take(count as usize)
+        .map(|(_, name)| String::from_utf8_lossy(name).into_owned())
+        .collect()
+}
+
+/// `data` atoms start with a type indicator and locale; type 1 is UTF-8 text.
+fn parse_data_text(data: &[u8]) -> Option<String> {
+    let type_indicator = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?);
+    if type_indicator != 1 {
+        return None;
+    }
+    Some(String::from_utf8_lossy(data.get(8..)?).into_owned())
+}
+
+/// Parses ISO 8601 dates such as `2023-08-31T14:22:10+0200` together with
+/// the UTC offset of the capture location.
+fn parse_creation_date(value: &str) -> Option<CaptureTime> {
+    let value = value.trim();
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%z"))
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .map(CaptureTime::Zoned)
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+                .map(CaptureTime::Floating)
+                .ok()
+        })
+}
+
+/// Parses the latitude and longitude of an ISO 6709 string like `+48.8584+002.2945+035.000/`.
+pub fn parse_iso6709(value: &str) -> Option<(f64, f64)> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+
+    for ch in value.trim().chars() {
+        match ch {
+            '+' | '-' | '/' => {
+                if !current.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                }
+                if ch == '/' {
+                    break;
+                }
+                current.push(ch);
+            }
+            c if c.is_ascii_digit() || c == '.' => current.push(c),
+            _ => return None,
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    let lat = parse_iso6709_component(parts.first()?, 2)?;
+    let lon = parse_iso6709_component(parts.get(1)?, 3)?;
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then_some((lat, lon))
+}
+
+/// Handles the degree, degree-minute and degree-minute-second forms of ISO 6709.
+fn parse_iso6709_component(component: &str, degree_digits: usize) -> Option<f64> {
+    let (sign, digits) = match component.split_at(1) {
+        ("-", rest) => (-1.0, rest),
+        ("+", rest) => (1.0, rest),
+        _ => return None,
+    };
+    let integer_len = digits.find('.').unwrap_or(digits.len());
+    let value: f64 = digits.parse().ok()?;
+
+    let degrees = match integer_len.checked_sub(degree_digits)? {
+        0 => value,
+        2 => {
+            let minutes = value % 100.0;
+            (value - minutes) / 100.0 + minutes / 60.0
+        }
+        4 => {
+            let seconds = value % 100.0;
+            let minutes = ((value - seconds) / 100.0) % 100.0;
+            let degrees = (value - seconds - minutes * 100.0) / 10_000.0;
+            degrees + minutes / 60.0 + seconds / 3600.0
+        }
+        _ => return None,
+    };
+
+    Some(sign * degrees)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn make_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(box_type);
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    fn mvhd(seconds_since_1904: u32) -> Vec<u8> {
+        let mut body = vec![0u8; 4];
+        body.extend_from_slice(&seconds_since_1904.to_be_bytes());
+        body.extend_from_slice(&[0u8; 92]);
+        make_box(b"mvhd", &body)
+    }
+
+    #[test]
+    fn reads_mvhd_time_and_xyz_location() {
+        let mut xyz = 18u16.to_be_bytes().to_vec();
+        xyz.extend_from_slice(&[0x15, 0xc7]);
+        xyz.extend_from_slice(b"+37.3317-122.0307/");
+        let udta = make_box(b"udta", &make_box(b"\xa9xyz", &xyz));
+        let unix_seconds = 1_700_000_000i64;
+        let moov = make_box(
+            b"moov",
+            &[mvhd((unix_seconds + MP4_EPOCH_OFFSET) as u32), udta].concat(),
+        );
+        let file = [
+            make_box(b"ftyp", b"isom"),
+            make_box(b"mdat", &[0u8; 32]),
+            moov,
+        ]
+        .concat();
+
+        let moov = read_moov_box(&mut Cursor::new(file)).unwrap();
+        let metadata = parse_moov(&moov);
+
+        assert_eq!(
+            metadata.created_at,
+            DateTime::from_timestamp(unix_seconds, 0).map(CaptureTime::Instant)
+        );
+        assert_eq!(metadata.gps, Some((37.3317, -122.0307)));
+    }
+
+    #[test]
+    fn apple_keys_take_precedence_over_mvhd() {
+        let key_entry = |name: &str| make_box(b"mdta", name.as_bytes());
+        let mut keys = vec![0u8; 4];
+        keys.extend_from_slice(&3u32.to_be_bytes());
+        keys.extend(key_entry(LOCATION_KEY));
+        keys.extend(key_entry(CREATION_DATE_KEY));
+        keys.extend(key_entry(CONTENT_IDENTIFIER_KEY));
+
+        let data = |text: &str| {
+            let mut body = 1u32.to_be_bytes().to_vec();
+            body.extend_from_slice(&[0u8; 4]);
+            body.extend_from_slice(text.as_bytes());
+            make_box(b"data", &body)
+        };
+        let ilst = [
+            make_box(&1u32.to_be_bytes(), &data("+48.8584+002.2945+035.000/")),
+            make_box(&2u32.to_be_bytes(), &data("2023-08-31T14:22:10+0200")),
+            make_box(&3u32.to_be_bytes(), &data("5F3C1A2E-LIVE")),
+        ]
+        .concat();
+        let meta = [
+            make_box(b"hdlr", &[0u8; 24]),
+            make_box(b"keys", &keys),
+            make_box(b"ilst", &ilst),
+        ]
+        .concat();
+        let moov = [mvhd(3_000_000_000), make_box(b"meta", &meta)].concat();
+
+        let metadata = parse_moov(&moov);
+
+        assert_eq!(
+            metadata.created_at,
+            DateTime::parse_from_rfc3339("2023-08-31T14:22:10+02:00")
+                .ok()
+                .map(CaptureTime::Zoned)
+        );
+        assert_eq!(metadata.gps, Some((48.8584, 2.2945)));
+        assert_eq!(
+            metadata.content_identifier.as_deref(),
+            Some("5F3C1A2E-LIVE")
+        );
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_iso_bmff() {
+        let riff = b"RIFF\x24\x00\x00\x00AVI LIST".to_vec();
+
+        assert_eq!(read_moov_box(&mut Cursor::new(riff)), None);
+    }
+
+    #[test]
+    fn stops_at_a_largesize_past_the_end() {
+        let mut huge = 1u32.to_be_bytes().to_vec();
+        huge.extend_from_slice(b"free");
+        huge.extend_from_slice(&u64::MAX.to_be_bytes());
+        let moov = [make_box(b"mvhd", &[0u8; 4]), huge].concat();
+
+        let children: Vec<_> = child_boxes(&moov).map(|(box_type, _)| box_type).collect();
+
+        assert_eq!(children, vec![*b"mvhd"]);
+    }
+
+    #[test]
+    fn a_largesize_that_would_seek_backwards_ends_the_walk() {
+        let mut backwards = 1u32.to_be_bytes().to_vec();
+        backwards.extend_from_slice(b"free");
+        backwards.extend_from_slice(&(u64::MAX - 15).to_be_bytes());
+        // Read as a signed skip, the size would jump back to the start of `ftyp`.
+        let file = [make_box(b"ftyp", b"qt  \0\0\0\0"), backwards].concat();
+
+        assert_eq!(read_moov_box(&mut Cursor::new(file)), None);
+    }
+
+    #[test]
+    fn parses_iso6709_minute_forms() {
+        let (lat, lon) = parse_iso6709("+4851.30+00217.70/").unwrap();
+
+        assert!((lat - 48.855).abs() < 1e-9);
+        assert!((lon - 2.295).abs() < 1e-9);
+    }
+}
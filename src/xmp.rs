@@ -0,0 +1,409 @@
+use crate::timezone::{parse_utc_offset, CaptureTime};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+};
+
+/// How much of an image is searched for an embedded XMP packet; JPEG, TIFF
+/// and HEIF writers put it near the start of the file.
+const EMBEDDED_XMP_SEARCH_LIMIT: u64 = 4 * 1024 * 1024;
+const PACKET_START: &[u8] = b"<x:xmpmeta";
+const PACKET_END: &[u8] = b"</x:xmpmeta>";
+
+/// Naming inputs read from an XMP packet.
+#[derive(Debug, Default, PartialEq)]
+pub struct XmpMetadata {
+    pub taken_at: Option<CaptureTime>,
+    pub gps: Option<(f64, f64)>,
+    pub title: Option<String>,
+    pub keywords: Vec<String>,
+}
+
+impl XmpMetadata {
+    /// Fills the fields `self` is missing from `other`.
+    pub fn or(self, other: XmpMetadata) -> XmpMetadata {
+        XmpMetadata {
+            taken_at: self.taken_at.or(other.taken_at),
+            gps: self.gps.or(other.gps),
+            title: self.title.or(other.title),
+            keywords: if self.keywords.is_empty() {
+                other.keywords
+            } else {
+                self.keywords
+            },
+        }
+    }
+}
+
+/// An AI description and keywords to store in a file's XMP sidecar.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct XmpUpdate {
+    pub description: String,
+    pub keywords: Vec<String>,
+}
+
+/// Reads the XMP packet embedded in an image, if it has one.
+pub fn read_embedded_xmp(path: &Path) -> Option<XmpMetadata> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(EMBEDDED_XMP_SEARCH_LIMIT)
+        .read_to_end(&mut bytes)
+        .ok()?;
+
+    let start = find_bytes(&bytes, PACKET_START)?;
+    let end = start + find_bytes(&bytes[start..], PACKET_END)? + PACKET_END.len();
+    Some(parse_xmp(&String::from_utf8_lossy(&bytes[start..end])))
+}
+
+/// Reads an `.xmp` sidecar file.
+pub fn read_xmp_file(path: &Path) -> Option<XmpMetadata> {
+    fs::read_to_string(path).ok().map(|text| parse_xmp(&text))
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Extracts capture time, GPS, title and keywords. Properties may be written
+/// as attributes of `rdf:Description` or as child elements; both are read.
+pub fn parse_xmp(text: &str) -> XmpMetadata {
+    let taken_at = ["exif:DateTimeOriginal", "photoshop:DateCreated"]
+        .iter()
+        .filter_map(|name| simple_property(text, name))
+        .find_map(|value| parse_xmp_date(&value));
+    let gps = simple_property(text, "exif:GPSLatitude")
+        .and_then(|lat| parse_xmp_coordinate(&lat))
+        .zip(simple_property(text, "exif:GPSLongitude").and_then(|lon| parse_xmp_coordinate(&lon)));
+
+    XmpMetadata {
+        taken_at,
+        gps,
+        title: list_items(text, "dc:title")
+            .into_iter()
+            .find(|title| !title.is_empty()),
+        keywords: list_items(text, "dc:subject"),
+    }
+}
+
+/// A property written as `name="value"` or `<name>value</name>`.
+pub fn simple_property(text: &str, name: &str) -> Option<String> {
+    let attribute = format!("{}=", name);
+    let from_attribute = text.match_indices(&attribute).find_map(|(index, _)| {
+        let preceded_by_space = text[..index]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_whitespace);
+        let rest = &text[index + attribute.len()..];
+        let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let value = &rest[1..];
+        let value = &value[..value.find(quote)?];
+        preceded_by_space.then(|| value.to_string())
+    });
+
+    from_attribute
+        .or_else(|| element_body(text, name).map(str::to_string))
+        .map(|value| unescape(value.trim()))
+        .filter(|value| !value.is_empty() && !value.contains('<'))
+}
+
+/// The text between `<name ...>` and `</name>`.
+pub fn element_body<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}", name);
+    let (index, _) = text.match_indices(&open).find(|(index, _)| {
+        text[index + open.len()..]
+            .chars()
+            .next()
+            .is_some_and(|c| c == '>' || c.is_whitespace())
+    })?;
+    let body_start = index + text[index..].find('>')? + 1;
+    let body_end = body_start + text[body_start..].find(&format!("</{}>", name))?;
+    Some(&text[body_start..body_end])
+}
+
+/// The `rdf:li` entries of an `rdf:Alt`, `rdf:Bag` or `rdf:Seq` property.
+fn list_items(text: &str, name: &str) -> Vec<String> {
+    let Some(body) = element_body(text, name) else {
+        return Vec::new();
+    };
+
+    let mut items = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find("<rdf:li") {
+        rest = &rest[start..];
+        let Some(content_start) = rest.find('>').map(|index| index + 1) else {
+            break;
+        };
+        let Some(content_end) = rest.find("</rdf:li>") else {
+            break;
+        };
+        if content_start <= content_end {
+            items.push(unescape(rest[content_start..content_end].trim()));
+        }
+        rest = &rest[content_end + "</rdf:li>".len()..];
+    }
+    items
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// XMP dates are ISO 8601 and may stop at the minute or the day, with
+/// optional fractions of a second and a time zone.
+fn parse_xmp_date(value: &str) -> Option<CaptureTime> {
+    let value = value.trim();
+    let (date_time, zone) = match value
+        .get(11..)
+        .and_then(|time| time.find(['Z', 'z', '+', '-']))
+    {
+        Some(zone_start) => value.split_at(11 + zone_start),
+        None => (value, ""),
+    };
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date_time, format).ok())
+        .or_else(|| {
+            chrono::NaiveDate::parse_from_str(date_time.get(..10)?, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+        .map(|date_time| CaptureTime::new(date_time, parse_utc_offset(zone)))
+}
+
+/// XMP GPS values look like `48,51.5N` or `48,51,30N`.
+fn parse_xmp_coordinate(value: &str) -> Option<f64> {
+    let hemisphere = value.chars().next_back()?;
+    let sign = match hemisphere.to_ascii_uppercase() {
+        'N' | 'E' => 1.0,
+        'S' | 'W' => -1.0,
+        _ => return None,
+    };
+    let parts: Vec<f64> = value[..value.len() - 1]
+        .split(',')
+        .map(|part| part.trim().parse().ok())
+        .collect::<Option<_>>()?;
+
+    let degrees = match parts.as_slice() {
+        [degrees, minutes] => degrees + minutes / 60.0,
+        [degrees, minutes, seconds] => degrees + minutes / 60.0 + seconds / 3600.0,
+        _ => return None,
+    };
+    Some(sign * degrees)
+}
+
+/// Writes `update` into the sidecar at `path`. A new sidecar gets the
+/// description and keywords. An existing one keeps its description and every
+/// other property, and the keywords are added to its `dc:subject` list.
+/// Returns whether the file is new.
+pub fn write_xmp_sidecar(path: &Path, update: &XmpUpdate) -> io::Result<bool> {
+    if !path.exists() {
+        let keywords: Vec<&str> = update.keywords.iter().map(String::as_str).collect();
+        let packet = format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             {}\
+             </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>\n",
+            xmp_description_block(Some(&update.description), &keywords)
+        );
+        fs::write(path, packet)?;
+        return Ok(true);
+    }
+
+    let text = fs::read_to_string(path)?;
+    let merged = merge_xmp(&text, update)?;
+    if merged != text {
+        fs::write(path, merged)?;
+    }
+    Ok(false)
+}
+
+/// Adds the keywords of `update` that are missing from an existing packet,
+/// and its description only when the packet has none in any form. A keyword
+/// list that is not a plain `rdf:Bag` element, such as a self-closing
+/// `<dc:subject/>`, is refused rather than rewritten.
+fn merge_xmp(text: &str, update: &XmpUpdate) -> io::Result<String> {
+    let existing = list_items(text, "dc:subject");
+    let mut keywords: Vec<&str> = Vec::new();
+    for keyword in &update.keywords {
+        let known = |other: &&str| other.eq_ignore_ascii_case(keyword);
+        if !existing
+            .iter()
+            .map(String::as_str)
+            .any(|other| known(&other))
+            && !keywords.iter().any(known)
+        {
+            keywords.push(keyword);
+        }
+    }
+
+    let mut text = text.to_string();
+    let has_subject = text.contains("dc:subject");
+    if has_subject && !keywords.is_empty() {
+        let bag_end = element_span(&text, "dc:subject")
+            .and_then(|(start, end)| Some(start + text[start..end].find("</rdf:Bag>")?))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "dc:subject is not an rdf:Bag element, sidecar left unchanged",
+                )
+            })?;
+        let items: String = keywords
+            .iter()
+            .map(|keyword| format!("<rdf:li>{}</rdf:li>", escape(keyword)))
+            .collect();
+        text.insert_str(bag_end, &items);
+    }
+
+    let description = (!text.contains("dc:description")).then_some(update.description.as_str());
+    let keywords = if has_subject { &[][..] } else { &keywords[..] };
+    if description.is_some() || !keywords.is_empty() {
+        let insert_at = text.rfind("</rdf:RDF>").ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "not an XMP packet (no rdf:RDF)")
+        })?;
+        text.insert_str(insert_at, &xmp_description_block(description, keywords));
+    }
+    Ok(text)
+}
+
+fn element_span(text: &str, name: &str) -> Option<(usize, usize)> {
+    let body = element_body(text, name)?;
+    let body_start = body.as_ptr() as usize - text.as_ptr() as usize;
+    let start = text[..body_start].rfind(&format!("<{}", name))?;
+    let end = body_start + body.len() + format!("</{}>", name).len();
+    Some((start, end))
+}
+
+fn xmp_description_block(description: Option<&str>, keywords: &[&str]) -> String {
+    let description = description
+        .map(|description| {
+            format!(
+                "   <dc:description>\n\
+                 \x20   <rdf:Alt>\n\
+                 \x20    <rdf:li xml:lang=\"x-default\">{}</rdf:li>\n\
+                 \x20   </rdf:Alt>\n\
+                 \x20  </dc:description>\n",
+                escape(description)
+            )
+        })
+        .unwrap_or_default();
+    let subject = if keywords.is_empty() {
+        String::new()
+    } else {
+        let items: String = keywords
+            .iter()
+            .map(|keyword| format!("     <rdf:li>{}</rdf:li>\n", escape(keyword)))
+            .collect();
+        format!(
+            "   <dc:subject>\n\
+             \x20   <rdf:Bag>\n\
+             {}\
+             \x20   </rdf:Bag>\n\
+             \x20  </dc:subject>\n",
+            items
+        )
+    };
+    format!(
+        "  <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+         {}{}\
+         \x20 </rdf:Description>\n",
+        description, subject
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::create_temp_dir;
+
+    const LIGHTROOM_XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    exif:GPSLatitude="48,51.504N"
+    exif:GPSLongitude="2,17.67E"
+    xmp:Rating="4">
+   <exif:DateTimeOriginal>2021-07-04T18:30:00.120+02:00</exif:DateTimeOriginal>
+   <dc:title>
+    <rdf:Alt><rdf:li xml:lang="x-default">Eiffel Tower &amp; friends</rdf:li></rdf:Alt>
+   </dc:title>
+   <dc:subject>
+    <rdf:Bag><rdf:li>paris</rdf:li><rdf:li>tower</rdf:li></rdf:Bag>
+   </dc:subject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+    #[test]
+    fn parses_attribute_and_element_properties() {
+        let metadata = parse_xmp(LIGHTROOM_XMP);
+
+        assert_eq!(
+            metadata.taken_at,
+            chrono::DateTime::parse_from_rfc3339("2021-07-04T18:30:00.120+02:00")
+                .ok()
+                .map(CaptureTime::Zoned)
+        );
+        let (lat, lon) = metadata.gps.unwrap();
+        assert!((lat - 48.8584).abs() < 1e-9);
+        assert!((lon - 2.2945).abs() < 1e-9);
+        assert_eq!(metadata.title.as_deref(), Some("Eiffel Tower & friends"));
+        assert_eq!(metadata.keywords, vec!["paris", "tower"]);
+    }
+
+    #[test]
+    fn writing_merges_keywords_and_keeps_the_description() {
+        let dir = create_temp_dir("xmp");
+        let path = dir.join("IMG_0001.xmp");
+        let described = LIGHTROOM_XMP.replace(
+            "xmp:Rating=\"4\"",
+            "xmp:Rating=\"4\"\n    dc:description=\"Written by hand\"",
+        );
+        fs::write(&path, &described).unwrap();
+        let update = XmpUpdate {
+            description: "A tower at dusk".to_string(),
+            keywords: vec!["dusk".to_string(), "Paris".to_string()],
+        };
+
+        assert!(!write_xmp_sidecar(&path, &update).unwrap());
+        let text = fs::read_to_string(&path).unwrap();
+        let metadata = parse_xmp(&text);
+
+        assert_eq!(metadata.keywords, vec!["paris", "tower", "dusk"]);
+        assert_eq!(metadata.title.as_deref(), Some("Eiffel Tower & friends"));
+        assert!(text.contains("xmp:Rating=\"4\""));
+        assert!(text.contains("Written by hand"));
+        assert!(!text.contains("A tower at dusk"));
+
+        let self_closing = LIGHTROOM_XMP.replace(
+            "<dc:subject>\n    <rdf:Bag><rdf:li>paris</rdf:li><rdf:li>tower</rdf:li></rdf:Bag>\n   </dc:subject>",
+            "<dc:subject/>",
+        );
+        fs::write(&path, &self_closing).unwrap();
+        assert!(write_xmp_sidecar(&path, &update).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), self_closing);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}